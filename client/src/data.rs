@@ -0,0 +1,359 @@
+//! 索引文件和版本元数据的解析
+//!
+//! 数据格式和管理端保持一致，详细的字段说明参考管理端的`core::data`模块。
+//! 这里只解析客户端更新时用得到的字段，其它字段会被忽略
+
+use std::path::Component;
+use std::path::Path;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use json::JsonValue;
+
+use crate::error::ClientError;
+
+/// 当前客户端支持的最高数据格式版本，遇到更新的格式时会报错，而不是错误地解析
+pub const FORMAT_VERSION: u32 = 1;
+
+/// 代表索引文件里的一个版本
+#[derive(Clone, Debug)]
+pub struct VersionIndex {
+    /// 版本号
+    pub label: String,
+
+    /// 版本的数据存在哪个更新包里
+    pub filename: String,
+
+    /// 元数据组在更新包里的偏移值
+    pub offset: u64,
+
+    /// 元数据组的长度
+    pub len: u64,
+
+    /// 元数据组的签名，没有启用签名时为None
+    pub signature: Option<String>,
+}
+
+/// 文件数据的压缩算法
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Compression {
+    /// 不压缩
+    None,
+
+    /// zstd算法
+    Zstd,
+
+    /// deflate算法
+    Deflate,
+}
+
+/// 代表单个文件操作
+#[derive(Clone, Debug)]
+pub enum FileChange {
+    /// 创建一个目录
+    CreateFolder {
+        /// 要创建目录的路径
+        path: String,
+    },
+
+    /// 新增新的文件或者更新现有文件
+    UpdateFile {
+        /// 要更新的文件路径
+        path: String,
+
+        /// 文件校验值
+        hash: String,
+
+        /// 文件长度
+        len: u64,
+
+        /// 文件的修改时间
+        modified: SystemTime,
+
+        /// unix文件权限，不知道权限时为None
+        mode: Option<u32>,
+
+        /// 文件数据在更新包中的偏移值
+        offset: u64,
+
+        /// 文件数据的压缩算法
+        compression: Compression,
+
+        /// 文件数据压缩后的长度，没有压缩时和`len`相等
+        compressed_len: u64,
+    },
+
+    /// 文件不存在时才创建，已经存在时保持原样，各字段的含义和`UpdateFile`一样
+    CreateIfMissing {
+        /// 要创建的文件路径
+        path: String,
+
+        /// 文件校验值
+        hash: String,
+
+        /// 文件长度
+        len: u64,
+
+        /// 文件的修改时间
+        modified: SystemTime,
+
+        /// unix文件权限，不知道权限时为None
+        mode: Option<u32>,
+
+        /// 文件数据在更新包中的偏移值
+        offset: u64,
+
+        /// 文件数据的压缩算法
+        compression: Compression,
+
+        /// 文件数据压缩后的长度，没有压缩时和`len`相等
+        compressed_len: u64,
+    },
+
+    /// 使用差异补丁更新现有文件
+    PatchFile {
+        /// 要更新的文件路径
+        path: String,
+
+        /// 打完补丁后的文件校验值
+        hash: String,
+
+        /// 打完补丁后的文件长度
+        len: u64,
+
+        /// 文件的修改时间
+        modified: SystemTime,
+
+        /// unix文件权限，不知道权限时为None
+        mode: Option<u32>,
+
+        /// 补丁数据在更新包中的偏移值
+        offset: u64,
+
+        /// 补丁数据的长度
+        patch_len: u64,
+    },
+
+    /// 创建一个符号链接
+    CreateSymlink {
+        /// 符号链接的路径
+        path: String,
+
+        /// 链接指向的路径
+        target: String,
+    },
+
+    /// 删除一个目录
+    DeleteFolder {
+        /// 要删除的目录的路径
+        path: String,
+    },
+
+    /// 删除一个文件
+    DeleteFile {
+        /// 要删除的文件的路径
+        path: String,
+    },
+
+    /// 移动一个文件
+    MoveFile {
+        /// 文件从哪里来
+        from: String,
+
+        /// 文件到哪里去
+        to: String,
+    },
+}
+
+impl FileChange {
+    /// 复现文件操作时所处的阶段，同一个版本里的文件操作需要按阶段从小到大依次执行：
+    /// 删除旧文件 -> 创建目录 -> 移动文件 -> 更新文件 -> 创建符号链接 -> 删除目录
+    pub fn phase(&self) -> u8 {
+        match self {
+            FileChange::DeleteFile { .. } => 0,
+            FileChange::CreateFolder { .. } => 1,
+            FileChange::MoveFile { .. } => 2,
+            FileChange::UpdateFile { .. } => 3,
+            FileChange::CreateIfMissing { .. } => 3,
+            FileChange::PatchFile { .. } => 3,
+            FileChange::CreateSymlink { .. } => 4,
+            FileChange::DeleteFolder { .. } => 5,
+        }
+    }
+}
+
+/// 代表一个版本的元数据
+#[derive(Clone, Debug)]
+pub struct VersionMeta {
+    /// 版本号
+    pub label: String,
+
+    /// 这个版本的更新日志
+    pub logs: String,
+
+    /// 文件变动列表
+    pub changes: Vec<FileChange>,
+}
+
+/// 解析索引文件
+pub fn parse_index_file(content: &str) -> Result<Vec<VersionIndex>, ClientError> {
+    let root = parse_json(content)?;
+
+    if !root.is_array() {
+        return Err(ClientError::Parse("索引文件必须是一个列表".to_owned()));
+    }
+
+    let mut versions = Vec::new();
+
+    for v in root.members() {
+        check_format_version(v)?;
+
+        let label = required_str(v, "label", "索引文件")?;
+
+        versions.push(VersionIndex {
+            filename: required_str(v, "filename", &label)?,
+            offset: required_u64(v, "offset", &label)?,
+            len: required_u64(v, "length", &label)?,
+            signature: v["signature"].as_str().map(|e| e.to_owned()),
+            label,
+        });
+    }
+
+    Ok(versions)
+}
+
+/// 解析一个元数据组，也就是更新包里的`metadata.txt`
+pub fn parse_metadata_group(content: &str) -> Result<Vec<VersionMeta>, ClientError> {
+    let root = parse_json(content)?;
+
+    if !root.is_array() {
+        return Err(ClientError::Parse("元数据组必须是一个列表".to_owned()));
+    }
+
+    root.members().map(parse_meta).collect()
+}
+
+fn parse_meta(obj: &JsonValue) -> Result<VersionMeta, ClientError> {
+    check_format_version(obj)?;
+
+    let label = required_str(obj, "label", "版本元数据")?;
+
+    if !obj["changes"].is_array() {
+        return Err(missing_field("changes", &label));
+    }
+
+    Ok(VersionMeta {
+        logs: required_str(obj, "logs", &label)?,
+        changes: obj["changes"].members().map(parse_change).collect::<Result<_, _>>()?,
+        label,
+    })
+}
+
+fn parse_change(v: &JsonValue) -> Result<FileChange, ClientError> {
+    let operation = required_str(v, "operation", "文件操作")?;
+
+    // 报错时用来定位是哪个文件的操作
+    let context = v["path"].as_str().or(v["from"].as_str()).unwrap_or(&operation).to_owned();
+    let string = |field: &'static str| required_str(v, field, &context);
+    let path = |field: &'static str| string(field).and_then(check_path);
+    let number = |field: &'static str| required_u64(v, field, &context);
+    let modified = || number("modified").map(|e| UNIX_EPOCH + Duration::from_secs(e));
+
+    let change = match operation.as_str() {
+        "create-directory" => FileChange::CreateFolder { path: path("path")? },
+        "update-file" | "create-if-missing" => {
+            let len = number("len")?;
+
+            let compression = match v["compression"].as_str() {
+                None | Some("none") => Compression::None,
+                Some("zstd") => Compression::Zstd,
+                Some("deflate") => Compression::Deflate,
+                Some(name) => return Err(ClientError::Parse(format!("无法识别的压缩算法: {}", name))),
+            };
+
+            let (path, hash, modified, mode, offset) = (path("path")?, string("hash")?, modified()?, v["mode"].as_u32(), number("offset")?);
+            let compressed_len = v["compressed_len"].as_u64().unwrap_or(len);
+
+            match operation.as_str() {
+                "create-if-missing" => FileChange::CreateIfMissing { path, hash, len, modified, mode, offset, compression, compressed_len },
+                _ => FileChange::UpdateFile { path, hash, len, modified, mode, offset, compression, compressed_len },
+            }
+        },
+        "patch-file" => FileChange::PatchFile {
+            path: path("path")?,
+            hash: string("hash")?,
+            len: number("len")?,
+            modified: modified()?,
+            mode: v["mode"].as_u32(),
+            offset: number("offset")?,
+            patch_len: number("patch_len")?,
+        },
+        "create-symlink" => {
+            let (path, target) = (path("path")?, string("target")?);
+
+            check_symlink_target(&path, &target)?;
+
+            FileChange::CreateSymlink { path, target }
+        },
+        "delete-directory" => FileChange::DeleteFolder { path: path("path")? },
+        "delete-file" => FileChange::DeleteFile { path: path("path")? },
+        "move-file" => FileChange::MoveFile { from: path("from")?, to: path("to")? },
+        _ => return Err(ClientError::Parse(format!("无法识别的文件操作: {}，请升级客户端", operation))),
+    };
+
+    Ok(change)
+}
+
+/// 检查元数据里的文件路径，只允许指向目标目录里面的相对路径，防止被篡改过的元数据修改目标目录外面的文件
+fn check_path(path: String) -> Result<String, ClientError> {
+    let normal = Path::new(&path).components().all(|e| matches!(e, Component::Normal(_)));
+
+    match normal && !path.is_empty() {
+        true => Ok(path),
+        false => Err(ClientError::UnsafePath(path)),
+    }
+}
+
+/// 检查符号链接指向的路径，只允许指向目标目录里面的相对路径
+fn check_symlink_target(path: &str, target: &str) -> Result<(), ClientError> {
+    // 符号链接所在的目录的深度
+    let mut depth = path.split('/').count() - 1;
+
+    for component in Path::new(target).components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => (),
+            Component::ParentDir if depth > 0 => depth -= 1,
+            _ => return Err(ClientError::UnsafePath(format!("{} -> {}", path, target))),
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_json(content: &str) -> Result<JsonValue, ClientError> {
+    json::parse(content).map_err(|e| ClientError::Parse(format!("不是合法的json: {}", e)))
+}
+
+fn required_str(obj: &JsonValue, field: &'static str, context: &str) -> Result<String, ClientError> {
+    obj[field].as_str().map(|e| e.to_owned()).ok_or_else(|| missing_field(field, context))
+}
+
+fn required_u64(obj: &JsonValue, field: &'static str, context: &str) -> Result<u64, ClientError> {
+    obj[field].as_u64().ok_or_else(|| missing_field(field, context))
+}
+
+fn missing_field(field: &str, context: &str) -> ClientError {
+    ClientError::Parse(format!("缺少字段或者字段类型不正确: {}（位于 {}）", field, context))
+}
+
+/// 旧版本的数据没有`format_version`字段，视为版本0
+fn check_format_version(obj: &JsonValue) -> Result<(), ClientError> {
+    let version = obj["format_version"].as_u32().unwrap_or(0);
+
+    match version > FORMAT_VERSION {
+        true => Err(ClientError::Parse(format!("不支持的数据格式版本: {}（最高支持 {}），请升级客户端", version, FORMAT_VERSION))),
+        false => Ok(()),
+    }
+}
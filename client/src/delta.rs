@@ -0,0 +1,71 @@
+//! 二进制差异补丁的应用
+//!
+//! 补丁的格式和管理端的`core::delta`模块一致（所有整数都是小端序u64）：
+//!
+//! 1. 魔数`MCPD`，4个字节
+//! 2. 新文件的长度
+//! 3. 若干条指令，每条指令以1个字节的类型开头
+//!    + `0x01`：从旧文件复制，后面跟着旧文件中的偏移值和长度
+//!    + `0x02`：插入新数据，后面跟着数据长度和数据本身
+
+/// 补丁文件的魔数
+const MAGIC: &[u8; 4] = b"MCPD";
+
+/// 指令：从旧文件复制
+const OP_COPY: u8 = 0x01;
+
+/// 指令：插入新数据
+const OP_INSERT: u8 = 0x02;
+
+/// 将差异补丁`delta`应用到`old`上，还原出新文件的数据
+pub fn apply_delta(old: &[u8], delta: &[u8]) -> Result<Vec<u8>, String> {
+    if delta.len() < 12 || &delta[0..4] != MAGIC {
+        return Err("not a valid delta patch".to_owned());
+    }
+
+    let new_len = read_u64(delta, 4)? as usize;
+    let mut output = Vec::<u8>::with_capacity(new_len);
+    let mut pos = 12usize;
+
+    while pos < delta.len() {
+        let op = delta[pos];
+        pos += 1;
+
+        match op {
+            OP_COPY => {
+                let offset = read_u64(delta, pos)? as usize;
+                let len = read_u64(delta, pos + 8)? as usize;
+                pos += 16;
+
+                let end = offset.checked_add(len).filter(|e| *e <= old.len())
+                    .ok_or_else(|| format!("copy out of range: {}+{} (old length: {})", offset, len, old.len()))?;
+
+                output.extend_from_slice(&old[offset..end]);
+            },
+            OP_INSERT => {
+                let len = read_u64(delta, pos)? as usize;
+                pos += 8;
+
+                let end = pos.checked_add(len).filter(|e| *e <= delta.len())
+                    .ok_or_else(|| format!("insert out of range: {}+{}", pos, len))?;
+
+                output.extend_from_slice(&delta[pos..end]);
+                pos = end;
+            },
+            _ => return Err(format!("unknown delta instruction: 0x{:02x}", op)),
+        }
+    }
+
+    if output.len() != new_len {
+        return Err(format!("length mismatch after patching, expected: {}, actual: {}", new_len, output.len()));
+    }
+
+    Ok(output)
+}
+
+fn read_u64(buf: &[u8], pos: usize) -> Result<u64, String> {
+    match buf.get(pos..pos + 8) {
+        Some(bytes) => Ok(u64::from_le_bytes(bytes.try_into().unwrap())),
+        None => Err("unexpected end of delta patch".to_owned()),
+    }
+}
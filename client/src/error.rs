@@ -0,0 +1,81 @@
+//! 错误处理
+
+use std::fmt::Display;
+use std::path::Path;
+
+/// 代表更新过程中遇到的各种错误
+#[derive(Debug)]
+pub enum ClientError {
+    /// 本地文件读写失败
+    Io {
+        /// 出错的文件路径或者正在进行的操作
+        context: String,
+
+        /// 原始错误
+        error: std::io::Error,
+    },
+
+    /// 网络连接或者传输失败
+    Network(String),
+
+    /// 服务端上找不到要下载的文件
+    NotFound(String),
+
+    /// 索引文件或者元数据解析失败
+    Parse(String),
+
+    /// 本地记录的版本号不在服务端的版本列表里
+    UnknownLocalVersion(String),
+
+    /// 下载到的数据已经损坏，比如解压失败，校验值不匹配等
+    Corrupted(String),
+
+    /// 索引文件或者元数据的签名验证失败，数据可能被篡改过
+    BadSignature(String),
+
+    /// 元数据里的路径会修改目标目录外面的文件，数据可能被篡改过
+    UnsafePath(String),
+}
+
+impl Display for ClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientError::Io { context, error } => write!(f, "文件读写失败: {}，{}", context, error),
+            ClientError::Network(reason) => write!(f, "网络错误: {}", reason),
+            ClientError::NotFound(path) => write!(f, "服务端上找不到文件: {}", path),
+            ClientError::Parse(reason) => write!(f, "数据解析失败: {}", reason),
+            ClientError::UnknownLocalVersion(label) => write!(f, "服务端的版本列表里没有本地的版本: {}", label),
+            ClientError::Corrupted(reason) => write!(f, "更新数据已损坏: {}", reason),
+            ClientError::BadSignature(reason) => write!(f, "签名验证失败: {}", reason),
+            ClientError::UnsafePath(reason) => write!(f, "不安全的路径: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClientError::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// 给io错误附加上文件路径等上下文信息，转换成[`ClientError`]
+pub trait IoContext<T> {
+    /// 附加一段上下文信息
+    fn context(self, context: impl Display) -> Result<T, ClientError>;
+
+    /// 附加一个文件路径作为上下文信息
+    fn with_path(self, path: impl AsRef<Path>) -> Result<T, ClientError>;
+}
+
+impl<T> IoContext<T> for std::io::Result<T> {
+    fn context(self, context: impl Display) -> Result<T, ClientError> {
+        self.map_err(|error| ClientError::Io { context: context.to_string(), error })
+    }
+
+    fn with_path(self, path: impl AsRef<Path>) -> Result<T, ClientError> {
+        self.context(path.as_ref().display())
+    }
+}
@@ -0,0 +1,49 @@
+//! 文件校验值的计算
+//!
+//! 和管理端一样，根据校验值的前缀识别算法：`sha256:`和`blake3:`开头的分别是对应的算法，
+//! 没有前缀的是最早期的crc64和crc16的组合算法
+
+use std::io::Read;
+
+use crc::Crc;
+use crc::CRC_16_IBM_SDLC;
+use crc::CRC_64_XZ;
+use sha2::Digest;
+use sha2::Sha256;
+
+static CRC64: Crc<u64> = Crc::<u64>::new(&CRC_64_XZ);
+static CRC16: Crc<u16> = Crc::<u16>::new(&CRC_16_IBM_SDLC);
+
+/// 使用和`expected`相同的算法计算数据的校验值，返回的格式和`expected`一致，可以直接比较
+pub fn calculate_hash_like(read: &mut impl Read, expected: &str) -> std::io::Result<String> {
+    if expected.starts_with("sha256:") {
+        let mut hasher = Sha256::new();
+
+        std::io::copy(read, &mut hasher)?;
+
+        Ok(format!("sha256:{}", base16ct::lower::encode_string(&hasher.finalize())))
+    } else if expected.starts_with("blake3:") {
+        let mut hasher = blake3::Hasher::new();
+
+        std::io::copy(read, &mut hasher)?;
+
+        Ok(format!("blake3:{}", hasher.finalize().to_hex()))
+    } else {
+        let mut crc64 = CRC64.digest();
+        let mut crc16 = CRC16.digest();
+        let mut buffer = [0u8; 16 * 1024];
+
+        loop {
+            let count = read.read(&mut buffer)?;
+
+            if count == 0 {
+                break;
+            }
+
+            crc64.update(&buffer[0..count]);
+            crc16.update(&buffer[0..count]);
+        }
+
+        Ok(format!("{:016x}_{:04x}", crc64.finalize(), crc16.finalize()))
+    }
+}
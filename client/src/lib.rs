@@ -0,0 +1,190 @@
+//! 更新客户端的参考实现
+//!
+//! 这个库演示了客户端应该如何使用管理端生成的文件进行更新，也被用来对管理端做端到端的测试。更新流程如下：
+//!
+//! 1. 下载索引文件，拿到版本列表。如果服务端启用了签名，还会验证索引文件和元数据的签名，参考[`signing`]
+//! 2. 读取目标目录里的版本号文件（[`VERSION_FILE`]），确定本地的版本。没有这个文件时视为全新安装，从第一个版本开始更新
+//! 3. 下载所有缺少的版本的元数据，推演出最终的文件状态，再下载最终状态需要的数据（只下载需要的范围，不会下载整个更新包）
+//! 4. 以事务的方式把文件操作应用到目标目录上，参考[`transaction`]
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), client::error::ClientError> {
+//! use client::source::Source;
+//! use client::UpdateClient;
+//!
+//! let source = Source::from_index_url("http://127.0.0.1:6710/public/index.json");
+//! let mut client = UpdateClient::new(source, ".minecraft");
+//!
+//! let updated = client.update().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::data::parse_index_file;
+use crate::data::parse_metadata_group;
+use crate::data::VersionIndex;
+use crate::data::VersionMeta;
+use crate::error::ClientError;
+use crate::error::IoContext;
+use crate::signing::verify_signature;
+use crate::signing::PUBLIC_KEY_FILE;
+use crate::source::Downloader;
+use crate::source::Source;
+use crate::transaction::Transaction;
+
+pub mod data;
+pub mod delta;
+pub mod error;
+pub mod hash;
+pub mod signing;
+pub mod source;
+pub mod transaction;
+
+/// 记录本地版本号的文件，位于目标目录下
+pub const VERSION_FILE: &str = ".mcpatch-version";
+
+/// 代表一个更新客户端
+pub struct UpdateClient {
+    downloader: Downloader,
+    target_dir: PathBuf,
+
+    /// 内置的公钥
+    public_key: Option<String>,
+}
+
+impl UpdateClient {
+    /// 创建一个更新客户端，从`source`下载数据，更新`target_dir`目录
+    pub fn new(source: Source, target_dir: impl Into<PathBuf>) -> Self {
+        Self { downloader: Downloader::new(source), target_dir: target_dir.into(), public_key: None }
+    }
+
+    /// 使用内置的公钥验证签名，此时服务端上的数据必须带有签名
+    ///
+    /// 没有内置公钥时，服务端上有`signing.pub`文件才会验证签名。
+    /// 能篡改更新包的人也能删掉这个文件，所以正式发布的客户端最好内置公钥
+    pub fn with_public_key(mut self, public_key: impl Into<String>) -> Self {
+        self.public_key = Some(public_key.into());
+        self
+    }
+
+    /// 读取本地的版本号，还没有安装过任何版本时返回None
+    pub fn local_version(&self) -> Result<Option<String>, ClientError> {
+        let file = self.target_dir.join(VERSION_FILE);
+
+        match std::fs::read_to_string(&file) {
+            Ok(label) => Ok(Some(label.trim().to_owned())),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err).with_path(&file),
+        }
+    }
+
+    /// 下载服务端的版本列表，启用了签名时会验证索引文件的签名
+    pub async fn fetch_index(&mut self) -> Result<Vec<VersionIndex>, ClientError> {
+        let public_key = self.fetch_public_key().await?;
+
+        self.fetch_index_with(public_key.as_deref()).await
+    }
+
+    /// 获取用来验证签名的公钥，服务端没有启用签名时返回None
+    async fn fetch_public_key(&mut self) -> Result<Option<String>, ClientError> {
+        if let Some(public_key) = &self.public_key {
+            return Ok(Some(public_key.to_owned()));
+        }
+
+        match self.downloader.fetch(PUBLIC_KEY_FILE, None).await {
+            Ok(content) => Ok(Some(String::from_utf8_lossy(&content).trim().to_owned())),
+            Err(ClientError::NotFound(_)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn fetch_index_with(&mut self, public_key: Option<&str>) -> Result<Vec<VersionIndex>, ClientError> {
+        let index_file = self.downloader.source().index_file().to_owned();
+        let content = self.downloader.fetch(&index_file, None).await?;
+
+        if let Some(public_key) = public_key {
+            let signature_file = format!("{}.sig", index_file);
+
+            let signature = match self.downloader.fetch(&signature_file, None).await {
+                Ok(signature) => signature,
+                Err(ClientError::NotFound(_)) => return Err(ClientError::BadSignature(format!("{}: 缺少签名文件", index_file))),
+                Err(err) => return Err(err),
+            };
+
+            verify_signature(public_key, &content, &String::from_utf8_lossy(&signature), &index_file)?;
+        }
+
+        parse_index_file(&String::from_utf8_lossy(&content))
+    }
+
+    /// 把目标目录更新到最新版本，返回这次更新经过的所有版本号，已经是最新版本时返回空列表
+    ///
+    /// 更新失败时目标目录会保持更新前的状态
+    pub async fn update(&mut self) -> Result<Vec<String>, ClientError> {
+        let public_key = self.fetch_public_key().await?;
+        let index = self.fetch_index_with(public_key.as_deref()).await?;
+
+        let start = match self.local_version()? {
+            Some(label) => match index.iter().position(|e| e.label == label) {
+                Some(pos) => pos + 1,
+                None => return Err(ClientError::UnknownLocalVersion(label)),
+            },
+            None => 0,
+        };
+
+        let pending = &index[start..];
+
+        if pending.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let versions = self.fetch_metas(pending, public_key.as_deref()).await?;
+
+        std::fs::create_dir_all(&self.target_dir).with_path(&self.target_dir)?;
+
+        let mut transaction = Transaction::new(&self.target_dir)?;
+
+        if let Err(err) = transaction.prepare(&mut self.downloader, &versions).await {
+            transaction.abort();
+            return Err(err);
+        }
+
+        transaction.commit(&pending[pending.len() - 1].label)?;
+
+        Ok(pending.iter().map(|e| e.label.to_owned()).collect())
+    }
+
+    /// 下载这些版本的元数据，合并包里的多个版本共用一个元数据组，只需要下载一次。启用了签名时会验证元数据的签名
+    async fn fetch_metas(&mut self, versions: &[VersionIndex], public_key: Option<&str>) -> Result<Vec<(VersionIndex, VersionMeta)>, ClientError> {
+        let mut groups = HashMap::<(String, u64, u64), Vec<VersionMeta>>::new();
+        let mut result = Vec::new();
+
+        for index in versions {
+            let key = (index.filename.to_owned(), index.offset, index.len);
+
+            if !groups.contains_key(&key) {
+                let data = self.downloader.fetch(&index.filename, Some(index.offset..index.offset + index.len)).await?;
+
+                // 先验证签名再解析。索引文件本身也验证过签名，所以同一个元数据组只需要验证一次
+                if let Some(public_key) = public_key {
+                    let signature = index.signature.as_deref()
+                        .ok_or_else(|| ClientError::BadSignature(format!("版本 {} 没有签名", index.label)))?;
+
+                    verify_signature(public_key, &data, signature, &format!("版本 {} 的元数据", index.label))?;
+                }
+
+                groups.insert(key.clone(), parse_metadata_group(&String::from_utf8_lossy(&data))?);
+            }
+
+            let meta = groups[&key].iter().find(|e| e.label == index.label)
+                .ok_or_else(|| ClientError::Corrupted(format!("更新包 {} 里找不到版本 {} 的元数据", index.filename, index.label)))?;
+
+            result.push((index.clone(), meta.clone()));
+        }
+
+        Ok(result)
+    }
+}
@@ -0,0 +1,36 @@
+//! 索引文件和元数据的签名验证
+//!
+//! 签名的格式和管理端保持一致，详细说明参考管理端的`core::signing`模块：
+//!
+//! 1. 公钥以`signing.pub`文件的形式发布在索引文件所在的目录下，客户端也可以内置一个公钥
+//! 2. 索引文件的签名存在旁边的`.sig`文件里，比如`index.json.sig`
+//! 3. 索引文件里每个版本的`signature`字段，是对这个版本所在的元数据组原始内容的签名
+//!
+//! 密钥和签名都使用base64编码
+
+use base64ct::Base64;
+use base64ct::Encoding;
+use ed25519_dalek::Signature;
+use ed25519_dalek::VerifyingKey;
+
+use crate::error::ClientError;
+
+/// 公钥文件的文件名，位于索引文件所在的目录下
+pub const PUBLIC_KEY_FILE: &str = "signing.pub";
+
+/// 使用base64编码的公钥`public_key`验证`data`的签名，`context`用来说明验证的是什么数据
+pub fn verify_signature(public_key: &str, data: &[u8], signature: &str, context: &str) -> Result<(), ClientError> {
+    let bad = |reason: String| ClientError::BadSignature(format!("{}: {}", context, reason));
+
+    let public_key: [u8; 32] = Base64::decode_vec(public_key.trim()).ok()
+        .and_then(|e| e.try_into().ok())
+        .ok_or_else(|| bad("公钥格式不正确".to_owned()))?;
+
+    let signature: [u8; 64] = Base64::decode_vec(signature.trim()).ok()
+        .and_then(|e| e.try_into().ok())
+        .ok_or_else(|| bad("签名格式不正确".to_owned()))?;
+
+    let key = VerifyingKey::from_bytes(&public_key).map_err(|e| bad(format!("公钥不正确: {}", e)))?;
+
+    key.verify_strict(data, &Signature::from_bytes(&signature)).map_err(|_| bad("签名不匹配".to_owned()))
+}
@@ -0,0 +1,202 @@
+//! 更新源，也就是从哪里下载索引文件和更新包
+//!
+//! 支持两种下载方式：
+//!
+//! 1. http：从webui的`/public`路径，或者任何托管了public目录的http服务器上下载，使用Range请求读取部分数据
+//! 2. 私有协议：从管理端的内置服务端下载，一个连接可以连续发送多个请求
+//!
+//! 私有协议的请求格式为：路径长度（u64）+ 路径 + 起始位置（u64）+ 结束位置（u64，不包含），
+//! 起始位置和结束位置都为0时表示整个文件。服务端先回复数据长度（i64），然后是数据本身，
+//! 长度为-1时表示文件不存在，为-2时表示请求的范围不对。所有整数都是小端序
+
+use std::io::Write;
+use std::ops::Range;
+
+use reqwest::StatusCode;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+
+use crate::error::ClientError;
+use crate::error::IoContext;
+
+/// 代表一个更新源
+#[derive(Clone, Debug)]
+pub enum Source {
+    /// 使用http下载
+    Http {
+        /// public目录对应的url，以`/`结尾
+        base_url: String,
+
+        /// 要使用的索引文件的文件名
+        index_file: String,
+    },
+
+    /// 使用私有协议从内置服务端下载
+    Builtin {
+        /// 内置服务端的地址，比如`127.0.0.1:6700`
+        addr: String,
+
+        /// 要使用的索引文件的文件名
+        index_file: String,
+    },
+}
+
+impl Source {
+    /// 使用http下载，`index_url`是索引文件的完整url，比如`http://127.0.0.1:6710/public/index.json`
+    ///
+    /// 更新包会从索引文件所在的目录下载
+    pub fn from_index_url(index_url: &str) -> Self {
+        let split = index_url.rfind('/').map(|e| e + 1).unwrap_or(0);
+
+        Source::Http {
+            base_url: index_url[..split].to_owned(),
+            index_file: index_url[split..].to_owned(),
+        }
+    }
+
+    /// 使用私有协议从内置服务端下载`channel`频道的版本，稳定频道是`stable`
+    pub fn builtin(addr: impl Into<String>, channel: &str) -> Self {
+        let index_file = match channel {
+            "stable" => "index.json".to_owned(),
+            _ => format!("index.{}.json", channel),
+        };
+
+        Source::Builtin { addr: addr.into(), index_file }
+    }
+
+    /// 要使用的索引文件的文件名
+    pub fn index_file(&self) -> &str {
+        match self {
+            Source::Http { index_file, .. } => index_file,
+            Source::Builtin { index_file, .. } => index_file,
+        }
+    }
+}
+
+/// 负责从更新源下载数据，私有协议的连接会被重复使用
+pub(crate) struct Downloader {
+    source: Source,
+    http: reqwest::Client,
+    connection: Option<TcpStream>,
+}
+
+impl Downloader {
+    pub fn new(source: Source) -> Self {
+        Self { source, http: reqwest::Client::new(), connection: None }
+    }
+
+    pub fn source(&self) -> &Source {
+        &self.source
+    }
+
+    /// 下载`path`文件里`range`范围内的数据，`range`为None时下载整个文件
+    pub async fn fetch(&mut self, path: &str, range: Option<Range<u64>>) -> Result<Vec<u8>, ClientError> {
+        let mut buf = Vec::new();
+
+        self.fetch_into(path, range, &mut buf).await?;
+
+        Ok(buf)
+    }
+
+    /// 下载`path`文件里`range`范围内的数据，并写到`out`里，`range`为None时下载整个文件
+    pub async fn fetch_into(&mut self, path: &str, range: Option<Range<u64>>, out: &mut impl Write) -> Result<(), ClientError> {
+        // 空范围不需要发出请求，私有协议里0..0还代表整个文件
+        if range.as_ref().is_some_and(|e| e.is_empty()) {
+            return Ok(());
+        }
+
+        match self.source.clone() {
+            Source::Http { base_url, .. } => self.fetch_http(&format!("{}{}", base_url, path), range, out).await,
+            Source::Builtin { addr, .. } => {
+                let result = self.fetch_builtin(&addr, path, range, out).await;
+
+                // 出错以后连接的状态就不确定了，下次重新连接
+                if result.is_err() {
+                    self.connection = None;
+                }
+
+                result
+            },
+        }
+    }
+
+    async fn fetch_http(&mut self, url: &str, range: Option<Range<u64>>, out: &mut impl Write) -> Result<(), ClientError> {
+        let network = |e: reqwest::Error| ClientError::Network(format!("{}: {}", url, e));
+
+        let mut request = self.http.get(url);
+
+        if let Some(range) = &range {
+            request = request.header("Range", format!("bytes={}-{}", range.start, range.end - 1));
+        }
+
+        let mut response = request.send().await.map_err(network)?;
+
+        match response.status() {
+            StatusCode::NOT_FOUND => return Err(ClientError::NotFound(url.to_owned())),
+            StatusCode::PARTIAL_CONTENT if range.is_some() => (),
+            StatusCode::OK if range.is_none() => (),
+            status => return Err(ClientError::Network(format!("{}: 服务端返回了意外的状态码 {}", url, status))),
+        }
+
+        let mut received = 0u64;
+
+        while let Some(chunk) = response.chunk().await.map_err(network)? {
+            out.write_all(&chunk).context(url)?;
+            received += chunk.len() as u64;
+        }
+
+        if let Some(range) = range {
+            if received != range.end - range.start {
+                return Err(ClientError::Network(format!("{}: 数据不完整，预期 {} 字节，实际 {} 字节", url, range.end - range.start, received)));
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn fetch_builtin(&mut self, addr: &str, path: &str, range: Option<Range<u64>>, out: &mut impl Write) -> Result<(), ClientError> {
+        let network = |e: std::io::Error| ClientError::Network(format!("{}/{}: {}", addr, path, e));
+
+        if self.connection.is_none() {
+            self.connection = Some(TcpStream::connect(addr).await.map_err(network)?);
+        }
+
+        let stream = self.connection.as_mut().unwrap();
+        let range = range.unwrap_or(0..0);
+
+        // 发送请求
+        let mut request = Vec::with_capacity(path.len() + 24);
+        request.extend_from_slice(&(path.len() as u64).to_le_bytes());
+        request.extend_from_slice(path.as_bytes());
+        request.extend_from_slice(&range.start.to_le_bytes());
+        request.extend_from_slice(&range.end.to_le_bytes());
+
+        stream.write_all(&request).await.map_err(network)?;
+
+        // 接收数据
+        let len = match stream.read_i64_le().await.map_err(network)? {
+            -1 => return Err(ClientError::NotFound(format!("{}/{}", addr, path))),
+            -2 => return Err(ClientError::Network(format!("{}/{}: 请求的范围超出了文件大小: {}..{}", addr, path, range.start, range.end))),
+            len if len < 0 => return Err(ClientError::Network(format!("{}/{}: 无法识别的回复: {}", addr, path, len))),
+            len => len as u64,
+        };
+
+        let mut remains = len;
+        let mut buf = vec![0u8; 32 * 1024];
+
+        while remains > 0 {
+            let limit = buf.len().min(remains as usize);
+            let read = stream.read(&mut buf[0..limit]).await.map_err(network)?;
+
+            if read == 0 {
+                return Err(ClientError::Network(format!("{}/{}: 连接被提前关闭", addr, path)));
+            }
+
+            out.write_all(&buf[0..read]).context(path)?;
+            remains -= read as u64;
+        }
+
+        Ok(())
+    }
+}
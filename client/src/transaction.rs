@@ -0,0 +1,560 @@
+//! 以事务的方式把文件操作应用到目标目录上
+//!
+//! 更新分成两个阶段进行：
+//!
+//! 1. 准备阶段：先按顺序推演所有要更新的版本里的文件操作，得到每个路径最终的状态，
+//!    再下载最终状态需要的数据，解压或者打补丁后写到暂存目录里，并逐个检查校验值。
+//!    中间版本里被覆盖、移走或者删除的文件不会被下载，合并包里也本来就不存储这些数据。
+//!    这个阶段不会修改目标目录里的任何文件，数据有问题时直接放弃即可
+//! 2. 提交阶段：把最终状态应用到目标目录上，被覆盖或者删除的文件会先挪到暂存目录里备份起来，
+//!    每一步都会记录下撤销的方法。中途出错时按相反的顺序逐个撤销，让目标目录回到更新前的状态
+//!
+//! 暂存目录位于目标目录里，这样挪动文件时只需要改名，不需要复制。
+//! 注意这里的事务只能应对更新过程中发生的错误，无法应对进程被强制结束或者断电
+
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use crate::data::Compression;
+use crate::data::FileChange;
+use crate::data::VersionIndex;
+use crate::data::VersionMeta;
+use crate::delta::apply_delta;
+use crate::error::ClientError;
+use crate::error::IoContext;
+use crate::hash::calculate_hash_like;
+use crate::source::Downloader;
+use crate::VERSION_FILE;
+
+/// 暂存目录的名字，位于目标目录下，更新结束后会被删除
+pub const STAGING_DIR: &str = ".mcpatch-staging";
+
+/// 准备阶段推演出来的某个路径上的文件内容
+#[derive(Clone)]
+enum Content {
+    /// 目标目录里原本就有的文件，记录的是这个文件原本的相对路径
+    Original(String),
+
+    /// 更新包里的完整文件数据，`change`是对应的更新文件或者按需创建操作
+    Remote {
+        filename: String,
+        change: FileChange,
+    },
+
+    /// 在`base`上打补丁得到的文件，`change`是对应的补丁文件操作
+    Patched {
+        base: Box<Content>,
+        filename: String,
+        change: FileChange,
+    },
+
+    /// 一个符号链接，记录的是链接指向的路径
+    Symlink(String),
+
+    /// 文件已经被删除或者移走了
+    Deleted,
+}
+
+/// 提交阶段要对某个路径做的事情
+enum Action {
+    /// 删除这个路径上原本的文件
+    Delete,
+
+    /// 把原本位于另一个路径上的文件移动过来
+    Move(String),
+
+    /// 放入暂存目录里准备好的文件
+    Place {
+        staged: PathBuf,
+        modified: SystemTime,
+        mode: Option<u32>,
+    },
+
+    /// 创建一个符号链接
+    Symlink(String),
+}
+
+/// 提交阶段每一步操作的撤销方法
+enum Undo {
+    /// 删除新创建的目录
+    RemoveDir(PathBuf),
+
+    /// 删除新写入的文件或者符号链接
+    RemoveFile(PathBuf),
+
+    /// 把备份起来的文件放回原处
+    Restore {
+        backup: PathBuf,
+        original: PathBuf,
+    },
+
+    /// 把移动过的文件移回去
+    MoveBack {
+        from: PathBuf,
+        to: PathBuf,
+    },
+
+    /// 重新创建被删除的空目录
+    CreateDir(PathBuf),
+}
+
+/// 代表一次更新事务
+pub struct Transaction {
+    target_dir: PathBuf,
+    staging_dir: PathBuf,
+
+    /// 每个路径最终要做的事情，按路径排序
+    actions: Vec<(String, Action)>,
+
+    /// 目录最终是否存在，key是目录的路径
+    folders: BTreeMap<String, bool>,
+
+    /// 提交阶段的撤销记录
+    journal: Vec<Undo>,
+
+    /// 用来给暂存文件起名字
+    counter: usize,
+}
+
+impl Transaction {
+    /// 在`target_dir`上开始一次新的更新事务
+    ///
+    /// 上次更新意外中止时留下的暂存目录会被清理掉
+    pub fn new(target_dir: &Path) -> Result<Self, ClientError> {
+        let staging_dir = target_dir.join(STAGING_DIR);
+
+        if staging_dir.exists() {
+            std::fs::remove_dir_all(&staging_dir).with_path(&staging_dir)?;
+        }
+
+        std::fs::create_dir_all(&staging_dir).with_path(&staging_dir)?;
+
+        Ok(Self {
+            target_dir: target_dir.to_owned(),
+            staging_dir,
+            actions: Vec::new(),
+            folders: BTreeMap::new(),
+            journal: Vec::new(),
+            counter: 0,
+        })
+    }
+
+    /// 准备阶段：推演`versions`里所有版本的文件操作，并下载检查最终状态需要的数据，`versions`需要按版本顺序排列
+    pub(crate) async fn prepare(&mut self, downloader: &mut Downloader, versions: &[(VersionIndex, VersionMeta)]) -> Result<(), ClientError> {
+        // 推演出每个路径上最终的文件内容，补丁文件需要基于打补丁时的旧文件
+        let mut state = BTreeMap::<String, Content>::new();
+
+        for (index, meta) in versions {
+            let mut changes = meta.changes.clone();
+            changes.sort_by_key(|e| e.phase());
+
+            for change in changes {
+                match &change {
+                    FileChange::DeleteFile { path } => {
+                        state.insert(path.to_owned(), Content::Deleted);
+                    },
+                    FileChange::MoveFile { from, to } => {
+                        let content = resolve(&state, from);
+
+                        state.insert(from.to_owned(), Content::Deleted);
+                        state.insert(to.to_owned(), content);
+                    },
+                    FileChange::UpdateFile { path, .. } => {
+                        state.insert(path.to_owned(), Content::Remote { filename: index.filename.to_owned(), change: change.clone() });
+                    },
+                    FileChange::CreateIfMissing { path, .. } => {
+                        // 按需创建的文件在本地原本就存在时保持原样。这次更新里刚创建的文件不算，还是要更新到最新的内容
+                        if !self.exists_locally(&state, path) {
+                            state.insert(path.to_owned(), Content::Remote { filename: index.filename.to_owned(), change: change.clone() });
+                        }
+                    },
+                    FileChange::PatchFile { path, .. } => {
+                        let base = match resolve(&state, path) {
+                            Content::Deleted | Content::Symlink(_) => return Err(ClientError::Corrupted(format!("找不到要打补丁的旧文件: {}", path))),
+                            base => Box::new(base),
+                        };
+
+                        state.insert(path.to_owned(), Content::Patched { base, filename: index.filename.to_owned(), change: change.clone() });
+                    },
+                    FileChange::CreateSymlink { path, target } => {
+                        state.insert(path.to_owned(), Content::Symlink(target.to_owned()));
+                    },
+                    FileChange::CreateFolder { path } => {
+                        self.folders.insert(path.to_owned(), true);
+                    },
+                    FileChange::DeleteFolder { path } => {
+                        self.folders.insert(path.to_owned(), false);
+                    },
+                }
+            }
+        }
+
+        // 只下载最终状态需要的数据
+        for (path, content) in state {
+            let action = match content {
+                Content::Original(from) if from == path => continue,
+                Content::Original(from) => Action::Move(from),
+                Content::Symlink(target) => Action::Symlink(target),
+                Content::Deleted => Action::Delete,
+                Content::Remote { ref change, .. } | Content::Patched { ref change, .. } => {
+                    let (modified, mode) = change_metadata(change);
+
+                    Action::Place { staged: self.materialize(downloader, &path, content).await?, modified, mode }
+                },
+            };
+
+            self.actions.push((path, action));
+        }
+
+        Ok(())
+    }
+
+    /// 把一个文件的最终内容写到暂存目录里，返回暂存文件的路径
+    async fn materialize(&mut self, downloader: &mut Downloader, path: &str, content: Content) -> Result<PathBuf, ClientError> {
+        // 把补丁链展开，从最底层的文件开始逐个打补丁
+        let mut patches = Vec::new();
+        let mut base = content;
+
+        while let Content::Patched { base: inner, filename, change } = base {
+            patches.push((filename, change));
+            base = *inner;
+        }
+
+        let base_file = match base {
+            Content::Original(from) => self.checked_path(&from)?,
+            Content::Remote { filename, change } => self.download(downloader, &filename, &change).await?,
+            _ => return Err(ClientError::Corrupted(format!("找不到要打补丁的旧文件: {}", path))),
+        };
+
+        if patches.is_empty() {
+            return Ok(base_file);
+        }
+
+        let mut data = std::fs::read(&base_file).with_path(&base_file)?;
+
+        for (filename, change) in patches.into_iter().rev() {
+            let FileChange::PatchFile { path, hash, len, offset, patch_len, .. } = change else {
+                unreachable!()
+            };
+
+            let patch = downloader.fetch(&filename, Some(offset..offset + patch_len)).await?;
+
+            data = apply_delta(&data, &patch)
+                .map_err(|e| ClientError::Corrupted(format!("文件 {} 打补丁失败: {}", path, e)))?;
+
+            verify(&path, &mut data.as_slice(), data.len() as u64, len, &hash)?;
+        }
+
+        let staged = self.new_staging_path();
+        std::fs::write(&staged, &data).with_path(&staged)?;
+
+        Ok(staged)
+    }
+
+    /// 下载一个更新文件或者按需创建操作的完整数据，解压并检查后写到暂存目录里
+    async fn download(&mut self, downloader: &mut Downloader, filename: &str, change: &FileChange) -> Result<PathBuf, ClientError> {
+        let (FileChange::UpdateFile { path, hash, len, offset, compression, compressed_len, .. } |
+            FileChange::CreateIfMissing { path, hash, len, offset, compression, compressed_len, .. }) = change else {
+            unreachable!()
+        };
+
+        let staged = self.new_staging_path();
+        let mut file = std::fs::File::create(&staged).with_path(&staged)?;
+        let range = Some(*offset..*offset + *compressed_len);
+
+        match compression {
+            Compression::None => downloader.fetch_into(filename, range, &mut file).await?,
+            _ => {
+                let data = downloader.fetch(filename, range).await?;
+
+                std::io::copy(&mut decompress(*compression, &data)?, &mut file)
+                    .map_err(|e| ClientError::Corrupted(format!("文件 {} 解压失败: {}", path, e)))?;
+            },
+        }
+
+        drop(file);
+
+        let mut open = std::fs::File::open(&staged).with_path(&staged)?;
+        let actual_len = open.metadata().with_path(&staged)?.len();
+
+        verify(path, &mut open, actual_len, *len, hash)?;
+
+        Ok(staged)
+    }
+
+    /// 提交阶段：把准备好的所有文件操作应用到目标目录上，最后把本地版本号记录为`label`
+    ///
+    /// 中途出错时会撤销已经做过的修改，无论成功与否，暂存目录都会被删除
+    pub fn commit(mut self, label: &str) -> Result<(), ClientError> {
+        let result = self.apply_all(label);
+
+        if result.is_err() {
+            self.rollback();
+        }
+
+        // 清理失败不影响更新的结果
+        let _ = std::fs::remove_dir_all(&self.staging_dir);
+
+        result
+    }
+
+    /// 放弃这次更新，目标目录不会有任何变化
+    pub fn abort(self) {
+        let _ = std::fs::remove_dir_all(&self.staging_dir);
+    }
+
+    fn apply_all(&mut self, label: &str) -> Result<(), ClientError> {
+        let actions = std::mem::take(&mut self.actions);
+        let folders = std::mem::take(&mut self.folders);
+
+        // 1. 先把要移动的文件挪到暂存目录里，它们原本的位置可能马上就要被别的文件占用
+        let mut moving = BTreeMap::<String, PathBuf>::new();
+
+        for (_path, action) in &actions {
+            if let Action::Move(from) = action {
+                let file = self.checked_path(from)?;
+                let backup = self.backup(&file)?;
+
+                moving.insert(from.to_owned(), backup);
+            }
+        }
+
+        // 2. 删除旧文件，已经不存在的文件不需要再删除了
+        for (path, action) in &actions {
+            if let Action::Delete = action {
+                let file = self.checked_path(path)?;
+
+                if file.symlink_metadata().is_ok() {
+                    self.backup(&file)?;
+                }
+            }
+        }
+
+        // 3. 创建目录
+        for (path, exists) in &folders {
+            if *exists {
+                let dir = self.checked_path(path)?;
+
+                self.create_dirs(&dir)?;
+            }
+        }
+
+        // 4. 放入新的文件和符号链接
+        for (path, action) in &actions {
+            // 前面可能刚创建了符号链接，所以每一步都要重新检查
+            let file = self.checked_path(path)?;
+
+            match action {
+                Action::Delete => (),
+                Action::Move(from) => {
+                    let moved = &moving[from];
+
+                    if file.symlink_metadata().is_ok() {
+                        self.backup(&file)?;
+                    }
+
+                    self.create_parent(&file)?;
+
+                    std::fs::rename(moved, &file).with_path(&file)?;
+                    self.journal.push(Undo::MoveBack { from: file, to: moved.to_owned() });
+                },
+                Action::Place { staged, modified, mode } => {
+                    self.place(staged, &file)?;
+
+                    set_metadata(&file, *modified, *mode)?;
+                },
+                Action::Symlink(target) => {
+                    if file.symlink_metadata().is_ok() {
+                        self.backup(&file)?;
+                    }
+
+                    self.create_parent(&file)?;
+
+                    #[cfg(unix)]
+                    std::os::unix::fs::symlink(target, &file).with_path(&file)?;
+
+                    #[cfg(windows)]
+                    std::os::windows::fs::symlink_file(target, &file).with_path(&file)?;
+
+                    self.journal.push(Undo::RemoveFile(file));
+                },
+            }
+        }
+
+        // 5. 删除目录，先删除深层的目录
+        for (path, exists) in folders.iter().rev() {
+            let dir = self.checked_path(path)?;
+
+            if *exists || !dir.is_dir() {
+                continue;
+            }
+
+            // 目录里还有不受更新管理的文件时（比如玩家自己放进去的文件），保留这个目录
+            if dir.read_dir().with_path(&dir)?.next().is_some() {
+                continue;
+            }
+
+            std::fs::remove_dir(&dir).with_path(&dir)?;
+            self.journal.push(Undo::CreateDir(dir));
+        }
+
+        // 版本号文件也是事务的一部分
+        let staged = self.new_staging_path();
+        std::fs::write(&staged, label).with_path(&staged)?;
+
+        self.place(&staged, &self.target_dir.join(VERSION_FILE))
+    }
+
+    /// 把暂存文件`staged`放到`file`的位置上，原有的文件会被备份起来
+    fn place(&mut self, staged: &Path, file: &Path) -> Result<(), ClientError> {
+        if file.symlink_metadata().is_ok() {
+            self.backup(file)?;
+        }
+
+        self.create_parent(file)?;
+
+        std::fs::rename(staged, file).with_path(file)?;
+        self.journal.push(Undo::RemoveFile(file.to_owned()));
+
+        Ok(())
+    }
+
+    /// 把一个文件挪到暂存目录里备份起来，返回备份的位置
+    fn backup(&mut self, file: &Path) -> Result<PathBuf, ClientError> {
+        let backup = self.new_staging_path();
+
+        std::fs::rename(file, &backup).with_path(file)?;
+        self.journal.push(Undo::Restore { backup: backup.clone(), original: file.to_owned() });
+
+        Ok(backup)
+    }
+
+    fn create_parent(&mut self, file: &Path) -> Result<(), ClientError> {
+        match file.parent() {
+            Some(parent) => self.create_dirs(parent),
+            None => Ok(()),
+        }
+    }
+
+    /// 逐级创建目录，每一级新创建的目录都会记录下来，方便撤销
+    fn create_dirs(&mut self, dir: &Path) -> Result<(), ClientError> {
+        if dir.is_dir() {
+            return Ok(());
+        }
+
+        if let Some(parent) = dir.parent() {
+            self.create_dirs(parent)?;
+        }
+
+        std::fs::create_dir(dir).with_path(dir)?;
+        self.journal.push(Undo::RemoveDir(dir.to_owned()));
+
+        Ok(())
+    }
+
+    /// 按相反的顺序撤销所有已经做过的修改，尽可能地恢复，撤销时遇到的错误会被忽略
+    fn rollback(&mut self) {
+        while let Some(undo) = self.journal.pop() {
+            let _ = match undo {
+                Undo::RemoveDir(dir) => std::fs::remove_dir(dir),
+                Undo::RemoveFile(file) => std::fs::remove_file(file),
+                Undo::Restore { backup, original } => std::fs::rename(backup, original),
+                Undo::MoveBack { from, to } => std::fs::rename(from, to),
+                Undo::CreateDir(dir) => std::fs::create_dir(dir),
+            };
+        }
+    }
+
+    /// 推演到当前这一步时，`path`上是不是更新前就有的文件
+    fn exists_locally(&self, state: &BTreeMap<String, Content>, path: &str) -> bool {
+        match resolve(state, path) {
+            Content::Original(from) => self.target_dir.join(from).symlink_metadata().is_ok(),
+            _ => false,
+        }
+    }
+
+    /// 获取`path`在目标目录里的位置，路径上的任何一级父目录都不能是符号链接，不然就会修改到符号链接指向的地方去
+    fn checked_path(&self, path: &str) -> Result<PathBuf, ClientError> {
+        let file = self.target_dir.join(path);
+        let mut dir = self.target_dir.clone();
+
+        for component in Path::new(path).parent().into_iter().flat_map(|e| e.components()) {
+            dir.push(component);
+
+            if dir.symlink_metadata().is_ok_and(|e| e.file_type().is_symlink()) {
+                return Err(ClientError::UnsafePath(format!("{} 的父目录 {} 是一个符号链接", path, dir.display())));
+            }
+        }
+
+        Ok(file)
+    }
+
+    fn new_staging_path(&mut self) -> PathBuf {
+        self.counter += 1;
+
+        self.staging_dir.join(self.counter.to_string())
+    }
+}
+
+/// 推演到当前这一步时`path`上的文件内容，没有被更新动过的路径上是原本就有的文件
+fn resolve(state: &BTreeMap<String, Content>, path: &str) -> Content {
+    match state.get(path) {
+        Some(content) => content.clone(),
+        None => Content::Original(path.to_owned()),
+    }
+}
+
+/// 获取一个更新文件、按需创建或者补丁文件操作里的修改时间和权限
+fn change_metadata(change: &FileChange) -> (SystemTime, Option<u32>) {
+    match change {
+        FileChange::UpdateFile { modified, mode, .. } |
+        FileChange::CreateIfMissing { modified, mode, .. } |
+        FileChange::PatchFile { modified, mode, .. } => (*modified, *mode),
+        _ => unreachable!(),
+    }
+}
+
+fn decompress(compression: Compression, data: &[u8]) -> Result<Box<dyn Read + '_>, ClientError> {
+    Ok(match compression {
+        Compression::None => Box::new(data),
+        Compression::Zstd => Box::new(zstd::Decoder::new(data).map_err(|e| ClientError::Corrupted(e.to_string()))?),
+        Compression::Deflate => Box::new(flate2::read::DeflateDecoder::new(data)),
+    })
+}
+
+/// 检查文件的长度和校验值
+fn verify(path: &str, read: &mut impl Read, actual_len: u64, len: u64, hash: &str) -> Result<(), ClientError> {
+    if actual_len != len {
+        return Err(ClientError::Corrupted(format!("文件 {} 的长度不匹配，预期 {}，实际 {}", path, len, actual_len)));
+    }
+
+    let actual = calculate_hash_like(read, hash).with_path(path)?;
+
+    if actual != hash {
+        return Err(ClientError::Corrupted(format!("文件 {} 的校验值不匹配，预期 {}，实际 {}", path, hash, actual)));
+    }
+
+    Ok(())
+}
+
+/// 设置文件的修改时间和权限
+fn set_metadata(file: &Path, modified: SystemTime, mode: Option<u32>) -> Result<(), ClientError> {
+    let open = std::fs::File::options().write(true).open(file).with_path(file)?;
+
+    open.set_modified(modified).with_path(file)?;
+
+    #[cfg(unix)]
+    if let Some(mode) = mode {
+        use std::os::unix::fs::PermissionsExt;
+
+        open.set_permissions(std::fs::Permissions::from_mode(mode)).with_path(file)?;
+    }
+
+    #[cfg(not(unix))]
+    let _ = mode;
+
+    Ok(())
+}
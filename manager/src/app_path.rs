@@ -1,7 +1,12 @@
 use std::path::PathBuf;
 
+use crate::error::IoContext;
+use crate::error::ManagerError;
 use crate::utility::is_running_under_cargo;
 
+/// 默认的发布频道，对应的索引文件就是`index.json`
+pub const STABLE_CHANNEL: &str = "stable";
+
 /// 代表各种目录的信息
 #[derive(Clone)]
 pub struct AppPath {
@@ -17,7 +22,7 @@ pub struct AppPath {
     /// 外部加载的web目录。当这个目录存在时，会优先从这个目录加载web目录资源，然后是从可执行文件内部
     pub web_dir: PathBuf,
 
-    /// 索引文件路径。用来识别当前有哪些更新包
+    /// 索引文件路径。用来识别当前有哪些更新包（稳定频道）
     pub index_file: PathBuf,
 
     /// 配置文件路径。用来存储管理端的配置项目
@@ -25,6 +30,12 @@ pub struct AppPath {
 
     /// 认证数据文件路径。用来存储用户认证等数据
     pub auth_file: PathBuf,
+
+    /// 签名私钥文件路径。用来对索引文件和元数据进行签名，不能公开
+    pub signing_key_file: PathBuf,
+
+    /// 签名公钥文件路径。放在公共目录里，随更新包一起发布，供客户端验证签名
+    pub public_key_file: PathBuf,
 }
 
 impl AppPath {
@@ -42,6 +53,8 @@ impl AppPath {
         let index_file = working_dir.join("public/index.json");
         let config_file = working_dir.join("config.toml");
         let auth_file = working_dir.join("user.toml");
+        let signing_key_file = working_dir.join("signing.key");
+        let public_key_file = working_dir.join("public/signing.pub");
 
         std::fs::create_dir_all(&workspace_dir).unwrap();
         std::fs::create_dir_all(&public_dir).unwrap();
@@ -54,6 +67,43 @@ impl AppPath {
             index_file,
             config_file,
             auth_file,
+            signing_key_file,
+            public_key_file,
+        }
+    }
+
+    /// 获取一个发布频道的索引文件路径
+    /// 
+    /// 稳定频道使用`index.json`，其它频道使用`index.{频道名}.json`，比如`index.beta.json`
+    pub fn index_file_of(&self, channel: &str) -> PathBuf {
+        match channel == STABLE_CHANNEL {
+            true => self.index_file.clone(),
+            false => self.public_dir.join(format!("index.{}.json", channel)),
+        }
+    }
+
+    /// 列出所有的发布频道，稳定频道总是排在第一个，即使它的索引文件还不存在
+    pub fn channels(&self) -> Result<Vec<String>, ManagerError> {
+        let mut channels = Vec::<String>::new();
+
+        for entry in std::fs::read_dir(&self.public_dir).with_path(&self.public_dir)? {
+            let filename = entry.with_path(&self.public_dir)?.file_name().to_string_lossy().to_string();
+
+            let channel = filename.strip_prefix("index.").and_then(|e| e.strip_suffix(".json"));
+
+            if let Some(channel) = channel.filter(|e| *e != STABLE_CHANNEL && is_valid_channel_name(e)) {
+                channels.push(channel.to_owned());
+            }
         }
+
+        channels.sort();
+        channels.insert(0, STABLE_CHANNEL.to_owned());
+
+        Ok(channels)
     }
 }
+
+/// 检查频道名是否合法。频道名会成为文件名的一部分，所以只允许小写字母、数字、减号和下划线
+pub fn is_valid_channel_name(channel: &str) -> bool {
+    !channel.is_empty() && channel.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
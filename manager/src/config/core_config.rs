@@ -1,15 +1,50 @@
 use serde::Deserialize;
 use serde::Serialize;
 
+use crate::core::compression::Compression;
+use crate::core::file_hash::HashAlgorithm;
+use crate::diff::disk_file::SymlinkMode;
+
 /// 核心功能配置（主要是打包相关）
 #[derive(Serialize, Deserialize, Clone, Default)]
 #[serde(default, rename_all = "kebab-case")]
 pub struct CoreConfig {
-    /// 要排除的文件规则，格式为正则表达式，暂时不支持Glob表达式
+    /// 要排除的文件规则，格式为正则表达式，匹配路径中的任意一部分即可
     /// 匹配任意一条规则时，文件就会被忽略（忽略：管理端会当这个文件不存在一般）
-    /// 编写规则时可以使用check命令快速调试是否生效
+    /// 编写规则时可以使用check-ignore命令快速调试是否生效
     pub exclude_rules: Vec<String>,
 
+    /// 要排除的文件规则，格式和gitignore一样，支持Glob表达式、取反规则（!）和只匹配目录的规则（以/结尾）
+    /// 这些规则相对于工作空间目录，在exclude-rules之后匹配。也可以在工作空间的任意目录下创建.mcpatchignore文件来编写规则
+    pub ignore_rules: Vec<String>,
+
+    /// 只在客户端上不存在时才创建的文件，格式和ignore-rules一样，比如options.txt或者模组的配置文件
+    /// 匹配的文件在新增或者修改时会使用create-if-missing操作打包，玩家自己修改过的文件不会被覆盖。客户端需要支持这个操作，开启前请确认客户端版本
+    pub create_if_missing: Vec<String>,
+
+    /// 受保护的路径，格式和ignore-rules一样，比如saves/，screenshots/，journeymap/
+    /// 匹配的文件和目录即使从工作空间里删除了，打包时也不会生成删除操作，客户端上的这些文件永远不会被删除
+    pub protected_paths: Vec<String>,
+
     /// 是否工作在webui模式下，还是在交互式命令行模式下
     pub webui_mode: bool,
-}
\ No newline at end of file
+
+    /// 打包时对文件数据使用的压缩算法，可选值：none（不压缩），zstd，deflate
+    /// 客户端需要支持对应的压缩算法才能正常更新，开启前请确认客户端版本
+    pub compression: Compression,
+
+    /// 文件大小达到多少字节时才进行压缩，太小的文件压缩效果不明显
+    pub compression_threshold: u64,
+
+    /// 计算文件哈希值使用的算法，可选值：crc-combo（旧版默认），sha256，blake3
+    /// 切换算法后不需要重新打包，旧文件的哈希值会在下次被修改时自动换成新算法
+    pub hash_algorithm: HashAlgorithm,
+
+    /// 如何对待工作空间目录里的符号链接，可选值：follow（跟随链接，打包链接指向的文件），preserve（保留链接本身）
+    /// 使用preserve时客户端需要支持创建符号链接，开启前请确认客户端版本
+    pub symlink_mode: SymlinkMode,
+
+    /// 是否在每次打包、合并等操作之后，为每个频道的最新版本生成一个完整安装包（public目录下的full-版本号.tar.gz）
+    /// 完整安装包里直接存放着最新版本的所有文件，可以作为新玩家的下载链接。也可以使用full-install命令手动生成
+    pub full_install: bool,
+}
@@ -6,19 +6,20 @@ use std::ops::Deref;
 use std::path::Path;
 use std::path::PathBuf;
 
-use crate::core::data::version_meta::FileChange;
 use crate::core::data::version_meta::VersionMeta;
 use crate::core::file_hash::calculate_hash;
-use crate::core::tar_reader::TarReader;
+use crate::core::file_hash::HashAlgorithm;
+use crate::core::packed_file::open_packed_file;
 use crate::diff::abstract_file::AbstractFile;
 use crate::diff::diff::Diff;
 use crate::diff::history_file::HistoryFile;
+use crate::error::ManagerError;
 
 pub struct ArchiveTester {
-    /// key: 文件路径，value: (更新包路径, 偏移值, 长度, 版本号)
-    file_locations: HashMap<String, (PathBuf, u64, u64, String)>,
+    /// key: 版本号，value: 这个版本所在的更新包路径
+    archives: HashMap<String, PathBuf>,
 
-    /// 当前文件状态
+    /// 当前文件状态，同时也记录了每个文件的数据在哪个版本里
     history: HistoryFile,
 
     finished: bool,
@@ -27,7 +28,7 @@ pub struct ArchiveTester {
 impl ArchiveTester {
     pub fn new() -> Self {
         Self {
-            file_locations: HashMap::new(), 
+            archives: HashMap::new(), 
             history: HistoryFile::new_empty(),
             finished: false,
         }
@@ -37,23 +38,8 @@ impl ArchiveTester {
     pub fn feed_version(&mut self, archive: impl AsRef<Path>, meta: &VersionMeta) {
         self.history.replay_operations(&meta);
 
-        // 记录所有文件的数据和来源
-        for change in &meta.changes {
-            match change {
-                FileChange::UpdateFile { path, offset, len, .. } => {
-                    let tuple = (archive.as_ref().to_owned(), *offset, *len, meta.label.to_owned());
-                    self.file_locations.insert(path.to_owned(), tuple);
-                },
-                FileChange::DeleteFile { path } => {
-                    self.file_locations.remove(path);
-                },
-                FileChange::MoveFile { from, to } => {
-                    let hold = self.file_locations.remove(from).unwrap();
-                    self.file_locations.insert(to.to_owned(), hold);
-                }
-                _ => (),
-            }
-        }
+        // 记录这个版本的数据来源
+        self.archives.insert(meta.label.to_owned(), archive.as_ref().to_owned());
     }
 
     /// 开始测试
@@ -74,21 +60,29 @@ impl ArchiveTester {
         }
 
         let total = vec.len();
+        let archive_of = |label: &str| self.archives.get(label)
+            .map(|e| e.to_owned())
+            .ok_or_else(|| ManagerError::task(format!("找不到版本 {} 所在的更新包", label)));
 
         for (index, up) in vec.iter().enumerate() {
             let path = up.path();
             let path = path.deref();
-            let (archive, offset, len, label) = self.file_locations.get(path).unwrap();
+            let loc = up.file_location();
+            let label = &loc.version;
 
             // println!("{index}/{total} 正在测试 {label} 的 {path} ({offset}+{len})");
-            f(Testing { index, total, label, path, offset: *offset, len: *len });
+            f(Testing { index, total, label, path, offset: loc.offset, len: loc.length });
 
-            let mut reader = TarReader::new(&archive);
-            let mut open = reader.open_file(*offset, *len);
-            let actual = calculate_hash(&mut open);
             let expected = up.hash();
             let expected = expected.deref();
 
+            // 差异补丁会在这里被还原，如果补丁本身有问题，也视为测试失败
+            // 另外新旧版本的更新包可能使用了不同的哈希算法，所以要按预期值的算法来计算
+            let actual = match open_packed_file(loc, &archive_of) {
+                Ok(mut open) => calculate_hash(&mut open, HashAlgorithm::detect(expected)),
+                Err(e) => format!("corrupted: {}", e),
+            };
+
             if &actual != expected {
                 return Err(Failure {
                     path: path.to_owned(), 
@@ -0,0 +1,84 @@
+//! 更新包内的单文件压缩
+//! 
+//! 更新包本身是不压缩的tar文件，但里面的每个文件数据可以单独进行压缩。
+//! 因为客户端是按偏移值直接读取文件数据的，所以只能逐个文件压缩，而不能把整个tar包压缩起来
+//! 
+//! 压缩算法会记录在元数据里，客户端读取到数据后需要先按对应的算法解压，才能得到原始文件
+
+use std::io::Read;
+use std::io::Write;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+/// 代表文件数据的压缩算法
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Compression {
+    /// 不压缩，存储原始数据
+    #[default]
+    None,
+
+    /// 使用zstd压缩
+    Zstd,
+
+    /// 使用deflate压缩
+    Deflate,
+}
+
+impl Compression {
+    /// 从元数据里的名字解析压缩算法
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(Compression::None),
+            "zstd" => Some(Compression::Zstd),
+            "deflate" => Some(Compression::Deflate),
+            _ => None,
+        }
+    }
+
+    /// 获取压缩算法在元数据里的名字
+    pub fn name(&self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Zstd => "zstd",
+            Compression::Deflate => "deflate",
+        }
+    }
+
+    /// 压缩一段数据
+    pub fn compress(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Compression::None => data.to_owned(),
+            Compression::Zstd => zstd::encode_all(data, 0).unwrap(),
+            Compression::Deflate => {
+                let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data).unwrap();
+                encoder.finish().unwrap()
+            },
+        }
+    }
+
+    /// 尝试压缩一段数据，数据小于`threshold`或者压缩后没有变小时会原样返回，同时返回实际使用的压缩算法
+    pub fn compress_if_smaller(&self, data: Vec<u8>, threshold: u64) -> (Compression, Vec<u8>) {
+        if *self == Compression::None || (data.len() as u64) < threshold {
+            return (Compression::None, data);
+        }
+
+        let encoded = self.compress(&data);
+
+        match encoded.len() < data.len() {
+            true => (*self, encoded),
+            false => (Compression::None, data),
+        }
+    }
+
+    /// 将一个读取压缩数据的`read`包装成读取原始数据的Read，数据损坏时可能会返回错误
+    pub fn decompress<'a>(&self, read: impl Read + 'a) -> std::io::Result<Box<dyn Read + 'a>> {
+        Ok(match self {
+            Compression::None => Box::new(read),
+            Compression::Zstd => Box::new(zstd::Decoder::new(read)?),
+            Compression::Deflate => Box::new(flate2::read::DeflateDecoder::new(read)),
+        })
+    }
+}
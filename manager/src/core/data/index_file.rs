@@ -4,12 +4,27 @@ use std::collections::HashMap;
 use std::collections::HashSet;
 use std::ops::Index;
 use std::path::Path;
+use std::path::PathBuf;
 
 use json::JsonValue;
 
+use crate::app_path::AppPath;
+use crate::app_path::STABLE_CHANNEL;
+use crate::core::data::parse_error::check_format_version;
+use crate::core::data::parse_error::required_str;
+use crate::core::data::parse_error::required_u64;
+use crate::core::data::parse_error::ParseError;
+use crate::core::data::FORMAT_VERSION;
 use crate::core::data::version_meta::VersionMeta;
 use crate::core::data::version_meta_group::VersionMetaGroup;
+use crate::core::signing::signature_file_of;
+use crate::core::signing::Signer;
 use crate::core::tar_reader::TarReader;
+use crate::error::IoContext;
+use crate::error::ManagerError;
+
+/// 旧版本管理端生成的索引文件里，没有计算更新包校验值时使用的占位字符串
+pub const NO_HASH: &str = "no hash";
 
 /// 代表一个版本的索引信息
 /// 
@@ -17,11 +32,18 @@ use crate::core::tar_reader::TarReader;
 /// 
 /// ```json
 /// {
+///     "format_version": 1,
 ///     "label": "1.2",
 ///     "file": "1.2.tar",
 ///     "offset": 7A9C,
 ///     "length": 1000,
-///     "hash": "23B87EA52C893"
+///     "hash": "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08",
+///     "signature": "WuJv0Y3fZ6s...（base64）",
+///     "full_install": { // 完整安装包，只有最新版本才可能有这个字段，参考[`FullInstall`]
+///         "filename": "full-1.2.tar.gz",
+///         "length": 104857600,
+///         "hash": "2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae"
+///     }
 /// }
 /// ```
 #[derive(Clone)]
@@ -38,7 +60,29 @@ pub struct VersionIndex {
     /// 元数据组的长度
     pub len: u64,
 
-    /// 整个tar包文件的校验
+    /// 整个tar包文件的校验，使用SHA-256算法。旧版本的索引文件里这里可能是[`NO_HASH`]
+    pub hash: String,
+
+    /// 元数据组的Ed25519签名，没有启用签名时没有这个字段
+    pub signature: Option<String>,
+
+    /// 这个版本的完整安装包，没有生成时没有这个字段
+    pub full_install: Option<FullInstall>,
+}
+
+/// 代表一个完整安装包
+/// 
+/// 完整安装包是一个tar.gz文件，里面直接存放着某个版本的所有文件，新玩家下载解压后就可以直接使用，
+/// 不需要从第一个版本开始逐个更新
+#[derive(Clone)]
+pub struct FullInstall {
+    /// 安装包的文件名，位于public目录下
+    pub filename: String,
+
+    /// 安装包的文件大小
+    pub len: u64,
+
+    /// 安装包的校验值，使用SHA-256算法
     pub hash: String,
 }
 
@@ -53,49 +97,117 @@ impl IndexFile {
         Self { versions: Vec::new() }
     }
 
-    /// 从文件加载索引文件
-    pub fn load_from_file(index_file: &Path) -> Self {
-        let content = std::fs::read_to_string(index_file)
-            .unwrap_or_else(|_| "[]".to_owned());
+    /// 从文件加载索引文件，文件不存在时视为一个空的索引文件
+    pub fn load_from_file(index_file: &Path) -> Result<Self, ManagerError> {
+        let content = match std::fs::read_to_string(index_file) {
+            Ok(ok) => ok,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => "[]".to_owned(),
+            Err(err) => return Err(err).with_path(index_file),
+        };
         
-        Self::load_from_json(&content)
+        Ok(Self::load_from_json(&content)?)
+    }
+
+    /// 加载一个发布频道的索引文件，稳定频道以外的频道不存在时会返回错误
+    pub fn load_channel(apppath: &AppPath, channel: &str) -> Result<Self, ManagerError> {
+        let index_file = apppath.index_file_of(channel);
+
+        if channel != STABLE_CHANNEL && !index_file.exists() {
+            return Err(ManagerError::task(format!("频道不存在: {}", channel)));
+        }
+
+        Self::load_from_file(&index_file)
+    }
+
+    /// 加载一个要往里面发布版本的频道的索引文件
+    /// 
+    /// 还不存在的频道会使用稳定频道的版本列表作为起点，发布之后这个频道就被创建出来了
+    pub fn load_or_create_channel(apppath: &AppPath, channel: &str) -> Result<Self, ManagerError> {
+        let index_file = apppath.index_file_of(channel);
+
+        match index_file.exists() {
+            true => Self::load_from_file(&index_file),
+            false => Self::load_from_file(&apppath.index_file),
+        }
     }
 
     /// 从Json字符串加载
-    pub fn load_from_json(json: &str) -> Self {
-        let root = json::parse(json).unwrap();
+    pub fn load_from_json(json: &str) -> Result<Self, ParseError> {
+        let root = json::parse(json).map_err(|e| ParseError::InvalidJson(e.to_string()))?;
         let mut versions = Vec::<VersionIndex>::new();
 
-        for v in root.members() {
-            let label = v["label"].as_str().unwrap().to_owned();
-            let filename = v["filename"].as_str().unwrap().to_owned();
-            let offset = v["offset"].as_u64().unwrap();
-            let len = v["length"].as_u64().unwrap();
-            let hash = v["hash"].as_str().unwrap().to_owned();
+        if !root.is_array() {
+            return Err(ParseError::InvalidJson("索引文件必须是一个列表".to_owned()));
+        }
 
-            versions.push(VersionIndex { label, filename, len, offset, hash })
+        for v in root.members() {
+            check_format_version(v)?;
+
+            let label = required_str(v, "label", "索引文件")?;
+            let filename = required_str(v, "filename", &label)?;
+            let offset = required_u64(v, "offset", &label)?;
+            let len = required_u64(v, "length", &label)?;
+            let hash = required_str(v, "hash", &label)?;
+            let signature = v["signature"].as_str().map(|e| e.to_owned());
+
+            let full_install = match &v["full_install"] {
+                JsonValue::Null => None,
+                obj => Some(FullInstall {
+                    filename: required_str(obj, "filename", &label)?,
+                    len: required_u64(obj, "length", &label)?,
+                    hash: required_str(obj, "hash", &label)?,
+                }),
+            };
+
+            versions.push(VersionIndex { label, filename, len, offset, hash, signature, full_install })
         }
 
-        Self { versions }
+        Ok(Self { versions })
     }
 
     /// 将索引数据写到`index_file`文件里
-    pub fn save(&self, index_file: &Path) {
+    /// 
+    /// 如果提供了`signer`，还会在旁边生成一个`.sig`签名文件，否则会删掉可能残留的旧签名文件
+    pub fn save(&self, index_file: &Path, signer: Option<&Signer>) -> Result<(), ManagerError> {
         let mut root = JsonValue::new_array();
 
         for v in &self.versions {
             let mut obj = JsonValue::new_object();
 
+            obj.insert("format_version", FORMAT_VERSION).unwrap();
             obj.insert("label", v.label.to_owned()).unwrap();
             obj.insert("filename", v.filename.to_owned()).unwrap();
             obj.insert("offset", v.offset).unwrap();
             obj.insert("length", v.len).unwrap();
             obj.insert("hash", v.hash.to_owned()).unwrap();
+
+            if let Some(signature) = &v.signature {
+                obj.insert("signature", signature.to_owned()).unwrap();
+            }
+
+            if let Some(full_install) = &v.full_install {
+                let mut full = JsonValue::new_object();
+                full.insert("filename", full_install.filename.to_owned()).unwrap();
+                full.insert("length", full_install.len).unwrap();
+                full.insert("hash", full_install.hash.to_owned()).unwrap();
+
+                obj.insert("full_install", full).unwrap();
+            }
             
             root.push(obj).unwrap();
         }
 
-        std::fs::write(index_file, root.pretty(4)).unwrap()
+        let content = root.pretty(4);
+        std::fs::write(index_file, &content).with_path(index_file)?;
+
+        let signature_file = signature_file_of(index_file);
+
+        match signer {
+            Some(signer) => std::fs::write(&signature_file, signer.sign(content.as_bytes())).with_path(&signature_file)?,
+            None => { let _ = std::fs::remove_file(signature_file); },
+        }
+
+        Ok(())
     }
 
     /// 添加一个新版本
@@ -113,11 +225,32 @@ impl IndexFile {
         self.versions.iter().find(|e| e.label == label)
     }
 
+    /// 获取一个版本所在的更新包文件的路径，找不到这个版本时返回错误
+    pub fn archive_of(&self, public_dir: &Path, label: &str) -> Result<PathBuf, ManagerError> {
+        match self.find(label) {
+            Some(index) => Ok(public_dir.join(&index.filename)),
+            None => Err(ManagerError::task(format!("找不到版本 {} 所在的更新包", label))),
+        }
+    }
+
     /// 查找一个版本的可变索引数据
     pub fn find_mut(&mut self, label: &str) -> Option<&mut VersionIndex> {
         self.versions.iter_mut().find(|e| e.label == label)
     }
 
+    /// 检查自己的版本列表是不是`other`的版本列表的开头部分（两者完全一样也算）
+    /// 
+    /// 频道之间只有满足这个条件，才能安全地把版本从一个频道推送到另一个频道
+    pub fn is_prefix_of(&self, other: &IndexFile) -> bool {
+        self.versions.len() <= other.versions.len() && 
+            self.versions.iter().zip(&other.versions).all(|(a, b)| a.label == b.label)
+    }
+
+    /// 从`at`位置开始删除后面所有的版本，并返回被删除的版本
+    pub fn split_off(&mut self, at: usize) -> Vec<VersionIndex> {
+        self.versions.split_off(at)
+    }
+
     /// 版本的数量
     pub fn len(&self) -> usize {
         self.versions.len()
@@ -125,7 +258,12 @@ impl IndexFile {
 
     /// 读取所有的meta数据
     /// 收集所有需要读取的元数据信息，同时进行去重，避免一个文件的相同部分被读取多遍，虽然读不满，但是解析很慢
-    pub fn read_all_metas(&self, public_dir: &Path) -> Vec::<(VersionIndex, VersionMeta)> {
+    pub fn read_all_metas(&self, public_dir: &Path) -> Result<Vec::<(VersionIndex, VersionMeta)>, ManagerError> {
+        self.read_all_metas_with(|filename| public_dir.join(filename))
+    }
+
+    /// 和[`IndexFile::read_all_metas`]一样，但是由`archive_of`根据文件名决定去哪里读取更新包
+    pub fn read_all_metas_with(&self, archive_of: impl Fn(&str) -> PathBuf) -> Result<Vec::<(VersionIndex, VersionMeta)>, ManagerError> {
         let relevant_files = self.versions
             .iter()
             .map(|e| format!("{}|{}|{}", e.filename, e.offset, e.len))
@@ -141,9 +279,9 @@ impl IndexFile {
             let offset = u64::from_str_radix(split.next().unwrap(), 10).unwrap();
             let len = u64::from_str_radix(split.next().unwrap(), 10).unwrap();
             
-            let mut reader = TarReader::new(public_dir.join(&filename));
+            let mut reader = TarReader::new(archive_of(filename))?;
 
-            reading_cache.insert(file, reader.read_metadata_group(offset, len));
+            reading_cache.insert(file, reader.read_metadata_group(offset, len)?);
         }
 
         // 再根据索引文件里的内容进行返回
@@ -152,12 +290,13 @@ impl IndexFile {
         for v in &self.versions {
             let cache_key = format!("{}|{}|{}", v.filename, v.offset, v.len);
             let group = reading_cache.get(&cache_key).unwrap();
-            let meta = group.find_meta(&v.label).unwrap();
+            let meta = group.find_meta(&v.label)
+                .ok_or_else(|| ParseError::MissingField { field: "label", context: format!("{} 的元数据组里没有版本 {}", v.filename, v.label) })?;
             
             metas.push((v.clone(), meta.to_owned()));
         }
 
-        metas
+        Ok(metas)
     }
 }
 
@@ -179,6 +318,16 @@ impl<'a> IntoIterator for &'a IndexFile {
     }
 }
 
+impl<'a> IntoIterator for &'a mut IndexFile {
+    type Item = &'a mut VersionIndex;
+
+    type IntoIter = std::slice::IterMut<'a, VersionIndex>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.versions.iter_mut()
+    }
+}
+
 impl IntoIterator for IndexFile {
     type Item = VersionIndex;
 
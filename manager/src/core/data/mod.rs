@@ -5,22 +5,42 @@
 //! 1. public：存放更新包和索引文件的地方
 //! 2. workspace：日常维护要更新的文件的地方
 //! 5. config.toml：管理端的配置文件
+//! 6. signing.key：签名私钥，不能公开
 //! 
 //! ### public目录下的文件
 //! 
 //! public目录负责存储所有更新包文件，索引文件这些供大家下载的公共文件
 //! 
-//! 1. index.txt：索引文件，也叫版本号列表文件，会存储每个版本的元数据的信息
-//! 2. index.internal.txt：同上，但是用于支持灰度发布
+//! 1. index.json：索引文件，也叫版本号列表文件，会存储每个版本的元数据的信息（稳定频道）
+//! 2. index.beta.json：同上，但是属于其它发布频道（这里是beta频道），用于支持灰度发布
 //! 3. combined.tar：合并包，所有合并后的更新包内容都会放到这个文件里，名字固定叫combined.tar
 //! 4. 1.0.tar：用户创建的1.0版本更新包
 //! 5. 1.1.tar：用户创建的1.1版本更新包
 //! 6. 还有更多用户创建的更新包...
+//! 7. index.json.sig：索引文件的签名，仅在生成过签名密钥后才有，其它频道的索引文件也一样
+//! 8. signing.pub：用来验证签名的公钥
 //! 
 //! 合并包文件和普通用户创建更新包文件是个容器，一个文件里面可以容纳多个版本的数据。
 //! 一般情况下，合并包会装多个版本的数据，而普通包只装一个版本的数据。
 //! 在合并更新包时，所有的普通包内的内容会被全部挪动到合并包里面去
+//! 
+//! ### 发布频道
+//! 
+//! 每个频道都有一个自己的索引文件，但是所有频道共用同一批更新包文件。
+//! 一个版本通常会先打包到beta等频道里，测试没问题后再推送（promote）到稳定频道。
+//! 打包到还不存在的频道时，会使用稳定频道的版本列表作为起点，其它操作遇到不存在的频道会报错
+//! 
+//! ### 数据格式版本
+//! 
+//! 索引文件里的每个版本和每个版本元数据都会带上一个`format_version`字段，记录生成它的数据格式版本。
+//! 新增可选字段这类兼容的修改不需要提升格式版本，旧程序会忽略掉不认识的字段。
+//! 只有旧程序无法正确处理的修改才需要提升格式版本，旧程序遇到更新的格式版本时会报错，而不是错误地解析。
+//! 旧版本生成的数据没有这个字段，视为版本0
 
 pub mod version_meta;
 pub mod index_file;
 pub mod version_meta_group;
+pub mod parse_error;
+
+/// 当前程序生成和支持的数据格式版本
+pub const FORMAT_VERSION: u32 = 1;
@@ -0,0 +1,78 @@
+//! 数据解析错误
+//!
+//! 索引文件和元数据都来自磁盘上的文件，可能是新版本管理端生成的，也可能已经损坏了。
+//! 解析时遇到的问题会以[`ParseError`]的形式返回，由调用者决定如何处理
+
+use std::fmt::Display;
+
+use json::JsonValue;
+
+use crate::core::data::FORMAT_VERSION;
+
+/// 代表解析索引文件或者元数据时遇到的错误
+#[derive(Debug)]
+pub enum ParseError {
+    /// 内容不是合法的json
+    InvalidJson(String),
+
+    /// 缺少必须的字段，或者字段的类型不对
+    MissingField {
+        /// 字段名
+        field: &'static str,
+
+        /// 字段所在的对象，一般是版本号或者文件路径，方便定位问题
+        context: String,
+    },
+
+    /// 字段的值无法识别
+    InvalidValue {
+        /// 字段名
+        field: &'static str,
+
+        /// 无法识别的值
+        value: String,
+    },
+
+    /// 无法识别的文件操作，一般是由更新版本的管理端生成的
+    UnknownOperation(String),
+
+    /// 数据格式的版本比当前程序支持的版本更新
+    UnsupportedFormatVersion(u32),
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::InvalidJson(reason) => write!(f, "不是合法的json: {}", reason),
+            ParseError::MissingField { field, context } => write!(f, "缺少字段或者字段类型不正确: {}（位于 {}）", field, context),
+            ParseError::InvalidValue { field, value } => write!(f, "无法识别的字段值: {} = {}", field, value),
+            ParseError::UnknownOperation(operation) => write!(f, "无法识别的文件操作: {}，请升级管理端", operation),
+            ParseError::UnsupportedFormatVersion(version) => write!(f, "不支持的数据格式版本: {}（最高支持 {}），请升级管理端", version, FORMAT_VERSION),
+        }
+    }
+}
+
+impl std::error::Error for ParseError { }
+
+/// 读取一个必须存在的字符串字段
+pub fn required_str(obj: &JsonValue, field: &'static str, context: &str) -> Result<String, ParseError> {
+    obj[field].as_str()
+        .map(|e| e.to_owned())
+        .ok_or_else(|| ParseError::MissingField { field, context: context.to_owned() })
+}
+
+/// 读取一个必须存在的整数字段
+pub fn required_u64(obj: &JsonValue, field: &'static str, context: &str) -> Result<u64, ParseError> {
+    obj[field].as_u64()
+        .ok_or_else(|| ParseError::MissingField { field, context: context.to_owned() })
+}
+
+/// 检查一个对象的`format_version`字段，旧版本的数据没有这个字段，视为版本0
+pub fn check_format_version(obj: &JsonValue) -> Result<(), ParseError> {
+    let version = obj["format_version"].as_u32().unwrap_or(0);
+
+    match version > FORMAT_VERSION {
+        true => Err(ParseError::UnsupportedFormatVersion(version)),
+        false => Ok(()),
+    }
+}
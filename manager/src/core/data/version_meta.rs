@@ -6,6 +6,7 @@
 //! 
 //! ```json
 //! {
+//!     "format_version": 1, // 数据格式版本，参考[`crate::core::data`]
 //!     "label": "1.0", // 版本号
 //!     "logs": "这是这个版本的更新记录文字示例", // 这个版本的更新日志
 //!     "changes": [ // 记录所有文件修改操作
@@ -19,7 +20,33 @@
 //!             "hash": "82e09fc553b335ab_1306", // 文件校验值
 //!             "length": 13761,                 // 文件长度
 //!             "modified": 1705651134,          // 文件的修改时间
-//!             "offset": 98724                  // 二进制数据在更新包中的偏移值
+//!             "mode": 493,                     // unix文件权限（十进制，这里是0o755），不知道权限时省略
+//!             "offset": 98724,                 // 二进制数据在更新包中的偏移值
+//!             "compression": "zstd",           // 数据的压缩算法，不压缩时省略
+//!             "compressed_len": 5120           // 压缩后的数据长度，不压缩时省略
+//!         }, 
+//!         {
+//!             "operation": "create-if-missing", // 只在文件不存在时创建，已经存在的文件保持原样（比如玩家修改过的配置文件）
+//!             "path": "options.txt",           // 其余字段和update-file完全一样
+//!             "hash": "5d1a2e7b90c4f8a3_2048",
+//!             "len": 2048,
+//!             "modified": 1705651134,
+//!             "offset": 99328
+//!         }, 
+//!         {
+//!             "operation": "patch-file",       // 用差异补丁更新现有文件
+//!             "path": "resourcepacks/a.zip",   // 要更新的文件路径
+//!             "hash": "0c3e1b52a7d0c4f1_93ab", // 打完补丁后的文件校验值
+//!             "len": 209715200,                // 打完补丁后的文件长度
+//!             "modified": 1705651134,          // 文件的修改时间
+//!             "mode": 420,                     // unix文件权限（十进制，这里是0o644），不知道权限时省略
+//!             "offset": 113152,                // 补丁数据在更新包中的偏移值
+//!             "patch_len": 41960               // 补丁数据的长度
+//!         }, 
+//!         {
+//!             "operation": "create-symlink",   // 创建一个符号链接
+//!             "path": ".minecraft/config",     // 符号链接的路径
+//!             "target": "../shared/config"     // 链接指向的路径（原样保存，可以是相对路径）
 //!         }, 
 //!         {
 //!             "operation": "delete-directory", // 删除一个目录
@@ -39,9 +66,15 @@
 //! ```
 //! 所有这些文件修改操作会被记录下来，并发送到客户端，客户端收到后，会复现这些操作，这样就完成了文件同步
 //! 
-//! 在复现这些文件修改时需要讲究严格顺序：删除旧文件 -> 覆盖文件 -> 移动文件 -> 更新文件 -> 删除目录
+//! 在复现这些文件修改时需要讲究严格顺序：删除旧文件 -> 覆盖文件 -> 移动文件 -> 更新文件 -> 创建符号链接 -> 删除目录
+//! 
+//! “按需创建的文件”也是“覆盖的文件”的一种特殊形式，和它处于同一个阶段，更新包里同样存有完整的文件数据。
+//! 区别在于客户端上已经存在这个文件时不做任何修改，用于选项文件等会被玩家自行修改的文件
 //! 
 //! 所有“覆盖的文件”除了有路径和哈希以外，打包时还得额外带上这个文件本身的二进制数据，这样客户端才可以进行解压覆盖。而其它文件操作则只需要有路径就够了，没有必要带着完整的文件数据
+//! 
+//! “补丁文件”是“覆盖的文件”的一种特殊形式，更新包里存的不是完整的文件数据，而是相对上个版本的差异补丁（参考[`crate::core::delta`]）。
+//! 客户端需要读取本地的旧文件，应用补丁后才能得到新文件。在复现时它和“覆盖的文件”处于同一个阶段
 
 use std::collections::LinkedList;
 use std::ops::Add;
@@ -51,6 +84,13 @@ use std::time::UNIX_EPOCH;
 
 use json::JsonValue;
 
+use crate::core::compression::Compression;
+use crate::core::data::parse_error::check_format_version;
+use crate::core::data::parse_error::required_str;
+use crate::core::data::parse_error::required_u64;
+use crate::core::data::parse_error::ParseError;
+use crate::core::data::FORMAT_VERSION;
+
 /// 代表单个文件操作
 #[derive(Clone)]
 pub enum FileChange {
@@ -74,8 +114,77 @@ pub enum FileChange {
         /// 文件的修改时间
         modified: SystemTime, 
 
+        /// unix文件权限，不知道权限时为None
+        mode: Option<u32>,
+
         /// 文件二进制数据在更新包中的偏移值
-        offset: u64
+        offset: u64,
+
+        /// 文件二进制数据的压缩算法
+        compression: Compression,
+
+        /// 文件二进制数据压缩后的长度，没有压缩时和`len`相等
+        compressed_len: u64,
+    },
+
+    /// 文件不存在时才创建，已经存在时保持原样，各字段的含义和`UpdateFile`一样
+    CreateIfMissing {
+        /// 要创建的文件路径
+        path: String, 
+
+        /// 文件校验值
+        hash: String, 
+        
+        /// 文件长度
+        len: u64, 
+        
+        /// 文件的修改时间
+        modified: SystemTime, 
+
+        /// unix文件权限，不知道权限时为None
+        mode: Option<u32>,
+
+        /// 文件二进制数据在更新包中的偏移值
+        offset: u64,
+
+        /// 文件二进制数据的压缩算法
+        compression: Compression,
+
+        /// 文件二进制数据压缩后的长度，没有压缩时和`len`相等
+        compressed_len: u64,
+    },
+
+    /// 使用差异补丁更新现有文件
+    PatchFile {
+        /// 要更新的文件路径
+        path: String, 
+
+        /// 打完补丁后的文件校验值
+        hash: String, 
+        
+        /// 打完补丁后的文件长度
+        len: u64, 
+        
+        /// 文件的修改时间
+        modified: SystemTime, 
+
+        /// unix文件权限，不知道权限时为None
+        mode: Option<u32>,
+
+        /// 补丁数据在更新包中的偏移值
+        offset: u64,
+
+        /// 补丁数据的长度
+        patch_len: u64,
+    },
+
+    /// 创建一个符号链接
+    CreateSymlink {
+        /// 符号链接的路径
+        path: String,
+
+        /// 链接指向的路径
+        target: String,
     },
 
     /// 删除一个目录
@@ -100,6 +209,18 @@ pub enum FileChange {
     },
 }
 
+impl FileChange {
+    /// 把一个“更新文件”操作转换成“按需创建文件”操作，其它操作原样返回
+    pub fn into_create_if_missing(self) -> Self {
+        match self {
+            FileChange::UpdateFile { path, hash, len, modified, mode, offset, compression, compressed_len } => {
+                FileChange::CreateIfMissing { path, hash, len, modified, mode, offset, compression, compressed_len }
+            },
+            other => other,
+        }
+    }
+}
+
 /// 代表一个版本的元数据
 #[derive(Clone)]
 pub struct VersionMeta {
@@ -120,14 +241,23 @@ impl VersionMeta {
     }
 
     /// 加载一个现有的版本元数据
-    pub fn load(obj: &JsonValue) -> Self {
-        Self {
-            label: obj["label"].as_str().unwrap().to_owned(),
-            logs: obj["logs"].as_str().unwrap().to_owned(), 
-            changes: obj["changes"].members()
+    pub fn load(obj: &JsonValue) -> Result<Self, ParseError> {
+        check_format_version(obj)?;
+
+        let label = required_str(obj, "label", "版本元数据")?;
+
+        let changes = match &obj["changes"] {
+            JsonValue::Array(changes) => changes.iter()
                 .map(|e| Self::parse_change(e))
-                .collect::<LinkedList<_>>()
-        }
+                .collect::<Result<LinkedList<_>, _>>()?,
+            _ => return Err(ParseError::MissingField { field: "changes", context: label }),
+        };
+
+        Ok(Self {
+            logs: required_str(obj, "logs", &label)?, 
+            label,
+            changes,
+        })
     }
 
     /// 将版本元数据序列化成JsonObject
@@ -139,6 +269,7 @@ impl VersionMeta {
             changes.push(Self::serialize_change(change)).unwrap();
         }
         
+        obj.insert("format_version", FORMAT_VERSION).unwrap();
         obj.insert("label", self.label.clone()).unwrap();
         obj.insert("logs", self.logs.clone()).unwrap();
         obj.insert("changes", changes).unwrap();
@@ -146,40 +277,85 @@ impl VersionMeta {
     }
 
     /// 解析单个文件变动操作
-    fn parse_change(v: &JsonValue) -> FileChange {
-        match v["operation"].as_str().unwrap() {
+    fn parse_change(v: &JsonValue) -> Result<FileChange, ParseError> {
+        let operation = required_str(v, "operation", "文件操作")?;
+
+        // 报错时用来定位是哪个文件的操作
+        let context = v["path"].as_str().or(v["from"].as_str()).unwrap_or(&operation).to_owned();
+        let string = |field: &'static str| required_str(v, field, &context);
+        let number = |field: &'static str| required_u64(v, field, &context);
+        let modified = || number("modified").map(|e| UNIX_EPOCH.add(Duration::from_secs(e)));
+
+        let change = match operation.as_str() {
             "create-directory" => {
                 FileChange::CreateFolder {
-                    path: v["path"].as_str().unwrap().to_owned()
+                    path: string("path")?
+                }
+            },
+            "update-file" | "create-if-missing" => {
+                let len = number("len")?;
+
+                // 旧版本的元数据里没有这两个字段，视为不压缩
+                let compression = match v["compression"].as_str() {
+                    Some(name) => Compression::parse(name)
+                        .ok_or_else(|| ParseError::InvalidValue { field: "compression", value: name.to_owned() })?,
+                    None => Compression::None,
+                };
+
+                let change = FileChange::UpdateFile {
+                    path: string("path")?, 
+                    hash: string("hash")?, 
+                    len, 
+                    modified: modified()?, 
+                    // 旧版本的元数据里没有这个字段，视为不知道权限
+                    mode: v["mode"].as_u32(),
+                    offset: number("offset")?,
+                    compression,
+                    compressed_len: v["compressed_len"].as_u64().unwrap_or(len),
+                };
+
+                match operation.as_str() {
+                    "create-if-missing" => change.into_create_if_missing(),
+                    _ => change,
                 }
             },
-            "update-file" => {
-                FileChange::UpdateFile {
-                    path: v["path"].as_str().unwrap().to_owned(), 
-                    hash: v["hash"].as_str().unwrap().to_owned(), 
-                    len: v["len"].as_u64().unwrap(), 
-                    modified: UNIX_EPOCH.add(Duration::from_secs(v["modified"].as_u64().unwrap())), 
-                    offset: v["offset"].as_u64().unwrap(),
+            "patch-file" => {
+                FileChange::PatchFile {
+                    path: string("path")?, 
+                    hash: string("hash")?, 
+                    len: number("len")?, 
+                    modified: modified()?, 
+                    mode: v["mode"].as_u32(),
+                    offset: number("offset")?,
+                    patch_len: number("patch_len")?,
+                }
+            },
+            "create-symlink" => {
+                FileChange::CreateSymlink {
+                    path: string("path")?, 
+                    target: string("target")?, 
                 }
             },
             "delete-directory" => {
                 FileChange::DeleteFolder {
-                    path: v["path"].as_str().unwrap().to_owned(), 
+                    path: string("path")?, 
                 }
             },
             "delete-file" => {
                 FileChange::DeleteFile {
-                    path: v["path"].as_str().unwrap().to_owned(), 
+                    path: string("path")?, 
                 }
             },
             "move-file" => {
                 FileChange::MoveFile {
-                    from: v["from"].as_str().unwrap().to_owned(), 
-                    to: v["to"].as_str().unwrap().to_owned(),
+                    from: string("from")?, 
+                    to: string("to")?,
                 }
             },
-            _ => panic!("unknown operation: {}", v["operation"].as_str().unwrap())
-        }
+            _ => return Err(ParseError::UnknownOperation(operation)),
+        };
+
+        Ok(change)
     }
 
     /// 序列化一个文件变动操作
@@ -191,13 +367,41 @@ impl VersionMeta {
                 obj.insert("operation", "create-directory").unwrap();
                 obj.insert("path", path.to_owned()).unwrap();
             },
-            FileChange::UpdateFile { path, hash, len, modified, offset } => {
-                obj.insert("operation", "update-file").unwrap();
+            FileChange::UpdateFile { path, hash, len, modified, mode, offset, compression, compressed_len } |
+            FileChange::CreateIfMissing { path, hash, len, modified, mode, offset, compression, compressed_len } => {
+                let operation = match change {
+                    FileChange::CreateIfMissing { .. } => "create-if-missing",
+                    _ => "update-file",
+                };
+
+                obj.insert("operation", operation).unwrap();
+                obj.insert("path", path.to_owned()).unwrap();
+                obj.insert("hash", hash.to_owned()).unwrap();
+                obj.insert("len", len.to_owned()).unwrap();
+                obj.insert("modified", modified.duration_since(UNIX_EPOCH).unwrap().as_secs()).unwrap();
+                Self::serialize_mode(&mut obj, mode);
+                obj.insert("offset", offset.to_owned()).unwrap();
+
+                // 不压缩时省略这两个字段，保持和旧版本客户端的兼容
+                if *compression != Compression::None {
+                    obj.insert("compression", compression.name()).unwrap();
+                    obj.insert("compressed_len", compressed_len.to_owned()).unwrap();
+                }
+            },
+            FileChange::PatchFile { path, hash, len, modified, mode, offset, patch_len } => {
+                obj.insert("operation", "patch-file").unwrap();
                 obj.insert("path", path.to_owned()).unwrap();
                 obj.insert("hash", hash.to_owned()).unwrap();
                 obj.insert("len", len.to_owned()).unwrap();
                 obj.insert("modified", modified.duration_since(UNIX_EPOCH).unwrap().as_secs()).unwrap();
+                Self::serialize_mode(&mut obj, mode);
                 obj.insert("offset", offset.to_owned()).unwrap();
+                obj.insert("patch_len", patch_len.to_owned()).unwrap();
+            },
+            FileChange::CreateSymlink { path, target } => {
+                obj.insert("operation", "create-symlink").unwrap();
+                obj.insert("path", path.to_owned()).unwrap();
+                obj.insert("target", target.to_owned()).unwrap();
             },
             FileChange::DeleteFolder { path } => {
                 obj.insert("operation", "delete-directory").unwrap();
@@ -216,4 +420,11 @@ impl VersionMeta {
 
         obj
     }
+
+    /// 序列化文件权限，不知道权限时省略这个字段
+    fn serialize_mode(obj: &mut JsonValue, mode: &Option<u32>) {
+        if let Some(mode) = mode {
+            obj.insert("mode", *mode).unwrap();
+        }
+    }
 }
\ No newline at end of file
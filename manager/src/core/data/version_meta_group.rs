@@ -7,6 +7,7 @@
 //! ```json
 //! [
 //!     {
+//!         "format_version": 1, // 数据格式版本
 //!         "label": "1.0", // 版本号
 //!         "logs": "这是这个版本的更新记录文字示例", // 这个版本的更新日志
 //!         "changes": [] // 记录所有文件修改操作
@@ -26,6 +27,7 @@
 
 use json::JsonValue;
 
+use crate::core::data::parse_error::ParseError;
 use crate::core::data::version_meta::VersionMeta;
 use crate::utility::vec_ext::VecRemoveIf;
 
@@ -44,10 +46,14 @@ impl VersionMetaGroup {
     }
 
     /// 从json字符串进行解析
-    pub fn parse(meta: &str) -> Self {
-        let root = json::parse(meta).unwrap();
+    pub fn parse(meta: &str) -> Result<Self, ParseError> {
+        let root = json::parse(meta).map_err(|e| ParseError::InvalidJson(e.to_string()))?;
 
-        VersionMetaGroup(root.members().map(|e| VersionMeta::load(e)).collect())
+        if !root.is_array() {
+            return Err(ParseError::InvalidJson("元数据组必须是一个列表".to_owned()));
+        }
+
+        Ok(VersionMetaGroup(root.members().map(|e| VersionMeta::load(e)).collect::<Result<_, _>>()?))
     }
 
     /// 将元数据组序列化成json字符串
@@ -123,4 +129,36 @@ impl<'a> IntoIterator for &'a mut VersionMetaGroup {
     fn into_iter(self) -> Self::IntoIter {
         (&mut self.0).into_iter()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::data::parse_error::ParseError;
+    use crate::core::data::version_meta_group::VersionMetaGroup;
+
+    #[test]
+    fn test_parse_tolerates_unknown_fields() {
+        let group = VersionMetaGroup::parse(r#"[{
+            "label": "1.0",
+            "logs": "",
+            "some-future-field": { "a": 1 },
+            "changes": [{ "operation": "delete-file", "path": "a.txt", "reason": "unused" }]
+        }]"#).unwrap();
+
+        assert!(group.contains_meta("1.0"));
+
+        let round_trip = VersionMetaGroup::parse(&group.serialize()).unwrap();
+
+        assert_eq!(round_trip.find_meta("1.0").unwrap().changes.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let parse = |json: &str| VersionMetaGroup::parse(json).err().unwrap();
+
+        assert!(matches!(parse("not json"), ParseError::InvalidJson(_)));
+        assert!(matches!(parse(r#"[{ "label": "1.0", "changes": [] }]"#), ParseError::MissingField { field: "logs", .. }));
+        assert!(matches!(parse(r#"[{ "label": "1.0", "logs": "", "changes": [{ "operation": "teleport-file" }] }]"#), ParseError::UnknownOperation(_)));
+        assert!(matches!(parse(r#"[{ "format_version": 999, "label": "1.0", "logs": "", "changes": [] }]"#), ParseError::UnsupportedFormatVersion(999)));
+    }
+}
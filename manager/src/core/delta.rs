@@ -0,0 +1,244 @@
+//! 二进制差异补丁
+//!
+//! 当一个文件只修改了一小部分时，没有必要把整个文件重新打包一次，只需要记录新文件相较旧文件的差异即可
+//!
+//! 差异补丁的计算方式类似rsync：先将旧文件按固定大小切成块，为每个块计算一个弱校验值并建立索引。
+//! 然后用滚动校验的方式在新文件上逐字节滑动，一旦校验值命中，就再逐字节确认并尽可能向后扩展匹配范围
+//!
+//! 补丁的二进制格式如下（所有整数都是小端序u64）：
+//!
+//! 1. 魔数`MCPD`，4个字节
+//! 2. 新文件的长度
+//! 3. 若干条指令，每条指令以1个字节的类型开头
+//!    + `0x01`：从旧文件复制，后面跟着旧文件中的偏移值和长度
+//!    + `0x02`：插入新数据，后面跟着数据长度和数据本身
+
+use std::collections::HashMap;
+
+/// 补丁文件的魔数
+const MAGIC: &[u8; 4] = b"MCPD";
+
+/// 旧文件切块的大小
+const BLOCK_SIZE: usize = 2048;
+
+/// 指令：从旧文件复制
+const OP_COPY: u8 = 0x01;
+
+/// 指令：插入新数据
+const OP_INSERT: u8 = 0x02;
+
+/// 计算从`old`变成`new`所需的差异补丁
+pub fn create_delta(old: &[u8], new: &[u8]) -> Vec<u8> {
+    let mut output = Vec::<u8>::with_capacity(new.len() / 4 + 16);
+    output.extend_from_slice(MAGIC);
+    output.extend_from_slice(&(new.len() as u64).to_le_bytes());
+
+    // 旧文件或者新文件太小时，没有必要去匹配了，直接插入所有数据
+    if old.len() < BLOCK_SIZE || new.len() < BLOCK_SIZE {
+        write_insert(&mut output, new);
+        return output;
+    }
+
+    // 给旧文件的每一个块建立索引
+    let mut blocks = HashMap::<u32, Vec<usize>>::new();
+
+    for start in (0..=old.len() - BLOCK_SIZE).step_by(BLOCK_SIZE) {
+        let checksum = RollingChecksum::new(&old[start..start + BLOCK_SIZE]).value();
+        blocks.entry(checksum).or_default().push(start);
+    }
+
+    // 在新文件上滑动窗口寻找相同的块
+    let mut literal_start = 0usize;
+    let mut pos = 0usize;
+    let mut rolling = RollingChecksum::new(&new[0..BLOCK_SIZE]);
+
+    while pos + BLOCK_SIZE <= new.len() {
+        let window = &new[pos..pos + BLOCK_SIZE];
+
+        let matched = blocks.get(&rolling.value())
+            .and_then(|candidates| candidates.iter().find(|c| &old[**c..**c + BLOCK_SIZE] == window))
+            .copied();
+
+        match matched {
+            Some(old_start) => {
+                // 尽可能向后扩展匹配范围
+                let mut len = BLOCK_SIZE;
+
+                while pos + len < new.len() && old_start + len < old.len() && new[pos + len] == old[old_start + len] {
+                    len += 1;
+                }
+
+                // 尽可能向前扩展匹配范围，把还没写出去的新数据吃掉一部分
+                let mut back = 0usize;
+
+                while pos - back > literal_start && old_start - back > 0 && new[pos - back - 1] == old[old_start - back - 1] {
+                    back += 1;
+                }
+
+                write_insert(&mut output, &new[literal_start..pos - back]);
+                write_copy(&mut output, (old_start - back) as u64, (len + back) as u64);
+
+                pos += len;
+                literal_start = pos;
+
+                if pos + BLOCK_SIZE <= new.len() {
+                    rolling = RollingChecksum::new(&new[pos..pos + BLOCK_SIZE]);
+                }
+            },
+            None => {
+                if pos + BLOCK_SIZE < new.len() {
+                    rolling.roll(new[pos], new[pos + BLOCK_SIZE]);
+                }
+
+                pos += 1;
+            },
+        }
+    }
+
+    write_insert(&mut output, &new[literal_start..]);
+
+    output
+}
+
+/// 将差异补丁`delta`应用到`old`上，还原出新文件的数据
+pub fn apply_delta(old: &[u8], delta: &[u8]) -> Result<Vec<u8>, String> {
+    if delta.len() < 12 || &delta[0..4] != MAGIC {
+        return Err("not a valid delta patch".to_owned());
+    }
+
+    let new_len = read_u64(delta, 4)? as usize;
+    let mut output = Vec::<u8>::with_capacity(new_len);
+    let mut pos = 12usize;
+
+    while pos < delta.len() {
+        let op = delta[pos];
+        pos += 1;
+
+        match op {
+            OP_COPY => {
+                let offset = read_u64(delta, pos)? as usize;
+                let len = read_u64(delta, pos + 8)? as usize;
+                pos += 16;
+
+                let end = offset.checked_add(len).filter(|e| *e <= old.len())
+                    .ok_or_else(|| format!("copy out of range: {}+{} (old length: {})", offset, len, old.len()))?;
+
+                output.extend_from_slice(&old[offset..end]);
+            },
+            OP_INSERT => {
+                let len = read_u64(delta, pos)? as usize;
+                pos += 8;
+
+                let end = pos.checked_add(len).filter(|e| *e <= delta.len())
+                    .ok_or_else(|| format!("insert out of range: {}+{}", pos, len))?;
+
+                output.extend_from_slice(&delta[pos..end]);
+                pos = end;
+            },
+            _ => return Err(format!("unknown delta instruction: 0x{:02x}", op)),
+        }
+    }
+
+    if output.len() != new_len {
+        return Err(format!("length mismatch after patching, expected: {}, actual: {}", new_len, output.len()));
+    }
+
+    Ok(output)
+}
+
+fn write_copy(output: &mut Vec<u8>, offset: u64, len: u64) {
+    output.push(OP_COPY);
+    output.extend_from_slice(&offset.to_le_bytes());
+    output.extend_from_slice(&len.to_le_bytes());
+}
+
+fn write_insert(output: &mut Vec<u8>, data: &[u8]) {
+    if data.is_empty() {
+        return;
+    }
+
+    output.push(OP_INSERT);
+    output.extend_from_slice(&(data.len() as u64).to_le_bytes());
+    output.extend_from_slice(data);
+}
+
+fn read_u64(buf: &[u8], pos: usize) -> Result<u64, String> {
+    match buf.get(pos..pos + 8) {
+        Some(bytes) => Ok(u64::from_le_bytes(bytes.try_into().unwrap())),
+        None => Err("unexpected end of delta patch".to_owned()),
+    }
+}
+
+/// 可以滚动计算的弱校验值（adler32的变种）
+struct RollingChecksum {
+    a: u32,
+    b: u32,
+}
+
+impl RollingChecksum {
+    fn new(window: &[u8]) -> Self {
+        let mut a = 0u32;
+        let mut b = 0u32;
+
+        for (i, byte) in window.iter().enumerate() {
+            a = a.wrapping_add(*byte as u32);
+            b = b.wrapping_add(((window.len() - i) as u32).wrapping_mul(*byte as u32));
+        }
+
+        Self { a, b }
+    }
+
+    /// 将窗口向后滑动一个字节，`out`是移出窗口的字节，`in`是移入窗口的字节
+    fn roll(&mut self, out: u8, r#in: u8) {
+        self.a = self.a.wrapping_sub(out as u32).wrapping_add(r#in as u32);
+        self.b = self.b.wrapping_sub((BLOCK_SIZE as u32).wrapping_mul(out as u32)).wrapping_add(self.a);
+    }
+
+    fn value(&self) -> u32 {
+        (self.a & 0xffff) | (self.b << 16)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::delta::apply_delta;
+    use crate::core::delta::create_delta;
+
+    fn pseudo_random(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+
+        (0..len).map(|_| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 56) as u8
+        }).collect()
+    }
+
+    #[test]
+    fn test_delta_roundtrip() {
+        let old = pseudo_random(300 * 1024, 1);
+
+        let mut new = old.clone();
+        new[1000..1100].copy_from_slice(&pseudo_random(100, 2));
+        new.splice(150_000..150_000, pseudo_random(5000, 3));
+        new.drain(250_000..260_000);
+        new.extend(pseudo_random(777, 4));
+
+        let delta = create_delta(&old, &new);
+
+        assert!(delta.len() < new.len() / 10);
+        assert_eq!(apply_delta(&old, &delta).unwrap(), new);
+    }
+
+    #[test]
+    fn test_delta_small_and_empty() {
+        let old = b"hello world".to_vec();
+
+        for new in [Vec::new(), b"hello there".to_vec(), pseudo_random(10000, 5)] {
+            let delta = create_delta(&old, &new);
+
+            assert_eq!(apply_delta(&old, &delta).unwrap(), new);
+        }
+
+        assert!(apply_delta(&old, b"garbage").is_err());
+    }
+}
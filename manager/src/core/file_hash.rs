@@ -1,23 +1,107 @@
 //! 计算文件哈希相关操作
+//!
+//! 文件哈希支持多种算法，为了让新旧版本的哈希值可以共存，除了最早的crc组合算法以外，
+//! 其它算法计算出来的哈希值都会带上算法名作为前缀，比如`sha256:9f86d08...`，`blake3:af1349b...`
+//!
+//! 这样在切换算法后的过渡期内，对比文件时也能知道旧哈希值是用什么算法算出来的
 
 use std::io::Read;
+use std::path::Path;
 
 use crc::Crc;
 use crc::CRC_16_IBM_SDLC;
 use crc::CRC_64_XZ;
+use serde::Deserialize;
+use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
 use tokio::io::AsyncRead;
 use tokio::io::AsyncReadExt;
 
+use crate::error::IoContext;
+use crate::error::ManagerError;
+
+static CRC64: Crc<u64> = Crc::<u64>::new(&CRC_64_XZ);
+static CRC16: Crc<u16> = Crc::<u16>::new(&CRC_16_IBM_SDLC);
+
+/// 代表文件哈希的算法
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum HashAlgorithm {
+    /// crc64和crc16的组合，最早期的算法，哈希值没有前缀
+    #[default]
+    CrcCombo,
+
+    /// SHA-256算法，哈希值以`sha256:`开头
+    Sha256,
+
+    /// BLAKE3算法，哈希值以`blake3:`开头
+    Blake3,
+}
+
+impl HashAlgorithm {
+    /// 根据哈希值的前缀识别出是用什么算法计算的
+    pub fn detect(hash: &str) -> Self {
+        if hash.starts_with(HashAlgorithm::Sha256.prefix()) {
+            HashAlgorithm::Sha256
+        } else if hash.starts_with(HashAlgorithm::Blake3.prefix()) {
+            HashAlgorithm::Blake3
+        } else {
+            HashAlgorithm::CrcCombo
+        }
+    }
+
+    /// 这个算法计算出来的哈希值的前缀
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            HashAlgorithm::CrcCombo => "",
+            HashAlgorithm::Sha256 => "sha256:",
+            HashAlgorithm::Blake3 => "blake3:",
+        }
+    }
+}
+
+/// 代表一个正在进行中的哈希计算
+enum Hasher {
+    CrcCombo(crc::Digest<'static, u64>, crc::Digest<'static, u16>),
+    Sha256(Sha256),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::CrcCombo => Hasher::CrcCombo(CRC64.digest(), CRC16.digest()),
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            HashAlgorithm::Blake3 => Hasher::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::CrcCombo(crc64, crc16) => {
+                crc64.update(data);
+                crc16.update(data);
+            },
+            Hasher::Sha256(sha256) => sha256.update(data),
+            Hasher::Blake3(blake3) => { blake3.update(data); },
+        }
+    }
+
+    fn finalize(self) -> String {
+        match self {
+            Hasher::CrcCombo(crc64, crc16) => format!("{:016x}_{:04x}", crc64.finalize(), crc16.finalize()),
+            Hasher::Sha256(sha256) => format!("{}{}", HashAlgorithm::Sha256.prefix(), base16ct::lower::encode_string(&sha256.finalize())),
+            Hasher::Blake3(blake3) => format!("{}{}", HashAlgorithm::Blake3.prefix(), blake3.finalize().to_hex()),
+        }
+    }
+}
+
 /// 计算文件哈希值
-pub fn calculate_hash(read: &mut impl Read) -> String {
-    // 所有计算文件哈希值时都会调用此函数，可以在此函数中替换任意哈希算法
-    
-    let crc64 = Crc::<u64>::new(&CRC_64_XZ);
-    let mut crc64 = crc64.digest();
-    
-    let crc16 = Crc::<u16>::new(&CRC_16_IBM_SDLC);
-    let mut crc16 = crc16.digest();
-    
+pub fn calculate_hash(read: &mut impl Read, algorithm: HashAlgorithm) -> String {
+    // 所有计算文件哈希值时都会调用此函数
+    let mut hasher = Hasher::new(algorithm);
+
     let mut buffer = [0u8; 16 * 1024];
 
     loop {
@@ -27,23 +111,17 @@ pub fn calculate_hash(read: &mut impl Read) -> String {
             break;
         }
 
-        crc64.update(&buffer[0..count]);
-        crc16.update(&buffer[0..count]);
+        hasher.update(&buffer[0..count]);
     }
 
-    format!("{:016x}_{:04x}", &crc64.finalize(), crc16.finalize())
+    hasher.finalize()
 }
 
 /// 计算文件哈希值
-pub async fn calculate_hash_async(read: &mut (impl AsyncRead + Unpin)) -> String {
-    // 所有计算文件哈希值时都会调用此函数，可以在此函数中替换任意哈希算法
-    
-    let crc64 = Crc::<u64>::new(&CRC_64_XZ);
-    let mut crc64 = crc64.digest();
-    
-    let crc16 = Crc::<u16>::new(&CRC_16_IBM_SDLC);
-    let mut crc16 = crc16.digest();
-    
+pub async fn calculate_hash_async(read: &mut (impl AsyncRead + Unpin), algorithm: HashAlgorithm) -> String {
+    // 所有计算文件哈希值时都会调用此函数
+    let mut hasher = Hasher::new(algorithm);
+
     let mut buffer = [0u8; 16 * 1024];
 
     tokio::pin!(read);
@@ -55,9 +133,18 @@ pub async fn calculate_hash_async(read: &mut (impl AsyncRead + Unpin)) -> String
             break;
         }
 
-        crc64.update(&buffer[0..count]);
-        crc16.update(&buffer[0..count]);
+        hasher.update(&buffer[0..count]);
     }
 
-    format!("{:016x}_{:04x}", &crc64.finalize(), crc16.finalize())
-}
\ No newline at end of file
+    hasher.finalize()
+}
+
+/// 计算整个更新包文件的校验值（SHA-256），用于客户端和镜像站检查更新包是否完整
+pub fn calculate_archive_hash(archive: impl AsRef<Path>) -> Result<String, ManagerError> {
+    let mut open = std::fs::File::open(&archive).with_path(&archive)?;
+    let mut hasher = Sha256::new();
+
+    std::io::copy(&mut open, &mut hasher).with_path(&archive)?;
+
+    Ok(base16ct::lower::encode_string(&hasher.finalize()))
+}
@@ -18,3 +18,7 @@ pub mod archive_tester;
 pub mod rule_filter;
 pub mod data;
 pub mod file_hash;
+pub mod delta;
+pub mod packed_file;
+pub mod compression;
+pub mod signing;
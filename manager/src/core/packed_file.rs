@@ -0,0 +1,51 @@
+//! 读取更新包中的文件数据
+//! 
+//! 大部分文件在更新包中存的都是完整数据，可以直接读取（压缩过的需要先解压）。但是以差异补丁形式存储的文件，
+//! 需要先还原出旧文件，再把补丁应用上去，才能得到真正的文件数据。这里负责处理这些细节
+
+use std::io::Read;
+use std::path::PathBuf;
+
+use crate::core::delta::apply_delta;
+use crate::core::tar_reader::TarReader;
+use crate::diff::history_file::FilePackedLoc;
+use crate::error::ManagerError;
+
+/// 打开一个位于更新包中的文件，返回的是解压和还原后的完整文件数据
+/// 
+/// `archive_of`负责根据版本号找到对应的更新包文件路径
+pub fn open_packed_file(loc: &FilePackedLoc, archive_of: &impl Fn(&str) -> Result<PathBuf, ManagerError>) -> Result<Box<dyn Read>, ManagerError> {
+    match &loc.base {
+        // 完整数据直接从更新包里读
+        None => {
+            let reader = TarReader::new(archive_of(&loc.version)?)?;
+
+            loc.compression.decompress(reader.into_file(loc.offset, loc.length)?)
+                .map_err(|e| ManagerError::Corrupted(format!("{}: {:?}", loc.version, e)))
+        },
+
+        // 差异补丁需要先还原
+        Some(_) => Ok(Box::new(std::io::Cursor::new(read_packed_file(loc, archive_of)?))),
+    }
+}
+
+/// 读取一个位于更新包中的文件的完整数据（已经解压和还原过）
+/// 
+/// `archive_of`负责根据版本号找到对应的更新包文件路径
+pub fn read_packed_file(loc: &FilePackedLoc, archive_of: &impl Fn(&str) -> Result<PathBuf, ManagerError>) -> Result<Vec<u8>, ManagerError> {
+    let mut raw = Vec::<u8>::with_capacity(loc.length as usize);
+    let mut reader = TarReader::new(archive_of(&loc.version)?)?;
+
+    loc.compression.decompress(reader.open_file(loc.offset, loc.length)?)
+        .and_then(|mut read| read.read_to_end(&mut raw))
+        .map_err(|e| ManagerError::Corrupted(format!("{}: {:?}", loc.version, e)))?;
+
+    match &loc.base {
+        None => Ok(raw),
+        Some(base) => {
+            let old = read_packed_file(base, archive_of)?;
+
+            apply_delta(&old, &raw).map_err(|e| ManagerError::Corrupted(format!("{}: {}", loc.version, e)))
+        },
+    }
+}
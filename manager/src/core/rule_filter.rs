@@ -1,58 +1,362 @@
 //! 规则过滤
+//!
+//! 用来决定工作空间里的哪些文件要被排除（排除：管理端会当这个文件不存在一般）。支持两种规则：
+//!
+//! 1. 正则表达式：来自配置文件里的`exclude-rules`，匹配路径中的任意一部分即可
+//! 2. gitignore格式的Glob表达式：来自配置文件里的`ignore-rules`，以及工作空间里任意目录下的`.mcpatchignore`文件
+//!
+//! Glob表达式的写法和gitignore一样：
+//!
+//! + `*`匹配除了`/`以外的任意字符，`?`匹配单个字符，`[abc]`匹配其中一个字符
+//! + `**/`匹配任意层目录，末尾的`/**`匹配目录里的所有内容
+//! + 以`/`结尾的规则只匹配目录
+//! + 开头或者中间带有`/`的规则相对于规则所在的目录进行匹配，否则可以匹配任意一层的文件名
+//! + 以`!`开头的规则是取反规则，会把之前排除掉的文件重新包含进来。但如果文件所在的目录已经被排除了，就无法再包含回来
+//! + 以`#`开头的行是注释，需要匹配`#`或者`!`开头的文件名时，可以用`\`进行转义
+//!
+//! 所有规则按顺序进行匹配，最后一条匹配上的规则说了算。顺序为：`exclude-rules`，`ignore-rules`，
+//! 然后是各个目录下的`.mcpatchignore`文件，越深的目录越靠后
+
+use std::path::Path;
 
 use regex::Regex;
 
+use crate::config::core_config::CoreConfig;
+use crate::error::IoContext;
+use crate::error::ManagerError;
+
+/// 每个目录下的忽略规则文件的文件名
+pub const IGNORE_FILENAME: &str = ".mcpatchignore";
+
 /// 代表单个过滤规则
+#[derive(Clone)]
 pub struct Rule {
     /// 原始字符串，主要是调试输出用途
     pub raw: String,
-    
+
+    /// 规则来自哪里，比如配置文件或者某个`.mcpatchignore`文件，主要是调试输出用途
+    pub source: String,
+
     /// 编译后的规则对象
-    pub pattern: Regex, 
+    pub pattern: Regex,
+
+    /// 是否是取反规则，取反规则匹配时会把文件重新包含进来
+    pub negated: bool,
+
+    /// 是否只匹配目录
+    pub dir_only: bool,
 }
 
 /// 代表一组过滤规则
+#[derive(Clone)]
 pub struct RuleFilter {
     pub filters: Vec<Rule>
 }
 
 impl RuleFilter {
-    /// 创建一个空的规则过滤器，空的规则过滤器会通过任何测试的文件
+    /// 创建一个空的规则过滤器，空的规则过滤器不会排除任何文件
     pub fn new() -> Self {
-        Self::from_rules([""; 0].iter())
+        Self { filters: Vec::new() }
     }
 
-    /// 从一个字符串引用迭代器创建规则过滤器
-    pub fn from_rules<'a>(rules: impl Iterator<Item = impl AsRef<str>>) -> RuleFilter {
-        let mut regexes_compiled = Vec::<Rule>::new();
+    /// 加载工作空间的所有排除规则，包括配置文件里的规则和工作空间里所有的`.mcpatchignore`文件
+    pub fn load(config: &CoreConfig, workspace_dir: &Path) -> Result<Self, ManagerError> {
+        let mut filter = Self::new();
+
+        for rule in &config.exclude_rules {
+            filter.add_regex(rule, "配置文件exclude-rules")
+                .map_err(|e| ManagerError::task(format!("排除规则不是合法的正则表达式: {}，{}", rule, e)))?;
+        }
+
+        for rule in &config.ignore_rules {
+            filter.add_glob(rule, "", "配置文件ignore-rules")
+                .map_err(|e| ManagerError::task(format!("忽略规则不正确: {}，{}", rule, e)))?;
+        }
+
+        // 越浅的目录越先加载，这样深层目录里的规则可以覆盖浅层目录的规则
+        let mut ignore_files = Vec::<String>::new();
 
-        for pattern in rules {
-            let raw_pattern = pattern.as_ref();
-            let pattern = raw_pattern;
-            regexes_compiled.push(Rule {
-                raw: raw_pattern.to_owned(),
-                pattern: Regex::new(&pattern).unwrap(), 
-            });
+        if workspace_dir.exists() {
+            find_ignore_files(workspace_dir, "", &mut ignore_files)?;
         }
 
-        RuleFilter { filters: regexes_compiled }
+        ignore_files.sort_by_key(|e| (e.matches('/').count(), e.to_owned()));
+
+        for relative in ignore_files {
+            let base = relative.rsplit_once('/').map(|e| e.0).unwrap_or("");
+            let file = workspace_dir.join(&relative);
+            let content = std::fs::read_to_string(&file).with_path(&file)?;
+
+            for (number, line) in content.lines().enumerate() {
+                let source = format!("{}:{}", relative, number + 1);
+
+                filter.add_glob(line, base, &source)
+                    .map_err(|e| ManagerError::task(format!("忽略规则不正确: {}（{}），{}", line, source, e)))?;
+            }
+        }
+
+        // 忽略规则文件本身只在管理端上使用，不需要发给客户端
+        filter.add_glob(IGNORE_FILENAME, "", "内置规则").unwrap();
+
+        Ok(filter)
     }
 
-    /// 测试一段字符串能否通过任何一个规则测试，如果不能通过或者规则列表为空，返回`default`。
-    pub fn test_any(&self, text: &str, default: bool) -> bool {
-        if self.filters.is_empty() {
-            return default;
+    /// 从一组gitignore格式的规则创建规则过滤器，`source`是这些规则的来源
+    pub fn from_globs(globs: &[String], source: &str) -> Result<Self, ManagerError> {
+        let mut filter = Self::new();
+
+        for glob in globs {
+            filter.add_glob(glob, "", source)
+                .map_err(|e| ManagerError::task(format!("规则不正确: {}（来自{}），{}", glob, source, e)))?;
         }
 
-        self.filters.iter().any(|filter| filter.pattern.is_match(text))
+        Ok(filter)
     }
-    
-    /// 测试一段字符串能否通过所有的规则测试，如果不能通过或者规则列表为空，返回`default`。
-    pub fn test_all(&self, text: &str, default: bool) -> bool {
-        if self.filters.is_empty() {
-            return default;
+
+    /// 添加一条正则表达式规则
+    pub fn add_regex(&mut self, rule: &str, source: &str) -> Result<(), regex::Error> {
+        self.filters.push(Rule {
+            raw: rule.to_owned(),
+            source: source.to_owned(),
+            pattern: Regex::new(rule)?,
+            negated: false,
+            dir_only: false,
+        });
+
+        Ok(())
+    }
+
+    /// 添加一条gitignore格式的规则，`base`是规则所在的目录（相对于工作空间目录），空行和注释会被跳过
+    pub fn add_glob(&mut self, line: &str, base: &str, source: &str) -> Result<(), regex::Error> {
+        let mut glob = line.trim_end();
+
+        if glob.is_empty() || glob.starts_with('#') {
+            return Ok(());
+        }
+
+        let negated = glob.starts_with('!');
+
+        if negated {
+            glob = &glob[1..];
         }
 
-        self.filters.iter().all(|filter| filter.pattern.is_match(text))
+        let dir_only = glob.ends_with('/');
+        glob = glob.trim_end_matches('/');
+
+        // 开头或者中间带有/的规则相对于所在目录进行匹配
+        let anchored = glob.contains('/');
+        glob = glob.trim_start_matches('/');
+
+        if glob.is_empty() {
+            return Ok(());
+        }
+
+        let prefix = match base.is_empty() {
+            true => "".to_owned(),
+            false => format!("{}/", regex::escape(base)),
+        };
+
+        let pattern = match anchored {
+            true => format!("^{}{}$", prefix, glob_to_regex(glob)),
+            false => format!("^{}(?:.*/)?{}$", prefix, glob_to_regex(glob)),
+        };
+
+        self.filters.push(Rule {
+            raw: line.trim_end().to_owned(),
+            source: source.to_owned(),
+            pattern: Regex::new(&pattern)?,
+            negated,
+            dir_only,
+        });
+
+        Ok(())
     }
-}
\ No newline at end of file
+
+    /// 找出决定一个路径是否被排除的规则，也就是最后一条匹配上的规则，没有任何规则匹配时返回None
+    ///
+    /// 这里只检查路径本身，不检查路径所在的目录
+    pub fn find_rule(&self, path: &str, is_dir: bool) -> Option<&Rule> {
+        self.filters.iter()
+            .rev()
+            .find(|rule| (is_dir || !rule.dir_only) && rule.pattern.is_match(path))
+    }
+
+    /// 一个路径是否被排除了
+    ///
+    /// 这里只检查路径本身，不检查路径所在的目录
+    pub fn is_excluded(&self, path: &str, is_dir: bool) -> bool {
+        self.find_rule(path, is_dir).is_some_and(|e| !e.negated)
+    }
+
+    /// 一个路径或者它所在的目录有没有被某条规则匹配上（不算取反规则）
+    pub fn matches(&self, path: &str, is_dir: bool) -> bool {
+        self.explain(path, is_dir).is_some()
+    }
+
+    /// 找出让一个路径被排除的规则，会先逐级检查路径所在的目录，没有被排除时返回None
+    pub fn explain<'a>(&'a self, path: &'a str, is_dir: bool) -> Option<(&'a str, &'a Rule)> {
+        let mut end = 0;
+
+        // 所在的目录被排除时，里面的所有文件都会被排除
+        while let Some(pos) = path[end..].find('/') {
+            let dir = &path[..end + pos];
+
+            if let Some(rule) = self.find_rule(dir, true).filter(|e| !e.negated) {
+                return Some((dir, rule));
+            }
+
+            end += pos + 1;
+        }
+
+        self.find_rule(path, is_dir).filter(|e| !e.negated).map(|e| (path, e))
+    }
+}
+
+/// 递归查找所有的`.mcpatchignore`文件，不会跟随符号链接
+fn find_ignore_files(dir: &Path, relative: &str, out: &mut Vec<String>) -> Result<(), ManagerError> {
+    for entry in std::fs::read_dir(dir).with_path(dir)? {
+        let entry = entry.with_path(dir)?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let path = match relative.is_empty() {
+            true => name.to_owned(),
+            false => format!("{}/{}", relative, name),
+        };
+
+        let file_type = entry.file_type().with_path(entry.path())?;
+
+        if file_type.is_dir() {
+            find_ignore_files(&entry.path(), &path, out)?;
+        } else if file_type.is_file() && name == IGNORE_FILENAME {
+            out.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// 将Glob表达式转换成等价的正则表达式（不带首尾的锚点）
+fn glob_to_regex(glob: &str) -> String {
+    let chars = glob.chars().collect::<Vec<_>>();
+    let mut output = String::with_capacity(glob.len() * 2);
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                let at_start = i == 0 || chars[i - 1] == '/';
+
+                match chars.get(i + 2) {
+                    // 开头或者中间的**/可以匹配任意层目录（包括0层）
+                    Some('/') if at_start => {
+                        output += "(?:.*/)?";
+                        i += 3;
+                        continue;
+                    },
+                    // 末尾的/**匹配目录里的所有内容
+                    None if at_start => output += ".*",
+                    // 其它位置的**和*一样
+                    _ => output += "[^/]*",
+                }
+
+                i += 2;
+                continue;
+            },
+            '*' => output += "[^/]*",
+            '?' => output += "[^/]",
+            '[' => match chars[i + 1..].iter().position(|e| *e == ']') {
+                Some(len) if len > 0 => {
+                    let class = &chars[i + 1..i + 1 + len];
+
+                    output += "[";
+
+                    for (index, c) in class.iter().enumerate() {
+                        match c {
+                            '!' | '^' if index == 0 => output += "^",
+                            '\\' | '[' | '&' | '~' | '^' => { output.push('\\'); output.push(*c); },
+                            _ => output.push(*c),
+                        }
+                    }
+
+                    output += "]";
+                    i += len + 2;
+                    continue;
+                },
+                _ => output += "\\[",
+            },
+            '\\' if i + 1 < chars.len() => {
+                output += &regex::escape(&chars[i + 1].to_string());
+                i += 2;
+                continue;
+            },
+            c => output += &regex::escape(&c.to_string()),
+        }
+
+        i += 1;
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::rule_filter::RuleFilter;
+
+    fn filter(rules: &[(&str, &str)]) -> RuleFilter {
+        let mut filter = RuleFilter::new();
+
+        for (base, rule) in rules {
+            filter.add_glob(rule, base, "test").unwrap();
+        }
+
+        filter
+    }
+
+    #[test]
+    fn test_glob_rules() {
+        let f = filter(&[("", "*.log"), ("", "/build"), ("", "cache/"), ("", "docs/**/*.md"), ("", "a?c")]);
+
+        assert!(f.is_excluded("latest.log", false));
+        assert!(f.is_excluded(".minecraft/logs/latest.log", false));
+        assert!(!f.is_excluded("latest.log.txt", false));
+
+        assert!(f.is_excluded("build", true));
+        assert!(!f.is_excluded("sub/build", true));
+
+        assert!(f.is_excluded("cache", true));
+        assert!(f.is_excluded("sub/cache", true));
+        assert!(!f.is_excluded("cache", false));
+
+        assert!(f.is_excluded("docs/a.md", false));
+        assert!(f.is_excluded("docs/x/y/a.md", false));
+        assert!(!f.is_excluded("other/docs/a.md", false));
+
+        assert!(f.is_excluded("abc", false));
+        assert!(!f.is_excluded("a/c", false));
+    }
+
+    #[test]
+    fn test_negation_and_order() {
+        let f = filter(&[("", "*.jar"), ("", "!keep.jar"), ("mods", "keep.jar")]);
+
+        assert!(f.is_excluded("a.jar", false));
+        assert!(!f.is_excluded("keep.jar", false));
+
+        // 更深的目录里的规则排在后面，可以覆盖前面的规则
+        assert!(f.is_excluded("mods/keep.jar", false));
+        assert!(!f.is_excluded("other/keep.jar", false));
+    }
+
+    #[test]
+    fn test_explain_checks_parent_directories() {
+        let f = filter(&[("", "logs/"), ("", "!logs/keep.txt"), ("", "\\#notes"), ("", "# comment"), ("", "[!a]b")]);
+
+        let (path, rule) = f.explain("logs/keep.txt", false).unwrap();
+        assert_eq!(path, "logs");
+        assert_eq!(rule.raw, "logs/");
+
+        assert!(f.explain("#notes", false).is_some());
+        assert!(f.explain("comment", false).is_none());
+        assert!(f.explain("xb", false).is_some());
+        assert!(f.explain("ab", false).is_none());
+    }
+}
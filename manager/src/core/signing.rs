@@ -0,0 +1,113 @@
+//! 索引文件和元数据的签名
+//!
+//! 任何能写入s3或者webdav的人都可以篡改上面的文件，所以管理端可以使用Ed25519私钥对索引文件和元数据进行签名，
+//! 客户端再用公开的公钥进行验证，验证不通过就拒绝更新
+//!
+//! 1. 私钥保存在工作目录下的`signing.key`里，不能上传或者泄露出去
+//! 2. 公钥保存在public目录下的`signing.pub`里，会随着更新包一起发布出去
+//! 3. 每个索引文件旁边都有一个对应的`.sig`文件，比如`index.json.sig`，是对整个索引文件内容的签名
+//! 4. 索引文件里每个版本的`signature`字段，是对这个版本所在的元数据组（tar包里的metadata.txt）原始内容的签名
+//!
+//! 密钥和签名都使用base64编码存储
+
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use base64ct::Base64;
+use base64ct::Encoding;
+use ed25519_dalek::Signature;
+use ed25519_dalek::SigningKey;
+use ed25519_dalek::VerifyingKey;
+use ed25519_dalek::Signer as _;
+
+use crate::app_path::AppPath;
+use crate::error::IoContext;
+use crate::error::ManagerError;
+
+/// 代表一个签名私钥
+pub struct Signer {
+    key: SigningKey,
+}
+
+impl Signer {
+    /// 随机生成一个新的私钥
+    pub fn generate() -> Self {
+        Self { key: SigningKey::generate(&mut rand::rngs::OsRng) }
+    }
+
+    /// 从工作目录加载私钥，如果还没有生成过私钥就返回None
+    pub fn load(apppath: &AppPath) -> Result<Option<Self>, ManagerError> {
+        let content = match std::fs::read_to_string(&apppath.signing_key_file) {
+            Ok(ok) => ok,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err).with_path(&apppath.signing_key_file),
+        };
+
+        let seed: [u8; 32] = Base64::decode_vec(content.trim()).ok()
+            .and_then(|e| e.try_into().ok())
+            .ok_or_else(|| ManagerError::task(format!("签名私钥格式不正确: {:?}", apppath.signing_key_file)))?;
+
+        Ok(Some(Self { key: SigningKey::from_bytes(&seed) }))
+    }
+
+    /// 将私钥保存到工作目录，同时将公钥发布到公共目录
+    pub fn save(&self, apppath: &AppPath) -> Result<(), ManagerError> {
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+
+        // 私钥只允许当前用户读写
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        let mut file = options.open(&apppath.signing_key_file).with_path(&apppath.signing_key_file)?;
+
+        // 使用--force覆盖旧私钥时，文件已经存在，创建时指定的权限不会生效
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            file.set_permissions(std::fs::Permissions::from_mode(0o600)).with_path(&apppath.signing_key_file)?;
+        }
+
+        file.write_all(Base64::encode_string(self.key.as_bytes()).as_bytes()).with_path(&apppath.signing_key_file)?;
+        std::fs::write(&apppath.public_key_file, self.public_key()).with_path(&apppath.public_key_file)?;
+
+        Ok(())
+    }
+
+    /// 获取base64编码的公钥
+    pub fn public_key(&self) -> String {
+        Base64::encode_string(self.key.verifying_key().as_bytes())
+    }
+
+    /// 对`data`进行签名，返回base64编码的签名
+    pub fn sign(&self, data: &[u8]) -> String {
+        Base64::encode_string(&self.key.sign(data).to_bytes())
+    }
+}
+
+/// 使用base64编码的公钥`public_key`验证`data`的签名
+pub fn verify_signature(public_key: &str, data: &[u8], signature: &str) -> Result<(), String> {
+    let public_key: [u8; 32] = Base64::decode_vec(public_key)
+        .map_err(|e| format!("invalid public key: {}", e))?
+        .try_into()
+        .map_err(|_| "invalid public key length".to_owned())?;
+
+    let signature: [u8; 64] = Base64::decode_vec(signature)
+        .map_err(|e| format!("invalid signature: {}", e))?
+        .try_into()
+        .map_err(|_| "invalid signature length".to_owned())?;
+
+    let key = VerifyingKey::from_bytes(&public_key).map_err(|e| format!("invalid public key: {}", e))?;
+
+    key.verify_strict(data, &Signature::from_bytes(&signature)).map_err(|e| format!("signature mismatch: {}", e))
+}
+
+/// 获取一个文件的分离签名文件的路径，也就是在文件名后面加上`.sig`
+pub fn signature_file_of(file: &Path) -> PathBuf {
+    let mut filename = file.file_name().unwrap().to_owned();
+    filename.push(".sig");
+
+    file.with_file_name(filename)
+}
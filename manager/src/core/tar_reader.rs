@@ -5,36 +5,88 @@ use std::io::Seek;
 use std::io::SeekFrom;
 use std::path::Path;
 
+use crate::core::data::parse_error::ParseError;
 use crate::core::data::version_meta_group::VersionMetaGroup;
+use crate::error::IoContext;
+use crate::error::ManagerError;
 use crate::utility::partial_read::PartialRead;
 
+/// 代表更新包里的一个tar条目
+pub struct TarEntry {
+    /// 条目的路径
+    pub path: String,
+
+    /// 条目数据在更新包中的偏移值（不包括tar头）
+    pub offset: u64,
+
+    /// 条目数据的长度
+    pub len: u64,
+}
+
 /// 代表一个更新包读取器，用于读取tar格式的更新包里面的数据
 pub struct TarReader {
-    open: std::fs::File
+    open: std::fs::File,
+
+    /// 更新包文件路径，出错时用来提示是哪个文件
+    file: String,
 }
 
 impl TarReader {
     /// 创建一个TarReader，从`file`读取数据
-    pub fn new(file: impl AsRef<Path>) -> Self {
+    pub fn new(file: impl AsRef<Path>) -> Result<Self, ManagerError> {
         // println!("open {:?}", file.as_ref());
-        Self { open: std::fs::File::open(file).unwrap() }
+        let open = std::fs::File::open(&file).with_path(&file)?;
+
+        Ok(Self { open, file: file.as_ref().display().to_string() })
     }
 
     /// 读取更新包中的元数据，需要提供元数据的`offset`和`len`以便定位
-    pub fn read_metadata_group(&mut self, offset: u64, len: u64) -> VersionMetaGroup {
+    pub fn read_metadata_group(&mut self, offset: u64, len: u64) -> Result<VersionMetaGroup, ManagerError> {
+        let buf = self.read_metadata_raw(offset, len)?;
+        let text = std::str::from_utf8(&buf).map_err(|e| ParseError::InvalidJson(e.to_string()))?;
+
+        Ok(VersionMetaGroup::parse(text)?)
+    }
+
+    /// 读取更新包中未经解析的元数据原始内容，用于验证签名
+    pub fn read_metadata_raw(&mut self, offset: u64, len: u64) -> Result<Vec<u8>, ManagerError> {
         let mut buf = Vec::<u8>::new();
         buf.resize(len as usize, 0);
 
-        self.open.seek(SeekFrom::Start(offset)).unwrap();
-        self.open.read_exact(&mut buf).unwrap();
+        self.open.seek(SeekFrom::Start(offset)).context(&self.file)?;
+        self.open.read_exact(&mut buf).context(&self.file)?;
 
-        VersionMetaGroup::parse(std::str::from_utf8(&buf).unwrap())
+        Ok(buf)
     }
 
     /// 读取更新包中的一个文件数据，需要提供文件的`offset`和`len`以便定位
-    pub fn open_file(&mut self, offset: u64, len: u64) -> PartialRead<&mut std::fs::File> {
-        self.open.seek(SeekFrom::Start(offset)).unwrap();
+    pub fn open_file(&mut self, offset: u64, len: u64) -> Result<PartialRead<&mut std::fs::File>, ManagerError> {
+        self.open.seek(SeekFrom::Start(offset)).context(&self.file)?;
+
+        Ok(PartialRead::new(&mut self.open, len))
+    }
+
+    /// 和[`TarReader::open_file`]一样，但是会消耗掉TarReader本身，方便在读取时不持有TarReader的借用
+    pub fn into_file(mut self, offset: u64, len: u64) -> Result<PartialRead<std::fs::File>, ManagerError> {
+        self.open.seek(SeekFrom::Start(offset)).context(&self.file)?;
+
+        Ok(PartialRead::new(self.open, len))
+    }
+
+    /// 读取更新包里所有的tar头，列出每个条目的路径和数据位置
+    pub fn entries(&mut self) -> Result<Vec<TarEntry>, ManagerError> {
+        self.open.seek(SeekFrom::Start(0)).context(&self.file)?;
+
+        let mut archive = tar::Archive::new(&mut self.open);
+        let mut entries = Vec::new();
+
+        for entry in archive.entries().context(&self.file)? {
+            let entry = entry.context(&self.file)?;
+            let path = entry.path().context(&self.file)?.to_string_lossy().into_owned();
+
+            entries.push(TarEntry { path, offset: entry.raw_file_position(), len: entry.size() });
+        }
 
-        PartialRead::new(&mut self.open, len)
+        Ok(entries)
     }
 }
@@ -6,43 +6,49 @@ use std::path::Path;
 
 use crate::core::data::version_meta::FileChange;
 use crate::core::data::version_meta_group::VersionMetaGroup;
+use crate::core::signing::Signer;
+use crate::error::IoContext;
+use crate::error::ManagerError;
 use crate::utility::counted_write::CountedWrite;
 use crate::utility::partial_read::PartialRead;
 
 pub struct MetadataLocation {
     pub offset: u64,
     pub length: u64,
+
+    /// 元数据组的签名，仅在提供了私钥时才会有
+    pub signature: Option<String>,
 }
 
 /// 代表一个更新包写入器，用于生成tar格式的更新包
+/// 
+/// 写入过程中出错时，已经写了一半的文件会留在磁盘上，由调用者负责清理
 pub struct TarWriter {
     builder: tar::Builder<CountedWrite<std::fs::File>>,
     addresses: HashMap<String, u64>,
-    finished: bool,
+    file: String,
 }
 
 impl TarWriter {
     /// 创建一个TarWriter，并将数据写到`file`文件中
-    pub fn new(file: impl AsRef<Path>) -> Self {
-        let open = std::fs::File::options().create(true).truncate(true).write(true).open(file).unwrap();
+    pub fn new(file: impl AsRef<Path>) -> Result<Self, ManagerError> {
+        let open = std::fs::File::options().create(true).truncate(true).write(true).open(&file).with_path(&file)?;
 
-        Self {
+        Ok(Self {
             builder: tar::Builder::new(CountedWrite::new(open)), 
             addresses: HashMap::new(),
-            finished: false,
-        }
+            file: file.as_ref().display().to_string(),
+        })
     }
 
     /// 往更新包里添加一个文件，除了数据和长度以外，还需要额外提供文件路径和所属版本号
-    pub fn add_file(&mut self, mut data: impl Read, len: u64, path: &str, version: &str) {
-        assert!(!self.finished, "TarWriter has already closed");
-
+    pub fn add_file(&mut self, mut data: impl Read, len: u64, path: &str, version: &str) -> Result<(), ManagerError> {
         // 写入更新包中
         let mut header = tar::Header::new_gnu();
         header.set_size(len);
 
         let partial_read = PartialRead::new(&mut data, len);
-        self.builder.append_data(&mut header, path, partial_read).unwrap();
+        self.builder.append_data(&mut header, path, partial_read).context(format_args!("{} ({})", self.file, path))?;
 
         let mut padding = 512 - (len % 512);
 
@@ -59,24 +65,29 @@ impl TarWriter {
         let tar_offset = position - len - padding;
 
         self.addresses.insert(key, tar_offset);
-    }
 
-    /// 完成更新包的创建，并返回元数据的偏移值和长度
-    pub fn finish(mut self, mut meta_group: VersionMetaGroup) -> MetadataLocation {
-        assert!(!self.finished, "TarWriter has already closed");
+        Ok(())
+    }
 
+    /// 完成更新包的创建，并返回元数据的偏移值和长度。如果提供了`signer`，还会顺便对元数据组进行签名
+    pub fn finish(mut self, mut meta_group: VersionMetaGroup, signer: Option<&Signer>) -> Result<MetadataLocation, ManagerError> {
         // 更新元数据中的偏移值
         for meta in &mut meta_group {
             for change in meta.changes.iter_mut() {
-                if let FileChange::UpdateFile { path, offset, .. } = change {
-                    // 合并文件时，中间版本里的文件数据为了节省空间，是不存储的
-                    // 也就是说即使这些元数据里有offset，len这些数据，但这些数据都是无效的
-                    // 正常情况下客户端也不会去这个数据，如果读取了那么必定是数据受损了
-                    let key = format!("{}_{}", path, &meta.label);
-                    match self.addresses.get(&key) {
-                        Some(addr) => *offset = *addr,
-                        None => (),
-                    }
+                let (path, offset) = match change {
+                    FileChange::UpdateFile { path, offset, .. } => (path, offset),
+                    FileChange::CreateIfMissing { path, offset, .. } => (path, offset),
+                    FileChange::PatchFile { path, offset, .. } => (path, offset),
+                    _ => continue,
+                };
+
+                // 合并文件时，中间版本里的文件数据为了节省空间，是不存储的
+                // 也就是说即使这些元数据里有offset，len这些数据，但这些数据都是无效的
+                // 正常情况下客户端也不会去这个数据，如果读取了那么必定是数据受损了
+                let key = format!("{}_{}", path, &meta.label);
+                match self.addresses.get(&key) {
+                    Some(addr) => *offset = *addr,
+                    None => (),
                 }
             }
         }
@@ -89,21 +100,15 @@ impl TarWriter {
         // 写入元数据
         let mut header = tar::Header::new_gnu();
         header.set_size(file_content.len() as u64);
-        self.builder.append_data(&mut header, "metadata.txt", std::io::Cursor::new(&file_content)).unwrap();
+        self.builder.append_data(&mut header, "metadata.txt", std::io::Cursor::new(&file_content)).context(&self.file)?;
 
         // 写入完毕
-        self.builder.finish().unwrap();
-        self.finished = true;
+        self.builder.finish().context(&self.file)?;
 
-        MetadataLocation {
+        Ok(MetadataLocation {
             offset: metadata_offset + 512,
             length: file_content.len() as u64,
-        }
-    }
-}
-
-impl Drop for TarWriter {
-    fn drop(&mut self) {
-        assert!(self.finished, "TarWriter has not closed yet");
+            signature: signer.map(|e| e.sign(file_content)),
+        })
     }
 }
\ No newline at end of file
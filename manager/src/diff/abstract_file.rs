@@ -17,6 +17,8 @@ use std::collections::LinkedList;
 use std::ops::Deref;
 use std::time::SystemTime;
 
+use crate::core::file_hash::HashAlgorithm;
+
 /// 从借用返回迭代器
 pub trait BorrowIntoIterator {
     type Item;
@@ -34,15 +36,26 @@ pub trait AbstractFile : Clone {
     
     /// 获取哈希值
     fn hash(&self) -> impl Deref<Target = String>;
+
+    /// 获取使用指定算法计算的哈希值，如果没法用这个算法计算，就返回None
+    fn hash_with(&self, algorithm: HashAlgorithm) -> Option<String>;
     
     /// 获取文件长度
     fn len(&self) -> u64;
 
     /// 获取文件修改时间
     fn modified(&self) -> SystemTime;
+
+    /// 获取unix文件权限（比如`0o755`），不知道文件权限时（非unix平台或者旧版本的元数据）返回None
+    fn mode(&self) -> Option<u32>;
     
     /// 是不是一个目录
     fn is_dir(&self) -> bool;
+
+    /// 如果是一个符号链接，返回链接指向的路径，否则返回None
+    /// 
+    /// 符号链接既不是目录也不是普通文件，它没有文件数据，也不会被展开
+    fn symlink_target(&self) -> Option<String>;
     
     /// 获取文件的相对路径
     fn path(&self) -> impl Deref<Target = String>;
@@ -54,6 +67,32 @@ pub trait AbstractFile : Clone {
     fn find(&self, path: &str) -> Option<Self>;
 }
 
+/// 比较两个文件哈希值的辅助函数
+/// 
+/// 如果两边的哈希值是用不同的算法计算的（比如切换哈希算法后的过渡期），
+/// 会尝试让其中一边用另一边的算法重新计算，然后再进行比较
+pub fn hash_equals_helper(a: &impl AbstractFile, b: &impl AbstractFile) -> bool {
+    let hash_a = a.hash().to_owned();
+    let hash_b = b.hash().to_owned();
+
+    let algorithm_a = HashAlgorithm::detect(&hash_a);
+    let algorithm_b = HashAlgorithm::detect(&hash_b);
+
+    if algorithm_a == algorithm_b {
+        return hash_a == hash_b;
+    }
+
+    if let Some(hash) = a.hash_with(algorithm_b) {
+        return hash == hash_b;
+    }
+
+    if let Some(hash) = b.hash_with(algorithm_a) {
+        return hash == hash_a;
+    }
+
+    false
+}
+
 /// 查找文件的辅助函数，实现了大部分查找逻辑，可以很方便地直接使用
 pub fn find_file_helper<T: AbstractFile>(parent: &T, path: &str) -> Option<T> {
     assert!(parent.is_dir());
@@ -96,6 +135,8 @@ pub fn calculate_path_helper(name: &str, parent: Option<&impl AbstractFile>) ->
 pub fn abstract_file_to_string(f: &impl AbstractFile) -> String {
     if f.is_dir() {
         format!("{} (directory: {}) {}", &f.name().deref(), f.files().iter().count(), f.path().deref())
+    } else if let Some(target) = f.symlink_target() {
+        format!("{} (symlink: {}) {}", &f.name().deref(), target, f.path().deref())
     } else {
         let dt = chrono::DateTime::<chrono::Local>::from(f.modified().to_owned());
 
@@ -1,5 +1,8 @@
 //! 目录差异对比
 
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
 use std::collections::LinkedList;
 use std::fmt::Debug;
 use std::fmt::Write;
@@ -7,7 +10,10 @@ use std::ops::Deref;
 use std::fmt::Display;
 use std::time::UNIX_EPOCH;
 
+use crate::core::compression::Compression;
 use crate::core::data::version_meta::FileChange;
+use crate::core::file_hash::HashAlgorithm;
+use crate::diff::abstract_file::hash_equals_helper;
 use crate::diff::abstract_file::AbstractFile;
 use crate::diff::abstract_file::BorrowIntoIterator;
 use crate::core::rule_filter::RuleFilter;
@@ -18,12 +24,14 @@ const OP_FULL_MODIFIED_FILE: &str   = "修改文件: ";
 const OP_FULL_MISSING_FOLDER: &str = "删除目录: ";
 const OP_FULL_MISSING_FILE: &str   = "删除文件: ";
 const OP_FULL_MOVE_FILE: &str     = "移动文件: ";
+const OP_FULL_ADDED_SYMLINK: &str = "创建链接: ";
 const OP_SHORT_ADDED_FOLDER: &str = OP_FULL_ADDED_FOLDER;
 const OP_SHORT_ADDED_FILE: &str   = OP_FULL_ADDED_FILE;
 const OP_SHORT_MODIFIED_FILE: &str   = OP_FULL_MODIFIED_FILE;
 const OP_SHORT_MISSING_FOLDER: &str = OP_FULL_MISSING_FOLDER;
 const OP_SHORT_MISSING_FILE: &str   = OP_FULL_MISSING_FILE;
 const OP_SHORT_MOVE_FILE: &str     = OP_FULL_MOVE_FILE;
+const OP_SHORT_ADDED_SYMLINK: &str = OP_FULL_ADDED_SYMLINK;
 
 /// 代表一组文件差异
 pub struct Diff<N: AbstractFile, O: AbstractFile> {
@@ -33,12 +41,14 @@ pub struct Diff<N: AbstractFile, O: AbstractFile> {
     pub missing_folders: Vec<O>,
     pub missing_files: Vec<O>,
     pub renamed_files: Vec<(O, N)>,
+    pub added_symlinks: Vec<N>,
     excluding_filter: RuleFilter,
+    protected_filter: RuleFilter,
 }
 
 impl<N: AbstractFile, O: AbstractFile> Diff<N, O> {
     /// 执行目录比较
-    pub fn diff(newer: &N, older: &O, filter_rules: Option<&Vec<String>>) -> Self {
+    pub fn diff(newer: &N, older: &O, filter: Option<&RuleFilter>) -> Self {
         let mut result = Diff {
             added_folders: Vec::new(),
             added_files: Vec::new(),
@@ -46,28 +56,78 @@ impl<N: AbstractFile, O: AbstractFile> Diff<N, O> {
             missing_folders: Vec::new(),
             missing_files: Vec::new(),
             renamed_files: Vec::new(),
-            excluding_filter: match filter_rules {
-                Some(filter_rules) => RuleFilter::from_rules(filter_rules.iter()),
-                None => RuleFilter::new(),
-            },
+            added_symlinks: Vec::new(),
+            excluding_filter: filter.cloned().unwrap_or_else(RuleFilter::new),
+            protected_filter: RuleFilter::new(),
         };
 
         result.find_added(newer, older);
         result.find_missing(newer, older);
         result.find_modified(newer, older);
-        result.detect_file_movings(newer, older);
+        result.detect_file_movings();
 
         result
     }
 
-    /// 有没有不同
+    /// 设置受保护的路径，匹配的文件和目录即使被删除了，也不会生成删除操作（比如玩家的存档目录）
+    ///
+    /// 从受保护的路径移出去的文件也会退回成新增文件，不然客户端上的文件会被移走
+    pub fn protect(&mut self, filter: &RuleFilter) {
+        self.protected_filter = filter.clone();
+
+        for i in (0..self.renamed_files.len()).rev() {
+            if self.is_protected(&self.renamed_files[i].0) {
+                let (o, n) = self.renamed_files.remove(i);
+
+                self.missing_files.push(o);
+                self.added_files.push(n);
+            }
+        }
+    }
+
+    /// 因为受保护而不会生成删除操作的文件和目录的路径
+    pub fn protected_deletions(&self) -> Vec<String> {
+        self.missing_files.iter()
+            .chain(&self.missing_folders)
+            .filter(|f| self.is_protected(*f))
+            .map(|f| f.path().to_owned())
+            .collect()
+    }
+
+    /// 有没有不同，受保护的路径的删除不算
     pub fn has_diff(&self) -> bool {
         !self.added_folders.is_empty() ||
         !self.added_files.is_empty() ||
         !self.modified_files.is_empty() ||
-        !self.missing_folders.is_empty() ||
-        !self.missing_files.is_empty() ||
-        !self.renamed_files.is_empty()
+        self.missing_folders.iter().any(|f| !self.is_protected(f)) ||
+        self.missing_files.iter().any(|f| !self.is_protected(f)) ||
+        !self.renamed_files.is_empty() ||
+        !self.added_symlinks.is_empty()
+    }
+
+    /// 将文件差异转换成Json对象，供脚本等程序读取。受保护的路径的删除不会生成删除操作，所以不列出来
+    pub fn to_json(&self) -> serde_json::Value {
+        fn paths(files: &[impl AbstractFile]) -> Vec<String> {
+            files.iter().map(|f| f.path().to_owned()).collect()
+        }
+
+        let unprotected = |files: &[O]| -> Vec<String> {
+            files.iter().filter(|f| !self.is_protected(f)).map(|f| f.path().to_owned()).collect()
+        };
+
+        serde_json::json!({
+            "added_folders": paths(&self.added_folders),
+            "added_files": paths(&self.added_files),
+            "modified_files": paths(&self.modified_files),
+            "missing_folders": unprotected(&self.missing_folders),
+            "missing_files": unprotected(&self.missing_files),
+            "renamed_files": self.renamed_files.iter()
+                .map(|(from, to)| serde_json::json!({ "from": from.path().to_owned(), "to": to.path().to_owned() }))
+                .collect::<Vec<_>>(),
+            "added_symlinks": self.added_symlinks.iter()
+                .map(|f| serde_json::json!({ "path": f.path().to_owned(), "target": f.symlink_target() }))
+                .collect::<Vec<_>>(),
+        })
     }
 
     /// 寻找新增的文件
@@ -76,7 +136,7 @@ impl<N: AbstractFile, O: AbstractFile> Diff<N, O> {
         assert!(older.is_dir());
 
         for n in newer.files().iter() {
-            if !self.is_visible(n.path().deref()) {
+            if !self.is_visible(&n) {
                 continue;
             }
 
@@ -92,8 +152,11 @@ impl<N: AbstractFile, O: AbstractFile> Diff<N, O> {
                         (true, false) => self.mark_as_added(&n),
                         (false, true) => self.mark_as_added(&n),
 
-                        // 两边都是文件，跳过，会由文件修改检查函数来处理此情况
-                        (false, false) => (),
+                        // 两边都是文件，如果其中有符号链接并且发生了变化，也是先删除后添加
+                        // 其它情况跳过，会由文件修改检查函数来处理
+                        (false, false) => if n.symlink_target() != o.symlink_target() {
+                            self.mark_as_added(&n)
+                        },
                     }
                 },
 
@@ -110,7 +173,7 @@ impl<N: AbstractFile, O: AbstractFile> Diff<N, O> {
 
         for o in older.files().iter() {
             let found = match newer.find(&o.name()) {
-                Some(o) => if self.is_visible(o.path().deref()) { Some(o) } else { None },
+                Some(o) => if self.is_visible(&o) { Some(o) } else { None },
                 None => None,
             };
 
@@ -123,8 +186,11 @@ impl<N: AbstractFile, O: AbstractFile> Diff<N, O> {
                     (true, false) => self.mark_as_missing(&o),
                     (false, true) => self.mark_as_missing(&o),
 
-                    // 两边都是文件，跳过，会由文件修改检查函数来处理此情况
-                    (false, false) => (),
+                    // 两边都是文件，如果其中有符号链接并且发生了变化，也是先删除后添加
+                    // 其它情况跳过，会由文件修改检查函数来处理
+                    (false, false) => if n.symlink_target() != o.symlink_target() {
+                        self.mark_as_missing(&o)
+                    },
                 },
 
                 // 在新目录里找不到，此时肯定是被删除的文件
@@ -139,7 +205,7 @@ impl<N: AbstractFile, O: AbstractFile> Diff<N, O> {
         assert!(older.is_dir());
 
         for n in newer.files().iter() {
-            if !self.is_visible(n.path().deref()) {
+            if !self.is_visible(&n) {
                 continue;
             }
 
@@ -155,6 +221,9 @@ impl<N: AbstractFile, O: AbstractFile> Diff<N, O> {
                         (true, false) => (),
                         (false, true) => (),
 
+                        // 符号链接的变化已经由文件新增和文件删除检测函数处理过了
+                        (false, false) if n.symlink_target().is_some() || o.symlink_target().is_some() => (),
+
                         // 两边都是文件，则对比文件，如果不同，视为修改过的文件
                         (false, false) => if !self.compare_file(&n, &o) {
                             self.mark_as_modified(&n)
@@ -183,7 +252,7 @@ impl<N: AbstractFile, O: AbstractFile> Diff<N, O> {
 
     /// 将一个文件或者目录标记为新增
     fn mark_as_added(&mut self, file: &N) {
-        if !self.is_visible(&file.path()) {
+        if !self.is_visible(file) {
             return;
         }
 
@@ -193,6 +262,8 @@ impl<N: AbstractFile, O: AbstractFile> Diff<N, O> {
             for f in file.files().iter() {
                 self.mark_as_added(&f);
             }
+        } else if file.symlink_target().is_some() {
+            self.added_symlinks.push(file.to_owned());
         } else {
             self.added_files.push(file.to_owned());
         }
@@ -200,7 +271,7 @@ impl<N: AbstractFile, O: AbstractFile> Diff<N, O> {
 
     /// 将一个文件标记为修改过的文件，目录不行
     fn mark_as_modified(&mut self, file: &N) {
-        if !self.is_visible(&file.path()) {
+        if !self.is_visible(file) {
             return;
         }
 
@@ -214,77 +285,144 @@ impl<N: AbstractFile, O: AbstractFile> Diff<N, O> {
         let ta = a.modified().duration_since(UNIX_EPOCH).unwrap().as_secs();
         let tb = b.modified().duration_since(UNIX_EPOCH).unwrap().as_secs();
 
-        ta == tb || a.hash().deref() == b.hash().deref()
+        // 只有权限发生变化的文件也算作修改过的文件。有一边不知道权限时（比如旧版本的元数据）不做比较
+        if let (Some(ma), Some(mb)) = (a.mode(), b.mode()) {
+            if ma != mb {
+                return false;
+            }
+        }
+
+        ta == tb || hash_equals_helper(a, b)
+    }
+
+    /// 检查一个被删除的文件是不是位于受保护的路径上
+    fn is_protected(&self, file: &O) -> bool {
+        self.protected_filter.matches(&file.path(), file.is_dir())
     }
 
     /// 检查一个文件要不要被忽略
-    fn is_visible(&self, path: &str) -> bool {
-        !self.excluding_filter.test_any(path, false)
+    fn is_visible(&self, file: &impl AbstractFile) -> bool {
+        !self.excluding_filter.is_excluded(&file.path(), file.is_dir())
     }
     
     /// 检测文件移动操作
-    fn detect_file_movings(&mut self, newer: &N, older: &O) {
-        // 首先收集所有可能的移动操作
-        for updated in &self.added_files {
-            for deleted in &self.missing_files {
-                let n = newer.find(updated.path().deref()).unwrap();
-                let o = older.find(deleted.path().deref()).unwrap();
-
-                if n.modified() == o.modified() {
-                    continue;
-                }
+    ///
+    /// 先按(长度, 哈希值)给被删除的文件建立索引，再给每个新增的文件查找内容完全一样的被删除的文件。
+    /// 只有长度和某个被删除的文件一样时，新增的文件才需要计算哈希值
+    fn detect_file_movings(&mut self) {
+        // key: (长度, 哈希值)，value: 被删除的文件的下标。哈希值使用被删除的文件自己的算法
+        let mut index = HashMap::<(u64, String), Vec<usize>>::new();
+
+        // 每种长度的被删除的文件用到了哪些哈希算法（切换哈希算法后的过渡期里可能有多种）
+        let mut algorithms = HashMap::<u64, Vec<HashAlgorithm>>::new();
+
+        for (i, o) in self.missing_files.iter().enumerate() {
+            // 符号链接没有文件数据，不参与移动检测
+            if o.symlink_target().is_some() {
+                continue;
+            }
 
-                if n.hash().deref() == o.hash().deref() {
-                    self.renamed_files.push((o, n));
-                }
+            let hash = o.hash().to_owned();
+            let algorithm = HashAlgorithm::detect(&hash);
+            let used = algorithms.entry(o.len()).or_default();
+
+            if !used.contains(&algorithm) {
+                used.push(algorithm);
             }
 
-            // println!("{}", updated.path().deref());
+            index.entry((o.len(), hash)).or_default().push(i);
         }
 
-        // 如果有多个同名但不同路径的文件移动，就将它们退回复制操作，而非移动
-        let mut ambiguous = Vec::<String>::new();
-        let mut scanned = Vec::<String>::new();
+        // 把新增的文件按相同的键分组，使用有序的容器，保证结果是确定的
+        let mut groups = BTreeMap::<(u64, String), Vec<usize>>::new();
 
-        for moving in &self.renamed_files {
-            if scanned.contains(&moving.0.path().to_owned()) {
-                if !ambiguous.contains(&moving.0.path().to_owned()) {
-                    ambiguous.push(moving.0.path().to_owned());
-                }
-            } else {
-                scanned.push(moving.0.path().to_owned());
+        for (i, n) in self.added_files.iter().enumerate() {
+            let Some(used) = algorithms.get(&n.len()) else {
+                continue;
+            };
+
+            let key = used.iter()
+                .filter_map(|algorithm| n.hash_with(*algorithm))
+                .map(|hash| (n.len(), hash))
+                .find(|key| index.contains_key(key));
+
+            if let Some(key) = key {
+                groups.entry(key).or_default().push(i);
             }
         }
 
-        for a in ambiguous {
-            for i in (0..self.renamed_files.len()).rev() {
-                if self.renamed_files[i].0.path().deref() == &a {
-                    self.renamed_files.remove(i);
-                }
-            }
+        // 同一组里可能有多个新增的文件和多个被删除的文件，需要一一配对
+        let mut pairs = Vec::<(usize, usize)>::new();
+
+        for (key, added) in groups {
+            pairs.extend(self.pair_movings(&index[&key], &added));
         }
 
-        // 将复制操作简化为移动操作
-        for moving in &self.renamed_files {
-            for i in (0..self.added_files.len()).rev() {
-                if self.added_files[i].path().deref() == moving.1.path().deref() {
-                    self.added_files.remove(i);
-                }
-            }
+        // 将配对上的新增和删除操作简化为移动操作
+        pairs.sort_by(|a, b| self.added_files[a.1].path().cmp(&self.added_files[b.1].path()));
+
+        for (o, n) in &pairs {
+            self.renamed_files.push((self.missing_files[*o].clone(), self.added_files[*n].clone()));
+        }
+
+        let moved_missing = pairs.iter().map(|e| e.0).collect::<HashSet<_>>();
+        let moved_added = pairs.iter().map(|e| e.1).collect::<HashSet<_>>();
+
+        let mut i = 0;
+        self.missing_files.retain(|_| { i += 1; !moved_missing.contains(&(i - 1)) });
+
+        let mut i = 0;
+        self.added_files.retain(|_| { i += 1; !moved_added.contains(&(i - 1)) });
+    }
+
+    /// 把内容相同的被删除的文件和新增的文件一一配对，返回(被删除的文件的下标, 新增的文件的下标)
+    ///
+    /// 优先配对文件名相同的文件，剩下的按路径顺序配对，配对不上的文件仍然保持新增或者删除
+    fn pair_movings(&self, missing: &[usize], added: &[usize]) -> Vec<(usize, usize)> {
+        let mut missing = missing.to_vec();
+        let mut added = added.to_vec();
+
+        missing.sort_by(|a, b| self.missing_files[*a].path().cmp(&self.missing_files[*b].path()));
+        added.sort_by(|a, b| self.added_files[*a].path().cmp(&self.added_files[*b].path()));
+
+        let mut pairs = Vec::new();
+
+        for same_name in [true, false] {
+            added.retain(|n| {
+                let new = &self.added_files[*n];
+
+                let found = missing.iter().position(|o| {
+                    let old = &self.missing_files[*o];
+
+                    // 移动操作不会携带文件权限，权限变了的文件只能按新文件处理
+                    let mode_kept = new.mode().is_none() || old.mode().is_none() || new.mode() == old.mode();
+
+                    mode_kept && (!same_name || new.name().deref() == old.name().deref())
+                });
 
-            for i in (0..self.missing_files.len()).rev() {
-                if self.missing_files[i].path().deref() == moving.0.path().deref() {
-                    self.missing_files.remove(i);
+                match found {
+                    Some(pos) => {
+                        pairs.push((missing.remove(pos), *n));
+                        false
+                    },
+                    None => true,
                 }
-            }
+            });
         }
+
+        pairs
     }
 
     /// 将一个`diff`对象转换成文件变动列表
     pub fn to_file_changes(&self) -> LinkedList<FileChange> {
+        self.to_file_changes_with(|_| false)
+    }
+
+    /// 将一个`diff`对象转换成文件变动列表，新增和修改的文件中，`create_if_missing`返回true的会使用“按需创建文件”操作
+    pub fn to_file_changes_with(&self, create_if_missing: impl Fn(&N) -> bool) -> LinkedList<FileChange> {
         let mut changes = LinkedList::new();
     
-        for f in &self.missing_files {
+        for f in self.missing_files.iter().filter(|f| !self.is_protected(f)) {
             changes.push_back(FileChange::DeleteFile { 
                 path: f.path().to_owned() 
             })
@@ -303,27 +441,32 @@ impl<N: AbstractFile, O: AbstractFile> Diff<N, O> {
             })
         }
     
-        for f in &self.added_files {
-            changes.push_back(FileChange::UpdateFile { 
+        for f in self.added_files.iter().chain(&self.modified_files) {
+            let change = FileChange::UpdateFile { 
                 path: f.path().to_owned(), 
                 hash: f.hash().to_owned(), 
                 len: f.len(), 
                 modified: f.modified(), 
+                mode: f.mode(),
                 offset: 0, // 此时offset是空的，需要由TarWriter去填充
-            })
-        }
+                compression: Compression::None, // 是否压缩需要由打包流程来决定
+                compressed_len: f.len(),
+            };
 
-        for f in &self.modified_files {
-            changes.push_back(FileChange::UpdateFile { 
+            changes.push_back(match create_if_missing(f) {
+                true => change.into_create_if_missing(),
+                false => change,
+            });
+        }
+    
+        for f in &self.added_symlinks {
+            changes.push_back(FileChange::CreateSymlink { 
                 path: f.path().to_owned(), 
-                hash: f.hash().to_owned(), 
-                len: f.len(), 
-                modified: f.modified(), 
-                offset: 0, // 此时offset是空的，需要由TarWriter去填充
+                target: f.symlink_target().unwrap(),
             })
         }
     
-        for f in &self.missing_folders {
+        for f in self.missing_folders.iter().filter(|f| !self.is_protected(f)) {
             changes.push_back(FileChange::DeleteFolder { 
                 path: f.path().to_owned() 
             })
@@ -335,13 +478,14 @@ impl<N: AbstractFile, O: AbstractFile> Diff<N, O> {
 
 impl<N: AbstractFile, O: AbstractFile> Display for Diff<N, O> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(&format!("Diff ({}{}, {}{}, {}{}, {}{}, {}{}, {}{})",
+        f.write_str(&format!("Diff ({}{}, {}{}, {}{}, {}{}, {}{}, {}{}, {}{})",
             OP_SHORT_ADDED_FOLDER, self.added_folders.len(),
             OP_SHORT_ADDED_FILE, self.added_files.len(),
             OP_SHORT_MODIFIED_FILE, self.modified_files.len(),
             OP_SHORT_MISSING_FOLDER, self.missing_folders.len(),
             OP_SHORT_MISSING_FILE, self.missing_files.len(),
             OP_SHORT_MOVE_FILE, self.renamed_files.len(),
+            OP_SHORT_ADDED_SYMLINK, self.added_symlinks.len(),
         ))
     }
 }
@@ -384,6 +528,11 @@ impl<N: AbstractFile, O: AbstractFile> Debug for Diff<N, O> {
             printn!(need_newline, fmt);
             fmt.write_str(&format!("{}{}", OP_FULL_MODIFIED_FILE, f.path().deref()))?;
         }
+
+        for f in &self.added_symlinks {
+            printn!(need_newline, fmt);
+            fmt.write_str(&format!("{}{} -> {}", OP_FULL_ADDED_SYMLINK, f.path().deref(), f.symlink_target().unwrap()))?;
+        }
     
         for f in &self.missing_folders {
             printn!(need_newline, fmt);
@@ -392,4 +541,117 @@ impl<N: AbstractFile, O: AbstractFile> Debug for Diff<N, O> {
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Weak;
+    use std::time::Duration;
+    use std::time::UNIX_EPOCH;
+
+    use crate::core::compression::Compression;
+    use crate::core::file_hash::calculate_hash;
+    use crate::core::file_hash::HashAlgorithm;
+    use crate::diff::abstract_file::AbstractFile;
+    use crate::diff::diff::Diff;
+    use crate::diff::disk_file::DiskFile;
+    use crate::diff::disk_file::SymlinkMode;
+    use crate::diff::history_file::FilePackedLoc;
+    use crate::diff::history_file::HistoryFile;
+
+    /// 构建一个文件树，每个文件是(路径, 哈希值, 修改时间, 权限)，上级目录会自动创建
+    fn history(files: &[(&str, &str, u64, u32)]) -> HistoryFile {
+        let root = HistoryFile::new_empty();
+
+        for (path, hash, modified, mode) in files {
+            if let Some((parent, _)) = path.rsplit_once('/') {
+                if root.find(parent).is_none() {
+                    root.create_directory(parent);
+                }
+            }
+
+            let loc = FilePackedLoc { version: "1.0".to_owned(), offset: 0, length: 8, base: None, compression: Compression::None };
+            let modified = UNIX_EPOCH + Duration::from_secs(*modified);
+
+            root.update_file(path, &hash.to_string(), &8, &modified, &Some(*mode), loc);
+        }
+
+        root
+    }
+
+    fn renamed<N: AbstractFile, O: AbstractFile>(diff: &Diff<N, O>) -> Vec<(String, String)> {
+        diff.renamed_files.iter().map(|(o, n)| (o.path().to_owned(), n.path().to_owned())).collect()
+    }
+
+    #[test]
+    fn test_move_with_equal_mtime() {
+        let older = history(&[("old.txt", "0123456789abcdef_0123", 100, 0o644)]);
+        let newer = history(&[("new.txt", "0123456789abcdef_0123", 100, 0o644)]);
+
+        let diff = Diff::diff(&newer, &older, None);
+
+        assert_eq!(renamed(&diff), vec![("old.txt".to_owned(), "new.txt".to_owned())]);
+        assert!(diff.added_files.is_empty());
+        assert!(diff.missing_files.is_empty());
+    }
+
+    #[test]
+    fn test_ambiguous_moves_pair_same_name_first() {
+        let older = history(&[
+            ("a/one.bin", "0123456789abcdef_0123", 100, 0o644),
+            ("a/two.bin", "0123456789abcdef_0123", 100, 0o644),
+        ]);
+        let newer = history(&[
+            ("b/two.bin", "0123456789abcdef_0123", 200, 0o644),
+            ("c/one.bin", "0123456789abcdef_0123", 200, 0o644),
+        ]);
+
+        let expected = vec![
+            ("a/two.bin".to_owned(), "b/two.bin".to_owned()),
+            ("a/one.bin".to_owned(), "c/one.bin".to_owned()),
+        ];
+
+        // 内部用到了HashMap，多比较几次确保结果不受哈希表顺序影响
+        for _ in 0..16 {
+            let diff = Diff::diff(&newer, &older, None);
+
+            assert_eq!(renamed(&diff), expected);
+            assert!(diff.added_files.is_empty());
+            assert!(diff.missing_files.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_mode_change_blocks_move() {
+        let older = history(&[("run.sh", "0123456789abcdef_0123", 100, 0o644)]);
+        let newer = history(&[("bin/run.sh", "0123456789abcdef_0123", 100, 0o755)]);
+
+        let diff = Diff::diff(&newer, &older, None);
+
+        assert!(diff.renamed_files.is_empty());
+        assert_eq!(diff.added_files.iter().map(|f| f.path().to_owned()).collect::<Vec<_>>(), vec!["bin/run.sh"]);
+        assert_eq!(diff.missing_files.iter().map(|f| f.path().to_owned()).collect::<Vec<_>>(), vec!["run.sh"]);
+    }
+
+    #[test]
+    fn test_move_with_mixed_hash_algorithms() {
+        let dir = std::env::temp_dir().join(format!("mcpatch-diff-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("moved.txt"), b"12345678").unwrap();
+
+        // 旧版本用sha256记录，还有一个长度相同但内容不同的crc-combo文件，新的工作空间用crc-combo计算
+        let sha256 = calculate_hash(&mut &b"12345678"[..], HashAlgorithm::Sha256);
+        let other = calculate_hash(&mut &b"87654321"[..], HashAlgorithm::CrcCombo);
+        let older = history(&[("origin.txt", &sha256, 100, 0o644), ("other.txt", &other, 100, 0o644)]);
+        let newer = DiskFile::new(dir.clone(), Weak::new(), HashAlgorithm::CrcCombo, SymlinkMode::Follow).unwrap();
+
+        let diff = Diff::diff(&newer, &older, None);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(renamed(&diff), vec![("origin.txt".to_owned(), "moved.txt".to_owned())]);
+        assert!(diff.added_files.is_empty());
+        assert_eq!(diff.missing_files.iter().map(|f| f.path().to_owned()).collect::<Vec<_>>(), vec!["other.txt"]);
+    }
+}
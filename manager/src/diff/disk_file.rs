@@ -1,23 +1,44 @@
 //! 磁盘文件对象
 
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::collections::LinkedList;
 use std::fmt::Debug;
 use std::ops::Deref;
+use std::path::Component;
 use std::path::Path;
 use std::path::PathBuf;
 use std::rc::Rc;
 use std::rc::Weak;
 use std::time::SystemTime;
 
+use serde::Deserialize;
+use serde::Serialize;
+
 use crate::core::file_hash::calculate_hash;
+use crate::core::file_hash::HashAlgorithm;
+use crate::core::rule_filter::RuleFilter;
 use crate::diff::abstract_file::calculate_path_helper;
 use crate::diff::abstract_file::find_file_helper;
 use crate::diff::abstract_file::walk_abstract_file;
 use crate::diff::abstract_file::AbstractFile;
 use crate::diff::abstract_file::BorrowIntoIterator;
+use crate::error::IoContext;
+use crate::error::ManagerError;
 use crate::utility::filename_ext::GetFileNamePart;
 
+/// 扫描工作空间目录时如何对待符号链接
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum SymlinkMode {
+    /// 跟随符号链接，把链接指向的文件或者目录当成普通的文件或者目录来打包
+    #[default]
+    Follow,
+
+    /// 保留符号链接本身，只记录链接指向的路径，客户端更新时会创建同样的符号链接
+    Preserve,
+}
+
 /// 借用哈希
 pub struct BorrowedHash<'a>(std::cell::Ref<'a, Option<String>>);
 
@@ -57,15 +78,39 @@ pub struct Inner {
     /// 文件修改时间
     modified: SystemTime,
 
+    /// unix文件权限
+    mode: Option<u32>,
+
     /// 是不是一个目录
     is_dir: bool,
 
+    /// 符号链接指向的路径，不是符号链接时为None
+    symlink: Option<String>,
+
+    /// 读取符号链接指向的路径失败时的原因，此时`symlink`是一个空字符串
+    symlink_error: Option<String>,
+
+    /// 无法读取这个文件（或者目录的内容）时的原因，无法读取的文件会被当成一个空文件
+    read_error: RefCell<Option<String>>,
+
+    /// 目录的真实路径，用来检测跟随符号链接时形成的循环，不是目录或者不跟随符号链接时为None
+    canonical: Option<PathBuf>,
+
+    /// 如何对待符号链接
+    symlink_mode: SymlinkMode,
+
     /// 文件的相对路径
     path: RefCell<String>,
 
+    /// 计算哈希值时使用的算法
+    hash_algorithm: HashAlgorithm,
+
     /// 文件的哈希值缓存
     hash: RefCell<Option<String>>,
 
+    /// 使用其它算法计算的哈希值缓存，主要在切换哈希算法后的过渡期使用
+    other_hashes: RefCell<HashMap<HashAlgorithm, String>>,
+
     /// 子文件列表缓存
     children: RefCell<Option<LinkedList<DiskFile>>>,
 }
@@ -83,10 +128,26 @@ impl Deref for DiskFile {
 }
 
 impl DiskFile {
-    /// 从磁盘路径创建
-    pub fn new(path: PathBuf, parent: Weak<Inner>) -> Self {
+    /// 从磁盘路径创建，`algorithm`是计算文件哈希值时使用的算法，`symlink_mode`决定如何对待符号链接
+    pub fn new(path: PathBuf, parent: Weak<Inner>, algorithm: HashAlgorithm, symlink_mode: SymlinkMode) -> Result<Self, ManagerError> {
         let filename = path.filename().to_owned();
-        let metadata = std::fs::metadata(&path).unwrap();
+        let metadata = match symlink_mode {
+            SymlinkMode::Follow => std::fs::metadata(&path),
+            SymlinkMode::Preserve => std::fs::symlink_metadata(&path),
+        };
+        let metadata = metadata.with_path(&path)?;
+        let canonical = match symlink_mode == SymlinkMode::Follow && metadata.is_dir() {
+            true => Some(std::fs::canonicalize(&path).with_path(&path)?),
+            false => None,
+        };
+        let (symlink, symlink_error) = match metadata.is_symlink() {
+            true => match read_symlink(&path) {
+                Ok(target) => (Some(target), None),
+                Err(reason) => (Some("".to_owned()), Some(reason)),
+            },
+            false => (None, None),
+        };
+        let modified = metadata.modified().with_path(&path)?;
         let strong_parent = parent.clone().upgrade().map(|p| DiskFile(p));
 
         let inner = Inner {
@@ -94,10 +155,48 @@ impl DiskFile {
             parent,
             name: filename.to_owned(), 
             len: metadata.len(), 
-            modified: metadata.modified().unwrap(), 
+            modified, 
+            mode: match symlink.is_some() {
+                true => None,
+                false => file_mode(&metadata),
+            },
             is_dir: metadata.is_dir(), 
+            symlink,
+            symlink_error,
+            read_error: RefCell::new(None),
+            canonical,
+            symlink_mode,
             path: RefCell::new(calculate_path_helper(&filename, strong_parent.as_ref())), 
+            hash_algorithm: algorithm,
+            hash: RefCell::new(None), 
+            other_hashes: RefCell::new(HashMap::new()),
+            children: RefCell::new(None), 
+        };
+
+        Ok(Self(Rc::new(inner)))
+    }
+
+    /// 创建一个代表无法读取的子文件的对象，在检查时会报告`reason`
+    fn unreadable_child(&self, path: PathBuf, reason: String) -> Self {
+        let filename = path.filename().to_owned();
+
+        let inner = Inner {
+            file: path, 
+            parent: Rc::downgrade(&self.0),
+            name: filename.to_owned(), 
+            len: 0, 
+            modified: SystemTime::UNIX_EPOCH, 
+            mode: None,
+            is_dir: false, 
+            symlink: None,
+            symlink_error: None,
+            read_error: RefCell::new(Some(reason)),
+            canonical: None,
+            symlink_mode: self.symlink_mode,
+            path: RefCell::new(calculate_path_helper(&filename, Some(self))), 
+            hash_algorithm: self.hash_algorithm,
             hash: RefCell::new(None), 
+            other_hashes: RefCell::new(HashMap::new()),
             children: RefCell::new(None), 
         };
 
@@ -108,6 +207,64 @@ impl DiskFile {
     pub fn disk_file(&self) -> &Path {
         &self.file
     }
+
+    /// 检查子目录`child`是不是这个目录自己或者某个上级目录（跟随符号链接时可能出现），是的话返回那个目录的真实路径
+    fn find_cycle(&self, child: &DiskFile) -> Option<PathBuf> {
+        let canonical = child.canonical.as_ref()?;
+        let mut dir = Some(self.0.clone());
+
+        while let Some(d) = dir {
+            if d.canonical.as_ref() == Some(canonical) {
+                return Some(canonical.to_owned());
+            }
+
+            dir = d.parent.upgrade();
+        }
+
+        None
+    }
+
+    /// 检查目录里所有没有被`filter`排除的文件和符号链接，对比文件差异前需要先检查一遍
+    /// 
+    /// 无法读取的文件和循环的符号链接会报错。另外客户端会原样创建符号链接，所以指向的路径必须是相对路径，并且不能跑到工作空间目录外面
+    pub fn check_files(&self, filter: &RuleFilter) -> Result<(), ManagerError> {
+        let files = self.files();
+
+        if let Some(reason) = self.read_error.borrow().as_ref() {
+            return Err(ManagerError::task(format!("无法读取目录 {}: {}", self.file.display(), reason)));
+        }
+
+        for f in files.iter() {
+            if filter.is_excluded(&f.path(), f.is_dir()) {
+                continue;
+            }
+
+            if let Some(reason) = f.read_error.borrow().as_ref() {
+                return Err(ManagerError::task(format!("无法读取 {}: {}", f.path().deref(), reason)));
+            }
+
+            if let Some(reason) = &f.symlink_error {
+                return Err(ManagerError::task(format!("无法读取符号链接 {}: {}", f.path().deref(), reason)));
+            }
+
+            match &f.symlink {
+                Some(target) => {
+                    if Path::new(target).has_root() || Path::new(target).is_absolute() {
+                        return Err(ManagerError::task(format!("符号链接 {} 指向了一个绝对路径: {}", f.path().deref(), target)));
+                    }
+
+                    if escapes_workspace(&f.path(), target) {
+                        return Err(ManagerError::task(format!("符号链接 {} 指向了工作空间目录外面: {}", f.path().deref(), target)));
+                    }
+                },
+                None => if f.is_dir() {
+                    f.check_files(filter)?;
+                },
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl AbstractFile for DiskFile {
@@ -121,12 +278,13 @@ impl AbstractFile for DiskFile {
 
     fn hash(&self) -> impl Deref<Target = String> {
         assert!(!self.is_dir);
+        assert!(self.symlink.is_none());
         
         let mut hash_mut = self.hash.borrow_mut();
 
         if hash_mut.is_none() {
             let mut fd = std::fs::File::open(&self.file).unwrap();
-            *hash_mut = Some(calculate_hash(&mut fd));
+            *hash_mut = Some(calculate_hash(&mut fd, self.hash_algorithm));
         }
 
         drop(hash_mut);
@@ -134,6 +292,21 @@ impl AbstractFile for DiskFile {
         BorrowedHash(self.hash.borrow())
     }
 
+    fn hash_with(&self, algorithm: HashAlgorithm) -> Option<String> {
+        if algorithm == self.hash_algorithm {
+            return Some(self.hash().to_owned());
+        }
+
+        let mut other_hashes = self.other_hashes.borrow_mut();
+
+        let hash = other_hashes.entry(algorithm).or_insert_with(|| {
+            let mut fd = std::fs::File::open(&self.file).unwrap();
+            calculate_hash(&mut fd, algorithm)
+        });
+
+        Some(hash.to_owned())
+    }
+
     fn len(&self) -> u64 { 
         self.len
     }
@@ -142,10 +315,18 @@ impl AbstractFile for DiskFile {
         self.modified
     }
 
+    fn mode(&self) -> Option<u32> {
+        self.mode
+    }
+
     fn is_dir(&self) -> bool {
         self.is_dir
     }
 
+    fn symlink_target(&self) -> Option<String> {
+        self.symlink.clone()
+    }
+
     fn path(&self) -> impl Deref<Target = String> {
         self.path.borrow()
     }
@@ -157,13 +338,24 @@ impl AbstractFile for DiskFile {
 
         if children_mut.is_none() {
             let mut result = LinkedList::new();
-        
-            for file in std::fs::read_dir(&self.file).unwrap() {
-                let file = file.unwrap();
-                
-                let child = DiskFile::new(file.path(), Rc::downgrade(&self.0));
-                
-                result.push_back(child);
+
+            // 这里没法返回错误，读取失败的原因会被记录下来，由check_files()报告出来
+            let read_dir = std::fs::read_dir(&self.file)
+                .and_then(|entries| entries.map(|e| e.map(|e| e.path())).collect::<std::io::Result<Vec<_>>>());
+
+            match read_dir {
+                Ok(paths) => for path in paths {
+                    let child = match DiskFile::new(path.clone(), Rc::downgrade(&self.0), self.hash_algorithm, self.symlink_mode) {
+                        Ok(child) => match self.find_cycle(&child) {
+                            Some(ancestor) => self.unreadable_child(path, format!("符号链接形成了循环，指向了上级目录 {}", ancestor.display())),
+                            None => child,
+                        },
+                        Err(err) => self.unreadable_child(path, err.to_string()),
+                    };
+
+                    result.push_back(child);
+                },
+                Err(err) => *self.read_error.borrow_mut() = Some(err.to_string()),
             }
 
             *children_mut = Some(result);
@@ -179,6 +371,53 @@ impl AbstractFile for DiskFile {
     }
 }
 
+/// 读取符号链接指向的路径，统一使用/作为分隔符
+fn read_symlink(path: &Path) -> Result<String, String> {
+    let target = std::fs::read_link(path).map_err(|e| e.to_string())?;
+
+    match target.to_str() {
+        Some(target) => Ok(target.replace("\\", "/")),
+        None => Err(format!("指向的路径不是合法的UTF-8字符串: {:?}", target)),
+    }
+}
+
+/// 检查位于`link`的符号链接指向的相对路径`target`会不会跑到工作空间目录外面
+fn escapes_workspace(link: &str, target: &str) -> bool {
+    // 符号链接所在的目录的深度
+    let mut depth = link.split('/').count() - 1;
+
+    for component in Path::new(target).components() {
+        match component {
+            Component::ParentDir => match depth {
+                0 => return true,
+                _ => depth -= 1,
+            },
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => (),
+            Component::Prefix(_) | Component::RootDir => return true,
+        }
+    }
+
+    false
+}
+
+/// 读取文件的unix权限位，非unix平台上始终返回None
+#[cfg(unix)]
+fn file_mode(metadata: &std::fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+
+    match metadata.is_dir() {
+        true => None,
+        false => Some(metadata.permissions().mode() & 0o7777),
+    }
+}
+
+/// 读取文件的unix权限位，非unix平台上始终返回None
+#[cfg(not(unix))]
+fn file_mode(_metadata: &std::fs::Metadata) -> Option<u32> {
+    None
+}
+
 impl Debug for DiskFile {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str(&walk_abstract_file(self, 4))
@@ -1,5 +1,6 @@
 //! 历史文件对象（文件状态快照）
 
+use std::cell::Cell;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fmt::Debug;
@@ -8,8 +9,10 @@ use std::rc::Rc;
 use std::rc::Weak;
 use std::time::SystemTime;
 
+use crate::core::compression::Compression;
 use crate::core::data::version_meta::FileChange;
 use crate::core::data::version_meta::VersionMeta;
+use crate::core::file_hash::HashAlgorithm;
 use crate::diff::abstract_file::calculate_path_helper;
 use crate::diff::abstract_file::find_file_helper;
 use crate::diff::abstract_file::walk_abstract_file;
@@ -22,6 +25,12 @@ pub struct FilePackedLoc {
     pub version: String,
     pub offset: u64,
     pub length: u64,
+
+    /// 如果这里存的是一个差异补丁，那么这里是打补丁之前的旧文件的位置
+    pub base: Option<Rc<FilePackedLoc>>,
+
+    /// 数据的压缩算法
+    pub compression: Compression,
 }
 
 impl Default for FilePackedLoc {
@@ -30,6 +39,8 @@ impl Default for FilePackedLoc {
             version: "none".to_owned(), 
             offset: 0, 
             length: 0,
+            base: None,
+            compression: Compression::None,
         }
     }
 }
@@ -59,9 +70,15 @@ pub struct Inner {
     /// 文件修改时间
     modified: SystemTime,
 
+    /// unix文件权限
+    mode: Option<u32>,
+
     /// 是不是一个目录
     is_dir: bool,
 
+    /// 符号链接指向的路径，不是符号链接时为None
+    symlink: Option<String>,
+
     /// 文件的相对路径
     path: RefCell<String>,
 
@@ -70,6 +87,9 @@ pub struct Inner {
 
     /// 所属版本
     loc: FilePackedLoc,
+
+    /// 是不是由“按需创建文件”操作创建的，这种文件在客户端上的内容可能和这里记录的不一样
+    create_if_missing: Cell<bool>,
     
     /// 子文件列表
     children: RefCell<HashMap<String, HistoryFile>>,
@@ -81,7 +101,7 @@ pub struct HistoryFile(Rc<Inner>);
 
 impl HistoryFile {
     /// 创建一个文件对象
-    pub fn new_file(name: &str, modified: SystemTime, len: u64, hash: String, parent: Weak<Inner>, loc: FilePackedLoc) -> Self {
+    pub fn new_file(name: &str, modified: SystemTime, mode: Option<u32>, len: u64, hash: String, parent: Weak<Inner>, loc: FilePackedLoc) -> Self {
         let strong_parent = parent.clone().upgrade().map(|p| HistoryFile(p));
         
         Self(Rc::new(Inner {
@@ -89,10 +109,13 @@ impl HistoryFile {
             name: RefCell::new(name.to_owned()),
             len,
             modified,
+            mode,
             is_dir: false,
+            symlink: None,
             path: RefCell::new(calculate_path_helper(name, strong_parent.as_ref())),
             hash,
             loc,
+            create_if_missing: Cell::new(false),
             children: RefCell::new(HashMap::new()),
         }))
     }
@@ -106,10 +129,33 @@ impl HistoryFile {
             name: RefCell::new(name.to_owned()),
             len: 0,
             modified: std::time::UNIX_EPOCH,
+            mode: None,
             is_dir: true,
+            symlink: None,
             path: RefCell::new(calculate_path_helper(name, strong_parent.as_ref())),
             hash: "it is a dir".to_owned(),
             loc: FilePackedLoc::default(),
+            create_if_missing: Cell::new(false),
+            children: RefCell::new(HashMap::new()),
+        }))
+    }
+
+    /// 创建一个符号链接对象
+    pub fn new_symlink(name: &str, target: String, parent: Weak<Inner>) -> Self {
+        let strong_parent = parent.clone().upgrade().map(HistoryFile);
+        
+        Self(Rc::new(Inner {
+            parent: RefCell::new(parent),
+            name: RefCell::new(name.to_owned()),
+            len: 0,
+            modified: std::time::UNIX_EPOCH,
+            mode: None,
+            is_dir: false,
+            symlink: Some(target),
+            path: RefCell::new(calculate_path_helper(name, strong_parent.as_ref())),
+            hash: "it is a symlink".to_owned(),
+            loc: FilePackedLoc::default(),
+            create_if_missing: Cell::new(false),
             children: RefCell::new(HashMap::new()),
         }))
     }
@@ -124,13 +170,36 @@ impl HistoryFile {
         for change in &meta.changes {
             match change {
                 FileChange::CreateFolder { path } =>  self.create_directory(&path),
-                FileChange::UpdateFile { path, hash, offset, len, modified, .. } => {
-                    self.update_file(&path, hash, len, modified, FilePackedLoc {
+                FileChange::UpdateFile { path, hash, offset, len, modified, mode, compression, compressed_len } |
+                FileChange::CreateIfMissing { path, hash, offset, len, modified, mode, compression, compressed_len } => {
+                    // 这里记录的是全新安装时的文件状态，所以按需创建的文件也会覆盖掉现有的记录
+                    self.update_file(&path, hash, len, modified, mode, FilePackedLoc {
                         version: meta.label.to_owned(), 
                         offset: *offset, 
-                        length: (*len),
+                        length: *compressed_len,
+                        base: None,
+                        compression: *compression,
+                    });
+
+                    if let FileChange::CreateIfMissing { .. } = change {
+                        self.find(path).unwrap().create_if_missing.set(true);
+                    }
+                },
+                FileChange::PatchFile { path, hash, len, modified, mode, offset, patch_len } => {
+                    let base = self.find(path)
+                        .unwrap_or_else(|| panic!("can not found the file {} to patch", path))
+                        .file_location()
+                        .to_owned();
+
+                    self.update_file(path, hash, len, modified, mode, FilePackedLoc {
+                        version: meta.label.to_owned(), 
+                        offset: *offset, 
+                        length: *patch_len,
+                        base: Some(Rc::new(base)),
+                        compression: Compression::None,
                     })
                 },
+                FileChange::CreateSymlink { path, target } => self.create_symlink(path, target),
                 FileChange::DeleteFolder { path } => self.delete_file_or_directory(&path),
                 FileChange::DeleteFile { path } => self.delete_file_or_directory(&path),
                 FileChange::MoveFile { from, to } => self.move_file(&from, &to),
@@ -139,10 +208,10 @@ impl HistoryFile {
     }
 
     /// 复现一个“更新文件”的操作
-    pub fn update_file(&self, path: &str, hash: &String, len: &u64, modified: &SystemTime, loc: FilePackedLoc) {
+    pub fn update_file(&self, path: &str, hash: &String, len: &u64, modified: &SystemTime, mode: &Option<u32>, loc: FilePackedLoc) {
         let (parent, end) = self.lookup_parent_and_end(path);
 
-        let file = HistoryFile::new_file(end, *modified, *len, hash.to_owned(), Rc::downgrade(&parent), loc);
+        let file = HistoryFile::new_file(end, *modified, *mode, *len, hash.to_owned(), Rc::downgrade(&parent), loc);
 
         parent.children.borrow_mut().insert(end.to_owned(), file);
     }
@@ -156,6 +225,15 @@ impl HistoryFile {
         parent.children.borrow_mut().insert(end.to_owned(), dir);
     }
 
+    /// 复现一个“创建符号链接”的操作
+    pub fn create_symlink(&self, path: &str, target: &str) {
+        let (parent, end) = self.lookup_parent_and_end(path);
+        
+        let link = HistoryFile::new_symlink(end, target.to_owned(), Rc::downgrade(&parent));
+
+        parent.children.borrow_mut().insert(end.to_owned(), link);
+    }
+
     /// 复现一个“移动文件”的操作
     pub fn move_file(&self, from: &str, to: &str) {
         let (parent, end) = self.lookup_parent_and_end(from);
@@ -188,6 +266,11 @@ impl HistoryFile {
         &self.loc
     }
 
+    /// 是不是由“按需创建文件”操作创建的文件
+    pub fn is_create_if_missing(&self) -> bool {
+        self.create_if_missing.get()
+    }
+
     /// 查找一个文件
     fn lookup_parent_and_end<'a, 'b>(&'a self, path: &'b str) -> (HistoryFile, &'b str) {
         let (parent, end) = if path.contains("/") { 
@@ -238,6 +321,14 @@ impl AbstractFile for HistoryFile {
         &self.hash
     }
 
+    fn hash_with(&self, algorithm: HashAlgorithm) -> Option<String> {
+        // 历史文件没有数据，没法重新计算，只能返回现有的哈希值
+        match HashAlgorithm::detect(&self.hash) == algorithm {
+            true => Some(self.hash.to_owned()),
+            false => None,
+        }
+    }
+
     fn len(&self) -> u64 { 
         self.len
     }
@@ -246,10 +337,18 @@ impl AbstractFile for HistoryFile {
         self.modified
     }
 
+    fn mode(&self) -> Option<u32> {
+        self.mode
+    }
+
     fn is_dir(&self) -> bool {
         self.is_dir
     }
 
+    fn symlink_target(&self) -> Option<String> {
+        self.symlink.clone()
+    }
+
     fn path(&self) -> impl Deref<Target = String> {
         self.path.borrow()
     }
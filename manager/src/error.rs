@@ -0,0 +1,139 @@
+//! 错误处理
+//!
+//! 所有的任务（`task_*`函数）在失败时都会返回一个[`ManagerError`]，而不是直接panic。
+//! 错误由调用者统一输出到日志里，并转换成任务的返回代码（参考[`report_result`]），
+//! 这样在webui模式下，某个任务失败了也不会影响到整个进程
+
+use std::fmt::Display;
+use std::path::Path;
+
+use crate::core::archive_tester::Failure;
+use crate::core::data::parse_error::ParseError;
+use crate::web::log::Console;
+
+/// 代表管理端的各种错误
+#[derive(Debug)]
+pub enum ManagerError {
+    /// 文件读写失败
+    Io {
+        /// 出错的文件路径或者正在进行的操作
+        context: String,
+
+        /// 原始错误
+        error: std::io::Error,
+    },
+
+    /// 索引文件或者元数据解析失败
+    Parse(ParseError),
+
+    /// 更新包的解压测试没有通过
+    TestFailed(Failure),
+
+    /// 更新包里的数据已经损坏，无法读取或者还原
+    Corrupted(String),
+
+    /// 上传到远程服务器失败
+    Upload(String),
+
+    /// 任务无法继续进行，比如版本号不存在，参数不正确等
+    Task(String),
+}
+
+impl ManagerError {
+    /// 创建一个任务错误
+    pub fn task(reason: impl Into<String>) -> Self {
+        ManagerError::Task(reason.into())
+    }
+
+    /// 将错误转换成Json对象，供命令行的`--json`模式输出
+    /// 
+    /// `kind`字段是错误的种类，`message`字段是错误的描述文字。测试失败时还会带上失败文件的详细信息
+    pub fn to_json(&self) -> serde_json::Value {
+        let kind = match self {
+            ManagerError::Io { .. } => "io",
+            ManagerError::Parse(_) => "parse",
+            ManagerError::TestFailed(_) => "test-failed",
+            ManagerError::Corrupted(_) => "corrupted",
+            ManagerError::Upload(_) => "upload",
+            ManagerError::Task(_) => "task",
+        };
+
+        let mut obj = serde_json::json!({ "kind": kind, "message": self.to_string() });
+
+        if let ManagerError::TestFailed(failure) = self {
+            obj["failure"] = serde_json::json!({
+                "path": failure.path,
+                "label": failure.label,
+                "actual": failure.actual,
+                "expected": failure.expected,
+            });
+        }
+
+        obj
+    }
+}
+
+impl Display for ManagerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ManagerError::Io { context, error } => write!(f, "文件读写失败: {}，{}", context, error),
+            ManagerError::Parse(err) => write!(f, "数据解析失败: {}", err),
+            ManagerError::TestFailed(e) => write!(f, "测试失败！文件哈希不匹配！文件路径: {}, 版本: {} 实际: {}, 预期: {}", e.path, e.label, e.actual, e.expected),
+            ManagerError::Corrupted(reason) => write!(f, "更新包数据已损坏: {}", reason),
+            ManagerError::Upload(reason) => write!(f, "上传失败: {}", reason),
+            ManagerError::Task(reason) => f.write_str(reason),
+        }
+    }
+}
+
+impl std::error::Error for ManagerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ManagerError::Io { error, .. } => Some(error),
+            ManagerError::Parse(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<ParseError> for ManagerError {
+    fn from(value: ParseError) -> Self {
+        ManagerError::Parse(value)
+    }
+}
+
+impl From<Failure> for ManagerError {
+    fn from(value: Failure) -> Self {
+        ManagerError::TestFailed(value)
+    }
+}
+
+/// 给io错误附加上文件路径等上下文信息，转换成[`ManagerError`]
+pub trait IoContext<T> {
+    /// 附加一段上下文信息
+    fn context(self, context: impl Display) -> Result<T, ManagerError>;
+
+    /// 附加一个文件路径作为上下文信息
+    fn with_path(self, path: impl AsRef<Path>) -> Result<T, ManagerError>;
+}
+
+impl<T> IoContext<T> for std::io::Result<T> {
+    fn context(self, context: impl Display) -> Result<T, ManagerError> {
+        self.map_err(|error| ManagerError::Io { context: context.to_string(), error })
+    }
+
+    fn with_path(self, path: impl AsRef<Path>) -> Result<T, ManagerError> {
+        self.context(path.as_ref().display())
+    }
+}
+
+/// 将任务的执行结果输出到日志里，并转换成返回代码：成功时为0，失败时为1
+pub fn report_result(result: Result<(), ManagerError>, console: &Console) -> u8 {
+    match result {
+        Ok(_) => 0,
+        Err(err) => {
+            console.log_error(err.to_string());
+            1
+        },
+    }
+}
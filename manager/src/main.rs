@@ -2,19 +2,33 @@
 
 use std::ffi::OsString;
 use std::io::Write;
+use std::path::PathBuf;
 use std::str::FromStr;
 
 use clap::Parser;
 use clap::Subcommand;
 
 use crate::app_path::AppPath;
+use crate::app_path::STABLE_CHANNEL;
 use crate::builtin_server::start_builtin_server;
 use crate::config::Config;
+use crate::error::report_result;
 use crate::task::check::task_check;
+use crate::task::check_ignore::task_check_ignore;
 use crate::task::combine::task_combine;
+use crate::task::combine::CombineRange;
+use crate::task::diff_versions::task_diff_versions;
+use crate::task::export::task_export;
+use crate::task::full_install::task_full_install;
+use crate::task::inspect::task_inspect;
+use crate::task::keygen::task_keygen;
 use crate::task::pack::task_pack;
+use crate::task::pack::task_pack_dry_run;
+use crate::task::promote::task_promote;
 use crate::task::revert::task_revert;
+use crate::task::rollback::task_pack_rollback;
 use crate::task::test::task_test;
+use crate::task::yank::task_yank;
 use crate::web::log::Console;
 use crate::web::serve_web;
 
@@ -27,11 +41,16 @@ pub mod builtin_server;
 pub mod upload;
 pub mod app_path;
 pub mod task;
+pub mod error;
 
 #[derive(Parser)]
 struct CommandLineInterface {
     #[command(subcommand)]
-    command: Commands
+    command: Commands,
+
+    /// 在标准输出上输出Json格式的任务结果，日志会改为输出到标准错误上
+    #[arg(long, global = true)]
+    json: bool,
 }
 
 #[derive(Subcommand)]
@@ -39,20 +58,142 @@ enum Commands {
     /// 打包一个新的版本
     Pack {
         /// 指定新的版本号
-        version_label: String
+        version_label: String,
+
+        /// 打包到哪个频道
+        #[arg(long, default_value = STABLE_CHANNEL)]
+        channel: String,
+
+        /// 只输出打包计划，不写入任何文件
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// 打包一个回退版本，让客户端回到某个旧版本的文件状态（不会读取和修改工作空间目录）
+    PackRollback {
+        /// 要回退到的旧版本号
+        target_label: String,
+
+        /// 指定新的版本号
+        version_label: String,
+
+        /// 打包到哪个频道
+        #[arg(long, default_value = STABLE_CHANNEL)]
+        channel: String,
+    },
+
+    /// 将一个频道里的版本推送到另一个频道
+    Promote {
+        /// 要推送的版本号
+        version_label: String,
+
+        /// 来源频道
+        #[arg(long)]
+        from: String,
+
+        /// 目标频道
+        #[arg(long, default_value = STABLE_CHANNEL)]
+        to: String,
+    },
+
+    /// 删除一个已经发布的版本
+    Yank {
+        /// 要删除的版本号
+        version_label: String,
+
+        /// 只从这个频道里删除，省略时会从所有包含这个版本的频道里删除
+        #[arg(long)]
+        channel: Option<String>,
+
+        /// 允许删除不是最新的版本，这个版本之后的所有版本也会被一并删除
+        #[arg(long)]
+        force: bool,
     },
 
     /// 检查工作空间的文件修改情况
-    Check,
+    Check {
+        /// 和哪个频道的最新版本进行对比
+        #[arg(long, default_value = STABLE_CHANNEL)]
+        channel: String,
+    },
+
+    /// 检查工作空间里的路径会不会被排除，以及是被哪条规则排除的
+    CheckIgnore {
+        /// 要检查的路径，相对于工作空间目录，以/结尾的路径视为目录
+        #[arg(required = true)]
+        paths: Vec<String>,
+    },
+
+    /// 对比两个已发布版本之间的文件差异
+    DiffVersions {
+        /// 旧版本号
+        older_label: String,
+
+        /// 新版本号
+        newer_label: String,
+
+        /// 版本所在的频道
+        #[arg(long, default_value = STABLE_CHANNEL)]
+        channel: String,
+    },
+
+    /// 把某个版本的完整文件导出到一个目录里
+    Export {
+        /// 要导出的版本号
+        version_label: String,
+
+        /// 导出到哪个目录，必须是不存在的目录或者空目录
+        dest: PathBuf,
+
+        /// 版本所在的频道
+        #[arg(long, default_value = STABLE_CHANNEL)]
+        channel: String,
+    },
 
     /// 合并更新包
-    Combine,
+    Combine {
+        /// 保留最新的N个版本不合并
+        #[arg(long, conflicts_with = "until")]
+        keep_latest: Option<usize>,
+
+        /// 只合并到指定的版本为止（包括这个版本）
+        #[arg(long)]
+        until: Option<String>,
+
+        /// 将合并的所有历史操作压缩成一个只包含最终文件状态的基准版本
+        #[arg(long)]
+        squash: bool,
+
+        /// 压缩历史时，在元数据组里保留其它版本的版本号和更新记录，仅供查阅，这些版本不能再作为更新起点
+        #[arg(long, requires = "squash")]
+        keep_labels: bool,
+    },
     
     /// 测试所有更新包是否能正常读取
     Test,
 
     /// 还原工作空间目录的修改
-    Revert,
+    Revert {
+        /// 还原到哪个频道的最新版本
+        #[arg(long, default_value = STABLE_CHANNEL)]
+        channel: String,
+    },
+
+    /// 生成签名密钥，并重新签名所有的索引文件和元数据
+    Keygen {
+        /// 覆盖已经存在的密钥
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// 为每个频道的最新版本生成完整安装包
+    FullInstall,
+
+    /// 打印一个更新包里的所有版本、文件操作和偏移值
+    Inspect {
+        /// 更新包文件，相对于public目录
+        file: String,
+    },
 
     /// 运行私有协议服务端
     Serve,
@@ -69,7 +210,7 @@ fn main() {
         .build()
         .unwrap();
 
-    runtime.block_on(async move {
+    let code = runtime.block_on(async move {
         let apppath = AppPath::new();
         let config = Config::load(&apppath).await;
         let console = Console::new_cli();
@@ -82,6 +223,9 @@ fn main() {
             false => interactive_mode(apppath, config, console).await,
         }
     });
+
+    // 把任务的返回代码作为进程的退出代码，方便脚本判断任务是否成功
+    std::process::exit(code);
 }
 
 /// 命令行模式，每次只运行一个命令
@@ -118,23 +262,55 @@ async fn interactive_mode(apppath: AppPath, config: Config, console: Console) ->
 }
 
 async fn handle_command(apppath: &AppPath, config: &Config, console: &Console, cmd: CommandLineInterface) -> i32 {
+    console.set_stderr(cmd.json);
+    console.take_output();
+
     let result = match cmd.command {
-        Commands::Pack { version_label } => task_pack(version_label, "".to_owned(), apppath, config, console),
-        Commands::Check => task_check(apppath, config, console),
-        Commands::Combine => task_combine(apppath, config, console),
+        Commands::Pack { version_label, channel, dry_run: false } => task_pack(version_label, "".to_owned(), channel, apppath, config, console),
+        Commands::Pack { version_label, channel, dry_run: true } => task_pack_dry_run(version_label, channel, apppath, config, console),
+        Commands::PackRollback { target_label, version_label, channel } => task_pack_rollback(target_label, version_label, "".to_owned(), channel, apppath, config, console),
+        Commands::Promote { version_label, from, to } => task_promote(version_label, from, to, apppath, config, console),
+        Commands::Yank { version_label, channel, force } => task_yank(version_label, channel, force, apppath, config, console),
+        Commands::Check { channel } => task_check(channel, apppath, config, console),
+        Commands::CheckIgnore { paths } => task_check_ignore(paths, apppath, config, console),
+        Commands::Export { version_label, dest, channel } => task_export(version_label, dest, channel, apppath, config, console),
+        Commands::DiffVersions { older_label, newer_label, channel } => task_diff_versions(older_label, newer_label, channel, apppath, config, console),
+        Commands::Combine { keep_latest, until, squash, keep_labels } => {
+            let range = match (keep_latest, until) {
+                (Some(n), _) => CombineRange::KeepLatest(n),
+                (_, Some(label)) => CombineRange::Until(label),
+                _ => CombineRange::All,
+            };
+
+            task_combine(range, squash, keep_labels, apppath, config, console)
+        },
         Commands::Test => task_test(apppath, config, console),
-        Commands::Revert => task_revert(apppath, config, console),
+        Commands::Revert { channel } => task_revert(channel, apppath, config, console),
+        Commands::Keygen { force } => task_keygen(force, apppath, config, console),
+        Commands::FullInstall => task_full_install(apppath, config, console),
+        Commands::Inspect { file } => task_inspect(file, apppath, config, console),
         Commands::Serve => {
             start_builtin_server(config.clone(), apppath.clone()).await;
 
-            0
+            Ok(())
         },
         Commands::Webui => {
             serve_web(apppath.clone(), config.clone()).await;
 
-            0
+            Ok(())
         },
     };
 
-    result as i32
+    // json模式下，标准输出上只有这一个Json对象
+    if cmd.json {
+        let output = serde_json::json!({
+            "code": if result.is_ok() { 0 } else { 1 },
+            "result": console.take_output(),
+            "error": result.as_ref().err().map(|e| e.to_json()),
+        });
+
+        println!("{}", serde_json::to_string_pretty(&output).unwrap());
+    }
+
+    report_result(result, console) as i32
 }
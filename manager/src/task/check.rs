@@ -3,33 +3,49 @@ use std::rc::Weak;
 use crate::app_path::AppPath;
 use crate::config::Config;
 use crate::core::data::index_file::IndexFile;
+use crate::core::rule_filter::RuleFilter;
 use crate::diff::diff::Diff;
 use crate::diff::disk_file::DiskFile;
 use crate::diff::history_file::HistoryFile;
+use crate::error::ManagerError;
 use crate::web::log::Console;
 
-pub fn task_check(apppath: &AppPath, config: &Config, console: &Console) -> u8 {
+pub fn task_check(channel: String, apppath: &AppPath, config: &Config, console: &Console) -> Result<(), ManagerError> {
     // 读取现有更新包，并复现在history上
-    let index_file = IndexFile::load_from_file(&apppath.index_file);
+    let index_file = IndexFile::load_channel(apppath, &channel)?;
 
     console.log_debug("正在读取数据");
 
     let mut history = HistoryFile::new_empty();
 
-    for (_index, meta) in index_file.read_all_metas(&apppath.public_dir) {
+    for (_index, meta) in index_file.read_all_metas(&apppath.public_dir)? {
         history.replay_operations(&meta);
     }
 
     // 对比文件
     console.log_debug("正在扫描文件更改");
 
-    let exclude_rules = &config.core.exclude_rules;
-    let disk_file = DiskFile::new(apppath.workspace_dir.clone(), Weak::new());
-    let diff = Diff::diff(&disk_file, &history, Some(&exclude_rules));
+    let filter = RuleFilter::load(&config.core, &apppath.workspace_dir)?;
+    let protected = RuleFilter::from_globs(&config.core.protected_paths, "配置文件protected-paths")?;
+    let disk_file = DiskFile::new(apppath.workspace_dir.clone(), Weak::new(), config.core.hash_algorithm, config.core.symlink_mode)?;
+    disk_file.check_files(&filter)?;
+    let mut diff = Diff::diff(&disk_file, &history, Some(&filter));
+    diff.protect(&protected);
 
     // 输出文件差异
     console.log_info(format!("{:#?}", diff));
     console.log_info(format!("{}", diff));
 
-    0
+    for path in diff.protected_deletions() {
+        console.log_warning(format!("受保护的路径不会在客户端上被删除: {}", path));
+    }
+
+    console.set_output(serde_json::json!({
+        "channel": channel,
+        "has_diff": diff.has_diff(),
+        "diff": diff.to_json(),
+        "protected_deletions": diff.protected_deletions(),
+    }));
+
+    Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,58 @@
+use crate::app_path::AppPath;
+use crate::config::Config;
+use crate::core::rule_filter::RuleFilter;
+use crate::error::ManagerError;
+use crate::web::log::Console;
+
+/// 检查工作空间里的这些路径会不会被排除，以及是被哪条规则排除的，类似于git check-ignore命令
+///
+/// 路径相对于工作空间目录，以`/`结尾的路径视为目录。路径不存在时也可以检查
+pub fn task_check_ignore(paths: Vec<String>, apppath: &AppPath, config: &Config, console: &Console) -> Result<(), ManagerError> {
+    if paths.is_empty() {
+        return Err(ManagerError::task("需要指定至少一个要检查的路径"));
+    }
+
+    let filter = RuleFilter::load(&config.core, &apppath.workspace_dir)?;
+
+    let mut results = Vec::new();
+
+    for raw in paths {
+        let normalized = raw.replace('\\', "/");
+        let path = normalized.trim_start_matches("./").trim_matches('/');
+
+        if path.is_empty() {
+            return Err(ManagerError::task(format!("路径不正确: {}", raw)));
+        }
+
+        let is_dir = normalized.ends_with('/') || apppath.workspace_dir.join(path).is_dir();
+
+        let result = match filter.explain(path, is_dir) {
+            Some((matched, rule)) => {
+                match matched == path {
+                    true => console.log_info(format!("{} 被排除，规则: {}（来自{}）", path, rule.raw, rule.source)),
+                    false => console.log_info(format!("{} 被排除，因为所在的目录 {} 被排除，规则: {}（来自{}）", path, matched, rule.raw, rule.source)),
+                }
+
+                serde_json::json!({ "path": path, "excluded": true, "matched": matched, "rule": rule.raw, "source": rule.source, "negated": false })
+            },
+            None => match filter.find_rule(path, is_dir) {
+                Some(rule) => {
+                    console.log_info(format!("{} 没有被排除，被取反规则重新包含，规则: {}（来自{}）", path, rule.raw, rule.source));
+
+                    serde_json::json!({ "path": path, "excluded": false, "matched": path, "rule": rule.raw, "source": rule.source, "negated": true })
+                },
+                None => {
+                    console.log_info(format!("{} 没有被排除", path));
+
+                    serde_json::json!({ "path": path, "excluded": false, "matched": null, "rule": null, "source": null, "negated": false })
+                },
+            },
+        };
+
+        results.push(result);
+    }
+
+    console.set_output(serde_json::json!({ "paths": results }));
+
+    Ok(())
+}
@@ -1,59 +1,111 @@
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::collections::LinkedList;
+use std::rc::Rc;
 use std::rc::Weak;
 
 use crate::app_path::AppPath;
+use crate::app_path::STABLE_CHANNEL;
 use crate::config::Config;
 use crate::core::archive_tester::ArchiveTester;
+use crate::core::compression::Compression;
 use crate::core::data::index_file::IndexFile;
 use crate::core::data::index_file::VersionIndex;
 use crate::core::data::version_meta::FileChange;
+use crate::core::data::version_meta::VersionMeta;
 use crate::core::data::version_meta_group::VersionMetaGroup;
+use crate::core::file_hash::calculate_archive_hash;
+use crate::core::packed_file::read_packed_file;
+use crate::core::signing::Signer;
+use crate::core::signing::signature_file_of;
 use crate::core::tar_reader::TarReader;
 use crate::core::tar_writer::TarWriter;
+use crate::diff::diff::Diff;
+use crate::diff::history_file::FilePackedLoc;
 use crate::diff::history_file::HistoryFile;
+use crate::error::IoContext;
+use crate::error::ManagerError;
+use crate::task::full_install::generate_full_installs;
+use crate::task::full_install::remove_unused_full_installs;
 use crate::web::log::Console;
 
 pub const COMBINED_FILENAME: &str = "combined.tar";
 
 /// 代表新的合并包中的某个文件数据要从哪个旧包中复制过来
 struct Location {
-    /// 所在的版本
-    pub label: String,
-
-    /// 所在的tar包的文件名
-    pub filename: String,
-
     /// 最原始的文件路径（不受后续移动操作的影响）
     pub path: String,
 
-    /// tar包中的文件偏移
-    pub offset: u64,
+    /// 文件数据在旧包中的位置，版本号也记录在这里面
+    pub loc: FilePackedLoc,
+}
+
+/// 要合并哪些版本。合并的总是从第一个版本开始的连续的若干个版本
+pub enum CombineRange {
+    /// 合并所有的版本
+    All,
+
+    /// 保留最新的N个版本不合并，让只落后几个版本的客户端不用去读取巨大的合并包
+    KeepLatest(usize),
 
-    /// 数据的长度
-    pub len: u64,
+    /// 合并到指定的版本为止（包括这个版本）
+    Until(String),
 }
 
-pub fn task_combine(apppath: &AppPath, _config: &Config, console: &Console) -> u8 {
-    let index_file = IndexFile::load_from_file(&apppath.index_file);
+/// 合并更新包
+/// 
+/// 如果指定了`squash`，合并包里的所有历史操作会被压缩成一个基准版本，只保留最终的文件状态，
+/// 这个基准版本使用合并范围内最后一个版本的版本号。此时如果还指定了`keep_labels`，
+/// 其它版本的版本号和更新记录也会作为不包含任何文件操作的空版本保留在元数据组里，仅供查阅。
+/// 基准版本里没有删除和移动操作，无法从这些中间版本上更新过来，所以它们不会出现在索引文件里，
+/// 停留在这些版本上的客户端需要重新完整安装
+pub fn task_combine(range: CombineRange, squash: bool, keep_labels: bool, apppath: &AppPath, config: &Config, console: &Console) -> Result<(), ManagerError> {
+    let index_file = IndexFile::load_from_file(&apppath.index_file)?;
+
+    // 计算出要合并的版本数量
+    let combine_count = match range {
+        CombineRange::All => index_file.len(),
+        CombineRange::KeepLatest(n) => index_file.len().saturating_sub(n),
+        CombineRange::Until(label) => match (&index_file).into_iter().position(|e| e.label == label) {
+            Some(position) => position + 1,
+            None => return Err(ManagerError::task(format!("版本号不存在: {}", label))),
+        },
+    };
 
     // 执行合并前需要先测试一遍
     console.log_debug("正在执行合并前的解压测试");
     let mut tester = ArchiveTester::new();
-    for (index, meta) in index_file.read_all_metas(&apppath.public_dir) {
+    for (index, meta) in index_file.read_all_metas(&apppath.public_dir)? {
         tester.feed_version(apppath.public_dir.join(&index.filename), &meta);
     }
-    tester.finish(|e| console.log_debug(format!("{}/{} 正在测试 {} 的 {} ({}+{})", e.index, e.total, e.label, e.path, e.offset, e.len))).unwrap();
+    tester.finish(|e| console.log_debug(format!("{}/{} 正在测试 {} 的 {} ({}+{})", e.index, e.total, e.label, e.path, e.offset, e.len)))?;
     console.log_debug("测试通过，开始更新包合并流程");
 
     // 开始合并流程
     let versions_to_be_combined = (&index_file).into_iter()
+        .take(combine_count)
         .filter(|e| e.filename != COMBINED_FILENAME)
         .collect::<LinkedList<_>>();
 
     if versions_to_be_combined.is_empty() {
-        console.log_info("没有更新包可以合并");
-        return 1;
+        return Err(ManagerError::task("没有更新包可以合并"));
+    }
+
+    // 合并包里只存储了合并范围内最后一个版本的文件数据，所以其它频道的最新版本不能停留在合并范围的中间，
+    // 不然这些频道的客户端就会读取到不存在的中间版本的数据
+    let last_combined = index_file[combine_count - 1].label.to_owned();
+
+    for channel in apppath.channels()?.into_iter().filter(|e| e != STABLE_CHANNEL) {
+        let channel_index = IndexFile::load_from_file(&apppath.index_file_of(&channel))?;
+
+        let common = (&channel_index).into_iter()
+            .zip(&index_file)
+            .take_while(|(a, b)| a.label == b.label)
+            .count();
+
+        if common < combine_count {
+            return Err(ManagerError::task(format!("{}频道没有包含版本 {} 及之前的所有版本，请先推送版本到这个频道，或者缩小合并范围", channel, last_combined)));
+        }
     }
 
     console.log_debug("正在读取数据");
@@ -65,7 +117,7 @@ pub fn task_combine(apppath: &AppPath, _config: &Config, console: &Console) -> u
     let mut meta_group = VersionMetaGroup::new();
 
     // 读取现有更新包，并复现在history上
-    for (index, meta) in index_file.read_all_metas(&apppath.public_dir) {
+    for (_index, meta) in index_file.read_all_metas(&apppath.public_dir)?.into_iter().take(combine_count) {
         if meta_group.contains_meta(&meta.label) {
             continue;
         }
@@ -75,13 +127,31 @@ pub fn task_combine(apppath: &AppPath, _config: &Config, console: &Console) -> u
         // 记录所有文件的数据和来源
         for change in &meta.changes {
             match change {
-                FileChange::UpdateFile { path, offset, len, .. } => {
+                FileChange::UpdateFile { path, offset, compression, compressed_len, .. } |
+                FileChange::CreateIfMissing { path, offset, compression, compressed_len, .. } => {
+                    data_locations.insert(path.to_owned(), Location {
+                        path: path.to_owned(),
+                        loc: FilePackedLoc {
+                            version: meta.label.clone(),
+                            offset: *offset,
+                            length: *compressed_len,
+                            base: None,
+                            compression: *compression,
+                        },
+                    });
+                },
+                FileChange::PatchFile { path, offset, patch_len, .. } => {
+                    let base = data_locations.remove(path).unwrap().loc;
+
                     data_locations.insert(path.to_owned(), Location {
-                        label: meta.label.clone(),
-                        filename: index.filename.to_owned(),
                         path: path.to_owned(),
-                        offset: *offset,
-                        len: *len,
+                        loc: FilePackedLoc {
+                            version: meta.label.clone(),
+                            offset: *offset,
+                            length: *patch_len,
+                            base: Some(Rc::new(base)),
+                            compression: Compression::None,
+                        },
                     });
                 },
                 FileChange::DeleteFile { path } => {
@@ -103,69 +173,210 @@ pub fn task_combine(apppath: &AppPath, _config: &Config, console: &Console) -> u
     // 生成新的合并包
     let temp_public = apppath.public_dir.join(".temp");
     
-    if !std::fs::exists(&temp_public).unwrap() {
-        std::fs::create_dir(&temp_public).unwrap();
+    if !std::fs::exists(&temp_public).with_path(&temp_public)? {
+        std::fs::create_dir(&temp_public).with_path(&temp_public)?;
     }
     
     let new_tar_file = temp_public.join("combined.tar");
-    let mut writer = TarWriter::new(&new_tar_file);
+    let mut writer = TarWriter::new(&new_tar_file)?;
+
+    let archive_of = |label: &str| index_file.archive_of(&apppath.public_dir, label);
+
+    // 记录每个文件的数据在合并包里是怎么存储的，key: 文件路径_版本号，value: (压缩算法, 存储的长度)
+    let mut stored_as = HashMap::<String, (Compression, u64)>::new();
 
     // 写入每个版本里的所有文件数据
-    for (_, loc) in &data_locations {
-        // 读取原tar包中的文件，然后复制到合并包中
-        let mut reader = TarReader::new(apppath.public_dir.join(&loc.filename));
-        let read = reader.open_file(loc.offset, loc.len);
-        writer.add_file(read, loc.len, &loc.path, &loc.label);
+    for (current_path, loc) in &data_locations {
+        let label = &loc.loc.version;
+
+        // 压缩历史时，所有文件都归属于基准版本，并且使用文件当前的路径
+        let (name, version) = match squash {
+            true => (current_path, &last_combined),
+            false => (&loc.path, label),
+        };
+
+        match &loc.loc.base {
+            // 读取原tar包中的文件，然后原样复制到合并包中（压缩过的数据也保持压缩状态）
+            None => {
+                let mut reader = TarReader::new(archive_of(label)?)?;
+                let read = reader.open_file(loc.loc.offset, loc.loc.length)?;
+                writer.add_file(read, loc.loc.length, name, version)?;
+                stored_as.insert(format!("{}_{}", name, version), (loc.loc.compression, loc.loc.length));
+            },
+
+            // 差异补丁所基于的旧文件数据在合并后就不存在了，所以需要还原成完整数据，再按配置重新压缩后写入
+            Some(_) => {
+                let data = read_packed_file(&loc.loc, &archive_of)?;
+                let (compression, stored) = config.core.compression.compress_if_smaller(data, config.core.compression_threshold);
+
+                writer.add_file(std::io::Cursor::new(&stored), stored.len() as u64, name, version)?;
+                stored_as.insert(format!("{}_{}", name, version), (compression, stored.len() as u64));
+            },
+        }
+    }
+
+    // 合并包里已经不存在差异补丁了，所以要把所有补丁操作都换成普通的文件更新操作
+    for meta in &mut meta_group {
+        for change in meta.changes.iter_mut() {
+            if let FileChange::PatchFile { path, hash, len, modified, mode, offset, .. } = change {
+                // 中间版本的数据是不存储的，所以这些数据只要格式正确就行
+                let (compression, compressed_len) = stored_as.get(&format!("{}_{}", path, meta.label))
+                    .copied()
+                    .unwrap_or((Compression::None, *len));
+
+                *change = FileChange::UpdateFile {
+                    path: path.to_owned(),
+                    hash: hash.to_owned(),
+                    len: *len,
+                    modified: *modified,
+                    mode: *mode,
+                    offset: *offset,
+                    compression,
+                    compressed_len,
+                };
+            }
+        }
+    }
+
+    let version_count = meta_group.0.len();
+
+    // 压缩历史，用最终的文件状态生成一个基准版本来代替所有的历史操作
+    if squash {
+        console.log_debug("正在压缩历史");
+
+        let empty = HistoryFile::new_empty();
+        let diff = Diff::diff(&history, &empty, None);
+
+        // 按需创建的文件在压缩后也要保持原来的语义，不然会覆盖掉玩家修改过的文件
+        let mut changes = diff.to_file_changes_with(|f| f.is_create_if_missing());
+
+        for change in changes.iter_mut() {
+            if let FileChange::UpdateFile { path, compression, compressed_len, .. } | FileChange::CreateIfMissing { path, compression, compressed_len, .. } = change {
+                (*compression, *compressed_len) = stored_as[&format!("{}_{}", path, last_combined)];
+            }
+        }
+
+        let mut squashed = VersionMetaGroup::new();
+
+        if keep_labels {
+            for meta in &meta_group {
+                if meta.label != last_combined {
+                    squashed.add_meta(VersionMeta::new(meta.label.to_owned(), meta.logs.to_owned(), LinkedList::new()));
+                }
+            }
+        }
+
+        let logs = meta_group.find_meta(&last_combined).unwrap().logs.to_owned();
+        squashed.add_meta(VersionMeta::new(last_combined.to_owned(), logs, changes));
+
+        meta_group = squashed;
     }
 
     console.log_debug("正在更新元数据");
 
     // 写入元数据
-    let version_count = meta_group.0.len();
-    let meta_loc = writer.finish(meta_group);
+    let signer = Signer::load(apppath)?;
+    let meta_loc = writer.finish(meta_group, signer.as_ref())?;
+    let combined_hash = calculate_archive_hash(&new_tar_file)?;
 
     // 更新索引文件
     let new_index_filepath = temp_public.join("index.json");
     let mut new_index = IndexFile::new();
-    for (index, _meta) in index_file.read_all_metas(&apppath.public_dir) {
+    let mut kept_index = IndexFile::new();
+    for (i, index) in (&index_file).into_iter().enumerate() {
+        if i >= combine_count {
+            kept_index.add(index.clone());
+            continue;
+        }
+
+        // 压缩历史后只有基准版本可以作为更新起点，保留下来的版本号只存在于元数据组里
+        if squash && index.label != last_combined {
+            continue;
+        }
+
         new_index.add(VersionIndex {
             label: index.label.to_owned(),
             filename: COMBINED_FILENAME.to_owned(),
             offset: meta_loc.offset,
             len: meta_loc.length,
-            hash: "no hash".to_owned(),
+            hash: combined_hash.to_owned(),
+            signature: meta_loc.signature.clone(),
+            full_install: index.full_install.clone(),
         })
     }
-    new_index.save(&new_index_filepath);
 
-    // 测试合并包
+    // 测试合并包，合并包这时还在临时目录里，而没有参与合并的更新包还在原来的位置
     let mut tester = ArchiveTester::new();
-    for (_index, meta) in new_index.read_all_metas(&temp_public) {
+    for (_index, meta) in new_index.read_all_metas(&temp_public)? {
         tester.feed_version(&new_tar_file, &meta);
     }
-    tester.finish(|e| console.log_debug(format!("{}/{} 正在测试 {} 的 {} ({}+{})", e.index, e.total, e.label, e.path, e.offset, e.len))).unwrap();
+    for (index, meta) in kept_index.read_all_metas(&apppath.public_dir)? {
+        tester.feed_version(apppath.public_dir.join(&index.filename), &meta);
+    }
+    tester.finish(|e| console.log_debug(format!("{}/{} 正在测试 {} 的 {} ({}+{})", e.index, e.total, e.label, e.path, e.offset, e.len)))?;
+
+    let combined_count = new_index.len();
+
+    for index in kept_index {
+        new_index.add(index);
+    }
+    new_index.save(&new_index_filepath, signer.as_ref())?;
     
     // 合并回原包
     // 1.移动索引文件
-    std::fs::remove_file(&apppath.index_file).unwrap();
-    std::fs::rename(&new_index_filepath, &apppath.index_file).unwrap();
+    std::fs::remove_file(&apppath.index_file).with_path(&apppath.index_file)?;
+    std::fs::rename(&new_index_filepath, &apppath.index_file).with_path(&apppath.index_file)?;
+
+    let _ = std::fs::remove_file(signature_file_of(&apppath.index_file));
+
+    if signer.is_some() {
+        let signature_file = signature_file_of(&apppath.index_file);
+
+        std::fs::rename(signature_file_of(&new_index_filepath), &signature_file).with_path(&signature_file)?;
+    }
     
-    // 2.移动更新包文件
+    // 2.其它频道里的这些版本也要改为指向合并包
+    for channel in apppath.channels()?.into_iter().filter(|e| e != STABLE_CHANNEL) {
+        let channel_index_file = apppath.index_file_of(&channel);
+        let channel_index = IndexFile::load_from_file(&channel_index_file)?;
+        let mut new_channel_index = IndexFile::new();
+
+        // 前面已经检查过，每个频道的开头部分都和合并范围完全一致
+        for v in (&new_index).into_iter().take(combined_count) {
+            new_channel_index.add(v.clone());
+        }
+
+        for v in (&channel_index).into_iter().skip(combine_count) {
+            new_channel_index.add(v.clone());
+        }
+
+        new_channel_index.save(&channel_index_file, signer.as_ref())?;
+    }
+
+    // 3.移动更新包文件
     let combine_file = apppath.public_dir.join(COMBINED_FILENAME);
     
     let _ = std::fs::remove_file(&combine_file);
-    std::fs::rename(&new_tar_file, &combine_file).unwrap();
+    std::fs::rename(&new_tar_file, &combine_file).with_path(&combine_file)?;
     
-    // 3.清理多余更新包
+    // 4.清理多余更新包
     for v in &versions_to_be_combined {
-        std::fs::remove_file(apppath.public_dir.join(&v.filename)).unwrap();
+        let file = apppath.public_dir.join(&v.filename);
+
+        std::fs::remove_file(&file).with_path(&file)?;
     }
 
-    // 4.清理临时目录
+    // 5.清理临时目录
     let _ = std::fs::remove_dir(temp_public);
     
     console.log_info(format!("合并完成！一共合并了 {} 个版本", version_count));
 
+    // 频道的最新版本可能变了，需要更新完整安装包，没有开启时也要清理掉不再使用的安装包
+    match config.core.full_install {
+        true => generate_full_installs(apppath, console)?,
+        false => remove_unused_full_installs(apppath, console)?,
+    }
+
     // // 生成上传脚本
     // let context = TemplateContext {
     //     upload_files: vec![combine_file.strip_prefix(&ctx.working_dir).unwrap().to_str().unwrap().to_owned()],
@@ -179,5 +390,43 @@ pub fn task_combine(apppath: &AppPath, _config: &Config, console: &Console) -> u
 
     // generate_upload_script(context, ctx, "combined");
 
-    0
-}
\ No newline at end of file
+    Ok(())
+}
+
+/// 获取一组元数据的最终文件状态里，每个文件的数据所在的位置：(版本号, 偏移值, 长度)
+/// 
+/// 对于合并包来说，这些也就是合并包里实际存储了数据的位置
+pub fn final_locations<'a>(metas: impl IntoIterator<Item = &'a VersionMeta>) -> HashSet<(String, u64, u64)> {
+    let mut history = HistoryFile::new_empty();
+
+    for meta in metas {
+        history.replay_operations(meta);
+    }
+
+    let empty = HistoryFile::new_empty();
+    let diff = Diff::diff(&history, &empty, None);
+
+    diff.added_files.iter()
+        .map(|f| f.file_location())
+        .map(|loc| (loc.version.to_owned(), loc.offset, loc.length))
+        .collect()
+}
+
+/// 检查`loc`的数据，以及差异补丁所基于的旧数据是不是都还存在，返回第一个数据在合并时已经被丢弃的版本号
+/// 
+/// `stored`是合并包里所有版本的[`final_locations`]，不在合并包里的数据总是存在的
+pub fn find_discarded<'a>(loc: &'a FilePackedLoc, index_file: &IndexFile, stored: &HashSet<(String, u64, u64)>) -> Option<&'a str> {
+    let mut loc = Some(loc);
+
+    while let Some(l) = loc {
+        let in_combined = index_file.find(&l.version).is_some_and(|e| e.filename == COMBINED_FILENAME);
+
+        if in_combined && !stored.contains(&(l.version.to_owned(), l.offset, l.length)) {
+            return Some(&l.version);
+        }
+
+        loc = l.base.as_deref();
+    }
+
+    None
+}
@@ -0,0 +1,97 @@
+use std::ops::Deref;
+
+use crate::app_path::AppPath;
+use crate::app_path::is_valid_channel_name;
+use crate::config::Config;
+use crate::core::data::index_file::IndexFile;
+use crate::diff::abstract_file::AbstractFile;
+use crate::diff::diff::Diff;
+use crate::diff::history_file::HistoryFile;
+use crate::error::ManagerError;
+use crate::web::log::Console;
+
+/// 对比同一个频道里两个已发布版本的文件差异，输出从`older_label`更新到`newer_label`时会有哪些文件变化
+pub fn task_diff_versions(older_label: String, newer_label: String, channel: String, apppath: &AppPath, _config: &Config, console: &Console) -> Result<(), ManagerError> {
+    if !is_valid_channel_name(&channel) {
+        return Err(ManagerError::task(format!("频道名不合法: {}", channel)));
+    }
+
+    let index_file = IndexFile::load_channel(apppath, &channel)?;
+
+    let mut positions = Vec::<usize>::new();
+
+    for label in [&older_label, &newer_label] {
+        match (&index_file).into_iter().position(|e| &e.label == label) {
+            Some(position) => positions.push(position),
+            None => return Err(ManagerError::task(format!("{}频道里没有这个版本: {}", channel, label))),
+        }
+    }
+
+    if positions[0] > positions[1] {
+        return Err(ManagerError::task(format!("版本 {} 比版本 {} 更新，请把旧版本放在前面", older_label, newer_label)));
+    }
+
+    // 分别推演出两个版本的文件状态
+    console.log_debug("正在读取数据");
+
+    let mut older = HistoryFile::new_empty();
+    let mut newer = HistoryFile::new_empty();
+    let mut older_reached = false;
+    let mut newer_reached = false;
+
+    for (_index, meta) in index_file.read_all_metas(&apppath.public_dir)? {
+        if !older_reached {
+            older.replay_operations(&meta);
+        }
+
+        if !newer_reached {
+            newer.replay_operations(&meta);
+        }
+
+        older_reached |= meta.label == older_label;
+        newer_reached |= meta.label == newer_label;
+
+        if older_reached && newer_reached {
+            break;
+        }
+    }
+
+    let diff = Diff::diff(&newer, &older, None);
+
+    // 输出文件差异
+    console.log_info(format!("{} -> {}（{}频道）", older_label, newer_label, channel));
+
+    for f in &diff.missing_files {
+        console.log_info(format!("删除文件: {}（{} 字节）", f.path().deref(), f.len()));
+    }
+
+    for f in &diff.missing_folders {
+        console.log_info(format!("删除目录: {}", f.path().deref()));
+    }
+
+    for f in &diff.added_folders {
+        console.log_info(format!("创建目录: {}", f.path().deref()));
+    }
+
+    for f in &diff.added_files {
+        console.log_info(format!("新增文件: {}（{} 字节）", f.path().deref(), f.len()));
+    }
+
+    for f in &diff.modified_files {
+        let old_len = older.find(&f.path()).map(|e| e.len()).unwrap_or(0);
+
+        console.log_info(format!("修改文件: {}（{} -> {} 字节）", f.path().deref(), old_len, f.len()));
+    }
+
+    for (from, to) in &diff.renamed_files {
+        console.log_info(format!("移动文件: {} -> {}（{} 字节）", from.path().deref(), to.path().deref(), to.len()));
+    }
+
+    for f in &diff.added_symlinks {
+        console.log_info(format!("创建链接: {} -> {}", f.path().deref(), f.symlink_target().unwrap_or_default()));
+    }
+
+    console.log_info(format!("{}", diff));
+
+    Ok(())
+}
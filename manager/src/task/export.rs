@@ -0,0 +1,89 @@
+use std::ops::Deref;
+use std::path::PathBuf;
+
+use crate::app_path::AppPath;
+use crate::app_path::is_valid_channel_name;
+use crate::config::Config;
+use crate::core::data::index_file::IndexFile;
+use crate::diff::abstract_file::AbstractFile;
+use crate::diff::diff::Diff;
+use crate::diff::history_file::HistoryFile;
+use crate::error::IoContext;
+use crate::error::ManagerError;
+use crate::task::combine::COMBINED_FILENAME;
+use crate::task::combine::final_locations;
+use crate::task::combine::find_discarded;
+use crate::task::revert::restore_file;
+use crate::task::revert::restore_symlink;
+use crate::web::log::Console;
+
+/// 把某个已发布版本的完整文件状态导出到`dest`目录下，得到的文件和停留在这个版本的客户端上的文件完全一样
+///
+/// 为了避免覆盖掉有用的文件，`dest`必须是一个不存在的目录或者空目录
+pub fn task_export(label: String, dest: PathBuf, channel: String, apppath: &AppPath, _config: &Config, console: &Console) -> Result<(), ManagerError> {
+    if !is_valid_channel_name(&channel) {
+        return Err(ManagerError::task(format!("频道名不合法: {}", channel)));
+    }
+
+    let index_file = IndexFile::load_channel(apppath, &channel)?;
+
+    if !index_file.contains(&label) {
+        return Err(ManagerError::task(format!("{}频道里没有这个版本: {}", channel, label)));
+    }
+
+    if dest.exists() && dest.read_dir().with_path(&dest)?.next().is_some() {
+        return Err(ManagerError::task(format!("导出目录不是空的: {}", dest.display())));
+    }
+
+    // 推演出这个版本的文件状态
+    console.log_debug("正在读取数据");
+
+    let metas = index_file.read_all_metas(&apppath.public_dir)?;
+    let mut history = HistoryFile::new_empty();
+
+    for (_index, meta) in &metas {
+        history.replay_operations(meta);
+
+        if meta.label == label {
+            break;
+        }
+    }
+
+    // 和一个空目录对比，就能拿到这个版本里所有的目录和文件
+    let diff = Diff::diff(&history, &HistoryFile::new_empty(), None);
+
+    // 合并包里只存储了最终文件状态的数据，中间版本的数据在合并时就已经丢掉了，导出前要先检查一遍
+    let stored = final_locations(metas.iter().filter(|e| e.0.filename == COMBINED_FILENAME).map(|e| &e.1));
+
+    for f in &diff.added_files {
+        if let Some(version) = find_discarded(f.file_location(), &index_file, &stored) {
+            return Err(ManagerError::task(format!("文件 {} 在版本 {} 的数据在合并时已经被丢弃，无法导出版本 {}", f.path().deref(), version, label)));
+        }
+    }
+
+    console.log_debug(format!("正在导出 {} 个文件到 {}", diff.added_files.len(), dest.display()));
+
+    std::fs::create_dir_all(&dest).with_path(&dest)?;
+
+    for mk in &diff.added_folders {
+        let dir = dest.join(mk.path().deref());
+
+        std::fs::create_dir_all(&dir).with_path(&dir)?;
+    }
+
+    let archive_of = |label: &str| index_file.archive_of(&apppath.public_dir, label);
+
+    for (i, f) in diff.added_files.iter().enumerate() {
+        console.log_debug(format!("{}/{} 正在导出 {}", i, diff.added_files.len(), f.path().deref()));
+
+        restore_file(f, &dest, &archive_of)?;
+    }
+
+    for link in &diff.added_symlinks {
+        restore_symlink(link, &dest)?;
+    }
+
+    console.log_info(format!("版本 {} 已经导出到 {}", label, dest.display()));
+
+    Ok(())
+}
@@ -0,0 +1,166 @@
+use std::collections::HashSet;
+use std::ops::Deref;
+use std::time::UNIX_EPOCH;
+
+use flate2::write::GzEncoder;
+use tar::EntryType;
+
+use crate::app_path::AppPath;
+use crate::config::Config;
+use crate::core::data::index_file::FullInstall;
+use crate::core::data::index_file::IndexFile;
+use crate::core::file_hash::calculate_archive_hash;
+use crate::core::packed_file::open_packed_file;
+use crate::core::signing::Signer;
+use crate::diff::abstract_file::AbstractFile;
+use crate::diff::diff::Diff;
+use crate::diff::history_file::HistoryFile;
+use crate::error::IoContext;
+use crate::error::ManagerError;
+use crate::web::log::Console;
+
+/// 完整安装包的文件名前缀，完整的文件名是`full-版本号.tar.gz`
+pub const FULL_INSTALL_PREFIX: &str = "full-";
+
+/// 为每个频道的最新版本生成完整安装包
+pub fn task_full_install(apppath: &AppPath, _config: &Config, console: &Console) -> Result<(), ManagerError> {
+    generate_full_installs(apppath, console)?;
+
+    console.log_info("完整安装包已生成");
+
+    Ok(())
+}
+
+/// 为每个频道的最新版本生成完整安装包，并记录到索引文件里
+///
+/// 安装包的数据是从现有的更新包里读取的，不会读取工作空间目录。已经生成过的安装包不会重复生成，
+/// 旧版本的安装包会从索引文件里去掉，不再被任何频道使用时会被删除
+pub fn generate_full_installs(apppath: &AppPath, console: &Console) -> Result<(), ManagerError> {
+    let signer = Signer::load(apppath)?;
+
+    for channel in apppath.channels()? {
+        let index_filepath = apppath.index_file_of(&channel);
+        let mut index_file = IndexFile::load_from_file(&index_filepath)?;
+
+        // 还没有任何版本的频道不需要安装包
+        if index_file.len() == 0 {
+            continue;
+        }
+
+        let head = &index_file[index_file.len() - 1];
+        let head_label = head.label.to_owned();
+        let filename = format!("{}{}.tar.gz", FULL_INSTALL_PREFIX, head_label);
+        let file = apppath.public_dir.join(&filename);
+
+        let up_to_date = head.full_install.as_ref().is_some_and(|e| e.filename == filename) && file.exists();
+
+        if !up_to_date {
+            // 同一个版本的安装包只需要生成一次，其它频道直接共用
+            if !file.exists() {
+                console.log_debug(format!("正在生成完整安装包: {}", filename));
+
+                build_full_install(&index_file, &filename, apppath)?;
+            }
+
+            let len = std::fs::metadata(&file).with_path(&file)?.len();
+            let hash = calculate_archive_hash(&file)?;
+
+            index_file.find_mut(&head_label).unwrap().full_install = Some(FullInstall { filename: filename.to_owned(), len, hash });
+        }
+
+        // 只有最新版本才提供安装包
+        let mut changed = !up_to_date;
+
+        for v in &mut index_file {
+            if v.label != head_label && v.full_install.take().is_some() {
+                changed = true;
+            }
+        }
+
+        if changed {
+            index_file.save(&index_filepath, signer.as_ref())?;
+        }
+    }
+
+    remove_unused_full_installs(apppath, console)
+}
+
+/// 删除public目录下不再被任何频道的索引文件使用的完整安装包
+///
+/// 即使没有开启`full-install`选项，之前手动生成的安装包也可能在删除或者合并版本之后变成没用的文件，所以总是需要清理
+pub fn remove_unused_full_installs(apppath: &AppPath, console: &Console) -> Result<(), ManagerError> {
+    let mut referenced = HashSet::<String>::new();
+
+    for channel in apppath.channels()? {
+        let index_file = IndexFile::load_from_file(&apppath.index_file_of(&channel))?;
+
+        referenced.extend((&index_file).into_iter().filter_map(|e| e.full_install.as_ref()).map(|e| e.filename.to_owned()));
+    }
+
+    for entry in std::fs::read_dir(&apppath.public_dir).with_path(&apppath.public_dir)? {
+        let entry = entry.with_path(&apppath.public_dir)?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+
+        if name.starts_with(FULL_INSTALL_PREFIX) && name.ends_with(".tar.gz") && !referenced.contains(&name) {
+            console.log_debug(format!("删除旧的完整安装包: {}", name));
+
+            std::fs::remove_file(entry.path()).with_path(entry.path())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// 推演出`index_file`里最新版本的文件状态，并把所有文件写到public目录下的`filename`里
+fn build_full_install(index_file: &IndexFile, filename: &str, apppath: &AppPath) -> Result<(), ManagerError> {
+    let mut history = HistoryFile::new_empty();
+
+    for (_index, meta) in index_file.read_all_metas(&apppath.public_dir)? {
+        history.replay_operations(&meta);
+    }
+
+    // 和一个空目录对比，就能拿到最新版本里所有的目录和文件
+    let diff = Diff::diff(&history, &HistoryFile::new_empty(), None);
+
+    // 先写到临时文件里，写完了再改名，避免留下写了一半的安装包
+    let temp_file = apppath.public_dir.join(format!("{}.tmp", filename));
+    let open = std::fs::File::create(&temp_file).with_path(&temp_file)?;
+    let mut builder = tar::Builder::new(GzEncoder::new(open, flate2::Compression::default()));
+
+    let archive_of = |label: &str| index_file.archive_of(&apppath.public_dir, label);
+
+    for dir in &diff.added_folders {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(EntryType::Directory);
+        header.set_size(0);
+        header.set_mode(0o755);
+
+        builder.append_data(&mut header, dir.path().deref(), std::io::empty()).with_path(&temp_file)?;
+    }
+
+    for f in &diff.added_files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(f.len());
+        header.set_mode(f.mode().unwrap_or(0o644));
+        header.set_mtime(f.modified().duration_since(UNIX_EPOCH).map(|e| e.as_secs()).unwrap_or(0));
+
+        let data = open_packed_file(f.file_location(), &archive_of)?;
+
+        builder.append_data(&mut header, f.path().deref(), data).with_path(&temp_file)?;
+    }
+
+    for link in &diff.added_symlinks {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(EntryType::Symlink);
+        header.set_size(0);
+        header.set_mode(0o777);
+
+        builder.append_link(&mut header, link.path().deref(), link.symlink_target().unwrap()).with_path(&temp_file)?;
+    }
+
+    builder.into_inner().with_path(&temp_file)?.finish().with_path(&temp_file)?;
+
+    let file = apppath.public_dir.join(filename);
+
+    std::fs::rename(&temp_file, &file).with_path(&file)
+}
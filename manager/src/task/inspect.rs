@@ -0,0 +1,140 @@
+use crate::app_path::AppPath;
+use crate::config::Config;
+use crate::core::data::index_file::IndexFile;
+use crate::core::data::version_meta::FileChange;
+use crate::core::tar_reader::TarEntry;
+use crate::core::tar_reader::TarReader;
+use crate::error::ManagerError;
+use crate::task::combine::COMBINED_FILENAME;
+use crate::task::combine::final_locations;
+use crate::web::log::Console;
+
+/// 打印一个更新包里的所有版本和文件操作，用来排查客户端读取更新包时遇到的问题
+///
+/// `file`是相对于public目录的路径，也可以是一个绝对路径
+pub fn task_inspect(file: String, apppath: &AppPath, _config: &Config, console: &Console) -> Result<(), ManagerError> {
+    let path = apppath.public_dir.join(&file);
+    let filename = path.file_name().map(|e| e.to_string_lossy().into_owned()).unwrap_or(file);
+
+    let mut reader = TarReader::new(&path)?;
+    let entries = reader.entries()?;
+
+    console.log_info(format!("更新包: {}，一共 {} 个tar条目", path.display(), entries.len()));
+
+    let mut problems = 0;
+
+    for (offset, len) in locate_metadata(&filename, &entries, apppath, console)? {
+        match check_alignment(&entries, offset, len) {
+            Some(problem) => {
+                problems += 1;
+                console.log_warning(format!("元数据: offset {}, len {}（{}）", offset, len, problem));
+            },
+            None => console.log_info(format!("元数据: offset {}, len {}", offset, len)),
+        }
+
+        let group = reader.read_metadata_group(offset, len)?;
+
+        // 合并包里只存储了最终文件状态的数据，中间版本的偏移值本来就是无效的
+        let stored = match filename == COMBINED_FILENAME {
+            true => Some(final_locations(&group)),
+            false => None,
+        };
+
+        for meta in &group {
+            console.log_info(format!("版本 {}，一共 {} 个文件操作", meta.label, meta.changes.len()));
+
+            for change in &meta.changes {
+                let (text, location) = describe_change(change);
+
+                let discarded = location.is_some_and(|(offset, len)| {
+                    stored.as_ref().is_some_and(|e| !e.contains(&(meta.label.to_owned(), offset, len)))
+                });
+
+                if discarded {
+                    console.log_info(format!("  {}（数据未存储（中间版本））", text));
+                    continue;
+                }
+
+                match location.and_then(|(offset, len)| check_alignment(&entries, offset, len)) {
+                    Some(problem) => {
+                        problems += 1;
+                        console.log_warning(format!("  {}（{}）", text, problem));
+                    },
+                    None => console.log_info(format!("  {}", text)),
+                }
+            }
+        }
+    }
+
+    match problems {
+        0 => console.log_info("所有偏移值都和tar条目对齐"),
+        _ => console.log_warning(format!("一共发现 {} 处偏移值没有和tar条目对齐", problems)),
+    }
+
+    Ok(())
+}
+
+/// 找到更新包里元数据的位置，返回所有不重复的`(offset, len)`
+///
+/// 优先使用索引文件里记录的位置，如果没有任何索引文件引用这个更新包，就扫描tar头找到最后一个`metadata.txt`
+fn locate_metadata(filename: &str, entries: &[TarEntry], apppath: &AppPath, console: &Console) -> Result<Vec<(u64, u64)>, ManagerError> {
+    let mut locations = Vec::<(u64, u64)>::new();
+
+    for channel in apppath.channels()? {
+        let index_file = IndexFile::load_channel(apppath, &channel)?;
+
+        for index in (&index_file).into_iter().filter(|e| e.filename == filename) {
+            console.log_debug(format!("{}频道的版本 {} 引用了这个更新包", channel, index.label));
+
+            if !locations.contains(&(index.offset, index.len)) {
+                locations.push((index.offset, index.len));
+            }
+        }
+    }
+
+    if !locations.is_empty() {
+        return Ok(locations);
+    }
+
+    console.log_debug("没有索引文件引用这个更新包，扫描tar头来查找元数据");
+
+    match entries.iter().rev().find(|e| e.path == "metadata.txt") {
+        Some(entry) => Ok(vec![(entry.offset, entry.len)]),
+        None => Err(ManagerError::task("更新包里找不到元数据")),
+    }
+}
+
+/// 检查一段数据是否正好对应一个tar条目，有问题时返回问题的描述
+fn check_alignment(entries: &[TarEntry], offset: u64, len: u64) -> Option<&'static str> {
+    match entries.iter().find(|e| e.offset == offset) {
+        Some(entry) if entry.len == len => None,
+        Some(_) => Some("长度和tar条目不一致"),
+        None => Some("偏移值没有对齐到任何tar条目"),
+    }
+}
+
+/// 生成一个文件操作的描述文字，如果这个操作在更新包里有数据，还会返回数据的`(offset, len)`
+fn describe_change(change: &FileChange) -> (String, Option<(u64, u64)>) {
+    match change {
+        FileChange::CreateFolder { path } => (format!("创建目录: {}", path), None),
+        FileChange::UpdateFile { path, hash, len, offset, compression, compressed_len, .. } => {
+            let text = format!("更新文件: {}，hash {}，len {}，offset {}，压缩 {}（{}）", path, hash, len, offset, compression.name(), compressed_len);
+
+            (text, Some((*offset, *compressed_len)))
+        },
+        FileChange::CreateIfMissing { path, hash, len, offset, compression, compressed_len, .. } => {
+            let text = format!("按需创建: {}，hash {}，len {}，offset {}，压缩 {}（{}）", path, hash, len, offset, compression.name(), compressed_len);
+
+            (text, Some((*offset, *compressed_len)))
+        },
+        FileChange::PatchFile { path, hash, len, offset, patch_len, .. } => {
+            let text = format!("补丁文件: {}，hash {}，len {}，offset {}，补丁长度 {}", path, hash, len, offset, patch_len);
+
+            (text, Some((*offset, *patch_len)))
+        },
+        FileChange::CreateSymlink { path, target } => (format!("创建链接: {} -> {}", path, target), None),
+        FileChange::DeleteFolder { path } => (format!("删除目录: {}", path), None),
+        FileChange::DeleteFile { path } => (format!("删除文件: {}", path), None),
+        FileChange::MoveFile { from, to } => (format!("移动文件: {} -> {}", from, to), None),
+    }
+}
@@ -0,0 +1,45 @@
+use crate::app_path::AppPath;
+use crate::config::Config;
+use crate::core::data::index_file::IndexFile;
+use crate::core::signing::Signer;
+use crate::core::tar_reader::TarReader;
+use crate::error::ManagerError;
+use crate::web::log::Console;
+
+/// 生成新的签名密钥，并用新密钥重新签名所有频道的索引文件和元数据
+/// 
+/// 已经有密钥时需要指定`force`才会覆盖，覆盖之后旧的公钥就作废了，客户端需要更新内置的公钥
+pub fn task_keygen(force: bool, apppath: &AppPath, _config: &Config, console: &Console) -> Result<(), ManagerError> {
+    if apppath.signing_key_file.exists() && !force {
+        return Err(ManagerError::task("签名密钥已经存在，如果确实要重新生成，请使用--force参数"));
+    }
+
+    let signer = Signer::generate();
+    signer.save(apppath)?;
+
+    // 元数据的签名是存在索引文件里的，所以已经打包好的版本也可以补上签名
+    for channel in apppath.channels()? {
+        let index_filepath = apppath.index_file_of(&channel);
+
+        if !index_filepath.exists() {
+            continue;
+        }
+
+        console.log_debug(format!("正在重新签名{}频道", channel));
+
+        let mut index_file = IndexFile::load_from_file(&index_filepath)?;
+
+        for index in &mut index_file {
+            let mut reader = TarReader::new(apppath.public_dir.join(&index.filename))?;
+            let metadata = reader.read_metadata_raw(index.offset, index.len)?;
+
+            index.signature = Some(signer.sign(&metadata));
+        }
+
+        index_file.save(&index_filepath, Some(&signer))?;
+    }
+
+    console.log_info(format!("签名密钥已生成，公钥: {}", signer.public_key()));
+
+    Ok(())
+}
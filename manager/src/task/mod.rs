@@ -1,6 +1,16 @@
 pub mod check;
+pub mod check_ignore;
 pub mod combine;
+pub mod diff_versions;
+pub mod export;
+pub mod full_install;
+pub mod inspect;
+pub mod keygen;
 pub mod pack;
+pub mod pack_plan;
+pub mod promote;
 pub mod revert;
+pub mod rollback;
 pub mod sync;
 pub mod test;
+pub mod yank;
@@ -1,22 +1,58 @@
+use std::collections::HashMap;
 use std::ops::Deref;
 use std::rc::Weak;
 
 use crate::app_path::AppPath;
+use crate::app_path::STABLE_CHANNEL;
+use crate::app_path::is_valid_channel_name;
 use crate::config::Config;
 use crate::core::archive_tester::ArchiveTester;
+use crate::core::compression::Compression;
 use crate::core::data::index_file::IndexFile;
 use crate::core::data::index_file::VersionIndex;
+use crate::core::data::version_meta::FileChange;
 use crate::core::data::version_meta::VersionMeta;
 use crate::core::data::version_meta_group::VersionMetaGroup;
+use crate::core::delta::create_delta;
+use crate::core::file_hash::calculate_archive_hash;
+use crate::core::packed_file::read_packed_file;
+use crate::core::rule_filter::RuleFilter;
+use crate::core::signing::Signer;
 use crate::core::tar_writer::TarWriter;
 use crate::diff::abstract_file::AbstractFile;
 use crate::diff::diff::Diff;
 use crate::diff::disk_file::DiskFile;
 use crate::diff::history_file::HistoryFile;
+use crate::error::IoContext;
+use crate::error::ManagerError;
+use crate::task::full_install::generate_full_installs;
+use crate::task::pack_plan::PackPlan;
 use crate::web::log::Console;
 
+/// 把工作空间目录里的文件修改打包成一个新版本
+pub fn task_pack(version_label: String, change_logs: String, channel: String, apppath: &AppPath, config: &Config, console: &Console) -> Result<(), ManagerError> {
+    pack(version_label, change_logs, channel, false, apppath, config, console).map(|_| ())
+}
 
-pub fn task_pack(version_label: String, change_logs: String, apppath: &AppPath, config: &Config, console: &Console) -> u8 {
+/// 试运行打包：和正常打包一样对比文件、生成差异补丁和压缩数据，但不会写入任何文件，最后输出打包计划
+pub fn task_pack_dry_run(version_label: String, channel: String, apppath: &AppPath, config: &Config, console: &Console) -> Result<(), ManagerError> {
+    let plan = plan_pack(version_label, channel, apppath, config, console)?;
+
+    console.log_info(serde_json::to_string_pretty(&plan).unwrap());
+    console.set_output(serde_json::to_value(&plan).unwrap());
+
+    Ok(())
+}
+
+/// 试运行打包，并返回打包计划
+pub fn plan_pack(version_label: String, channel: String, apppath: &AppPath, config: &Config, console: &Console) -> Result<PackPlan, ManagerError> {
+    let plan = pack(version_label, "".to_owned(), channel, true, apppath, config, console)?;
+
+    Ok(plan.unwrap())
+}
+
+/// 打包的具体过程，`dry_run`为true时不写入任何文件，并返回打包计划
+fn pack(version_label: String, change_logs: String, channel: String, dry_run: bool, apppath: &AppPath, config: &Config, console: &Console) -> Result<Option<PackPlan>, ManagerError> {
     // 读取更新日志
     let change_logs = match change_logs.is_empty() {
         false => change_logs,
@@ -32,100 +68,252 @@ pub fn task_pack(version_label: String, change_logs: String, apppath: &AppPath,
     };
 
 
-    let mut index_file = IndexFile::load_from_file(&apppath.index_file);
-
-    if index_file.contains(&version_label) {
-        console.log_error(format!("版本号已经存在: {}", version_label));
-        return 1;
+    if !is_valid_channel_name(&channel) {
+        return Err(ManagerError::task(format!("频道名不合法: {}", channel)));
     }
 
+    let index_filepath = apppath.index_file_of(&channel);
+    let mut index_file = IndexFile::load_or_create_channel(apppath, &channel)?;
+
+    check_label_available(&version_label, apppath)?;
+
+    let followers = find_followers(&channel, &index_file, apppath, console)?;
+
     // 1. 读取所有历史版本，并推演出上个版本的文件状态，用于和工作空间目录对比生成文件差异
     // 读取现有更新包，并复现在history上
     console.log_debug("正在读取数据");
 
     let mut history = HistoryFile::new_dir("workspace_root", Weak::new());
 
-    for (_index, meta) in index_file.read_all_metas(&apppath.public_dir) {
+    for (_index, meta) in index_file.read_all_metas(&apppath.public_dir)? {
         history.replay_operations(&meta);
     }
 
     // 对比文件
     console.log_debug("正在扫描文件更改");
 
-    let exclude_rules = &config.core.exclude_rules;
-    let disk_file = DiskFile::new(apppath.workspace_dir.clone(), Weak::new());
-    let diff = Diff::diff(&disk_file, &history, Some(exclude_rules));
+    let filter = RuleFilter::load(&config.core, &apppath.workspace_dir)?;
+    let create_if_missing = RuleFilter::from_globs(&config.core.create_if_missing, "配置文件create-if-missing")?;
+    let protected = RuleFilter::from_globs(&config.core.protected_paths, "配置文件protected-paths")?;
+    let disk_file = DiskFile::new(apppath.workspace_dir.clone(), Weak::new(), config.core.hash_algorithm, config.core.symlink_mode)?;
+    disk_file.check_files(&filter)?;
+    let mut diff = Diff::diff(&disk_file, &history, Some(&filter));
+    diff.protect(&protected);
+
+    for path in diff.protected_deletions() {
+        console.log_warning(format!("受保护的路径不会在客户端上被删除: {}", path));
+    }
 
     if !diff.has_diff() {
-        console.log_error("目前工作目录还没有任何文件修改");
-        return 1;
+        return Err(ManagerError::task("目前工作目录还没有任何文件修改"));
     }
 
     console.log_info(format!("{:#?}", diff));
 
     // 2. 将所有“覆盖的文件”的数据和元数据写入到更新包中，同时更新元数据中每个文件的偏移值
-    // 创建新的更新包，将所有文件修改写进去
-    std::fs::create_dir_all(&apppath.public_dir).unwrap();
+    // 创建新的更新包，将所有文件修改写进去。试运行时不创建更新包，只计算数据的存储形式
     let version_filename = format!("{}.tar", version_label);
     let version_file = apppath.public_dir.join(&version_filename);
-    let mut writer = TarWriter::new(&version_file);
 
-    // 写入每个更新的文件数据
-    let mut vec = Vec::<&DiskFile>::new();
+    let mut writer = match dry_run {
+        true => None,
+        false => {
+            std::fs::create_dir_all(&apppath.public_dir).with_path(&apppath.public_dir)?;
+            Some(TarWriter::new(&version_file)?)
+        },
+    };
+
+    // 写入每个更新的文件数据，同时记录是不是修改过的文件
+    let mut vec = Vec::<(&DiskFile, bool)>::new();
     
     for f in &diff.added_files {
-        vec.push(f);
+        vec.push((f, false));
     }
 
     for f in &diff.modified_files {
-        vec.push(f);
+        vec.push((f, true));
     }
 
+    let archive_of = |label: &str| index_file.archive_of(&apppath.public_dir, label);
+
+    // 记录哪些文件是以差异补丁的形式写入的，key: 文件路径，value: 补丁的长度
+    let mut patched = HashMap::<String, u64>::new();
+
+    // 记录哪些文件是压缩后写入的，key: 文件路径，value: (压缩算法, 压缩后的长度)
+    let mut compressed = HashMap::<String, (Compression, u64)>::new();
+
+    let compression = config.core.compression;
+
     let mut counter = 1;
-    for f in &vec {
+    for (f, modified) in &vec {
         console.log_debug(format!("打包({}/{}) {}", counter, vec.len(), f.path().deref()));
         counter += 1;
 
         let path = f.path().to_owned();
         let disk_file = apppath.workspace_dir.join(&path);
-        let open = std::fs::File::options().read(true).open(disk_file).unwrap();
+
+        // 已经读到内存里的文件数据，避免重复读取
+        let mut loaded = None::<Vec<u8>>;
+
+        // 对于修改过的文件，尝试生成差异补丁。按需创建的文件在客户端上的内容是不确定的，没法基于旧文件打补丁
+        // 新增的文件即使旧版本里有同名的符号链接或者目录，也会在同一个版本里被删掉，同样没法打补丁
+        let patchable = *modified && !create_if_missing.matches(&path, false);
+
+        if let Some(old) = history.find(&path).filter(|e| patchable && !e.is_dir() && e.symlink_target().is_none() && !e.is_create_if_missing()) {
+            let new_data = std::fs::read(&disk_file).with_path(&disk_file)?;
+            assert_eq!(new_data.len() as u64, f.len());
+
+            let old_data = read_packed_file(old.file_location(), &archive_of)?;
+            let delta = create_delta(&old_data, &new_data);
+
+            // 只有补丁足够小时才值得使用，不然客户端还不如直接下载完整文件
+            if (delta.len() as u64) < f.len() / 4 * 3 {
+                console.log_debug(format!("  使用差异补丁 {} -> {}", f.len(), delta.len()));
+
+                if let Some(writer) = &mut writer {
+                    writer.add_file(std::io::Cursor::new(&delta), delta.len() as u64, &path, &version_label)?;
+                }
+
+                patched.insert(path, delta.len() as u64);
+                continue;
+            }
+
+            loaded = Some(new_data);
+        }
+
+        // 尝试压缩文件数据，压缩后没有变小的话就还是存原始数据
+        if compression != Compression::None && f.len() >= config.core.compression_threshold {
+            let data = match loaded.take() {
+                Some(data) => data,
+                None => std::fs::read(&disk_file).with_path(&disk_file)?,
+            };
+            assert_eq!(data.len() as u64, f.len());
+
+            let encoded = compression.compress(&data);
+
+            if (encoded.len() as u64) < f.len() {
+                if let Some(writer) = &mut writer {
+                    writer.add_file(std::io::Cursor::new(&encoded), encoded.len() as u64, &path, &version_label)?;
+                }
+
+                compressed.insert(path, (compression, encoded.len() as u64));
+                continue;
+            }
+
+            loaded = Some(data);
+        }
+
+        let writer = match &mut writer {
+            Some(writer) => writer,
+            None => continue,
+        };
+
+        if let Some(data) = loaded {
+            writer.add_file(std::io::Cursor::new(&data), f.len(), &path, &version_label)?;
+            continue;
+        }
+
+        let open = std::fs::File::options().read(true).open(&disk_file).with_path(&disk_file)?;
 
         // 提供的len必须和读取到的长度严格相等
-        let meta = open.metadata().unwrap();
+        let meta = open.metadata().with_path(&disk_file)?;
         assert_eq!(meta.len(), f.len());
 
-        writer.add_file(open, f.len(), &path, &version_label);
+        writer.add_file(open, f.len(), &path, &version_label)?;
     }
 
     // 写入元数据
     console.log_debug("写入元数据");
 
+    // 把文件数据的实际存储形式更新到文件变动列表里
+    let mut changes = diff.to_file_changes_with(|f| create_if_missing.matches(&f.path(), false));
+
+    for change in changes.iter_mut() {
+        if let FileChange::UpdateFile { path, hash, len, modified, mode, offset, compression, compressed_len } = change {
+            if let Some(patch_len) = patched.get(path) {
+                *change = FileChange::PatchFile {
+                    path: path.to_owned(),
+                    hash: hash.to_owned(),
+                    len: *len,
+                    modified: *modified,
+                    mode: *mode,
+                    offset: *offset,
+                    patch_len: *patch_len,
+                };
+            } else if let Some((algorithm, length)) = compressed.get(path) {
+                *compression = *algorithm;
+                *compressed_len = *length;
+            }
+        } else if let FileChange::CreateIfMissing { path, compression, compressed_len, .. } = change {
+            if let Some((algorithm, length)) = compressed.get(path) {
+                *compression = *algorithm;
+                *compressed_len = *length;
+            }
+        }
+    }
+
     // 读取写好的更新记录
-    let meta = VersionMeta::new(version_label.clone(), change_logs, diff.to_file_changes());
+    let meta = VersionMeta::new(version_label.clone(), change_logs, changes);
+
+    let writer = match writer {
+        Some(writer) => writer,
+        None => return Ok(Some(PackPlan::new(meta, channel, &index_file, &apppath.public_dir)?)),
+    };
+
     let meta_group = VersionMetaGroup::with_one(meta);
-    let meta_info = writer.finish(meta_group);
+    let signer = Signer::load(apppath)?;
+    let meta_info = writer.finish(meta_group, signer.as_ref())?;
 
     // 3. 更新索引文件
-    index_file.add(VersionIndex {
+    let version_index = VersionIndex {
         label: version_label.to_owned(),
         filename: version_filename,
         offset: meta_info.offset,
         len: meta_info.length,
-        hash: "no hash".to_owned(),
-    });
+        hash: calculate_archive_hash(&version_file)?,
+        signature: meta_info.signature,
+        full_install: None,
+    };
+
+    index_file.add(version_index.clone());
 
     // 进行解压测试
     console.log_debug("正在测试");
 
     let mut tester = ArchiveTester::new();
-    for (index, meta) in index_file.read_all_metas(&apppath.public_dir) {
+    for (index, meta) in index_file.read_all_metas(&apppath.public_dir)? {
         tester.feed_version(apppath.public_dir.join(&index.filename), &meta);
     }
-    tester.finish(|e| console.log_debug(format!("{}/{} 正在测试 {} 的 {} ({}+{})", e.index, e.total, e.label, e.path, e.offset, e.len))).unwrap();
+    tester.finish(|e| console.log_debug(format!("{}/{} 正在测试 {} 的 {} ({}+{})", e.index, e.total, e.label, e.path, e.offset, e.len)))?;
 
-    console.log_info("测试通过，打包完成！");
+    console.log_info(format!("测试通过，打包完成！（{}频道）", channel));
     
-    index_file.save(&apppath.index_file);
+    index_file.save(&index_filepath, signer.as_ref())?;
+
+    let follower_names = followers.iter().map(|e| e.0.to_owned()).collect::<Vec<_>>();
+
+    for (ch, mut other) in followers {
+        other.add(version_index.clone());
+        other.save(&apppath.index_file_of(&ch), signer.as_ref())?;
+    }
+
+    // 频道的最新版本可能变了，需要更新完整安装包
+    if config.core.full_install {
+        generate_full_installs(apppath, console)?;
+    }
+
+    console.set_output(serde_json::json!({
+        "label": version_index.label,
+        "channel": channel,
+        "followers": follower_names,
+        "filename": version_index.filename,
+        "size": std::fs::metadata(&version_file).with_path(&version_file)?.len(),
+        "hash": version_index.hash,
+        "offset": version_index.offset,
+        "length": version_index.len,
+        "signature": version_index.signature,
+        "diff": diff.to_json(),
+    }));
 
     // // 生成上传脚本
     // let context = TemplateContext {
@@ -135,5 +323,39 @@ pub fn task_pack(version_label: String, change_logs: String, apppath: &AppPath,
 
     // generate_upload_script(context, ctx, &version_label);
 
-    0
-}
\ No newline at end of file
+    Ok(None)
+}
+
+/// 检查版本号是否还没有被使用过。所有频道共用同一批更新包文件，所以版本号在所有频道里都不能重复
+pub fn check_label_available(version_label: &str, apppath: &AppPath) -> Result<(), ManagerError> {
+    for ch in apppath.channels()? {
+        if IndexFile::load_from_file(&apppath.index_file_of(&ch))?.contains(version_label) {
+            return Err(ManagerError::task(format!("版本号已经存在: {}（{}频道）", version_label, ch)));
+        }
+    }
+
+    Ok(())
+}
+
+/// 找出需要跟着一起收到新版本的其它频道
+/// 
+/// 往稳定频道打包时，和稳定频道完全一致的频道也会跟着收到这个新版本，已经领先的频道则保持不动
+pub fn find_followers(channel: &str, index_file: &IndexFile, apppath: &AppPath, console: &Console) -> Result<Vec<(String, IndexFile)>, ManagerError> {
+    let mut followers = Vec::<(String, IndexFile)>::new();
+
+    if channel != STABLE_CHANNEL {
+        return Ok(followers);
+    }
+
+    for ch in apppath.channels()?.into_iter().filter(|e| e != STABLE_CHANNEL) {
+        let other = IndexFile::load_from_file(&apppath.index_file_of(&ch))?;
+
+        if other.is_prefix_of(index_file) && index_file.is_prefix_of(&other) {
+            followers.push((ch, other));
+        } else {
+            console.log_warning(format!("{}频道和稳定频道已经不一致，不会收到这个版本", ch));
+        }
+    }
+
+    Ok(followers)
+}
@@ -0,0 +1,128 @@
+//! 打包计划
+//!
+//! 试运行打包（`pack --dry-run`）时不会写入任何文件，而是输出一份打包计划，
+//! 列出这次打包会产生的所有文件变动、写入更新包的数据量，以及停留在各个旧版本上的客户端更新时需要下载的数据量
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::core::data::index_file::IndexFile;
+use crate::core::data::version_meta::FileChange;
+use crate::core::data::version_meta::VersionMeta;
+use crate::core::data::version_meta_group::VersionMetaGroup;
+use crate::error::ManagerError;
+use crate::task::combine::COMBINED_FILENAME;
+use crate::task::combine::final_locations;
+
+/// tar文件里每个条目的头部和数据都要对齐到512字节
+const TAR_BLOCK: u64 = 512;
+
+/// 代表一份打包计划
+#[derive(Serialize)]
+pub struct PackPlan {
+    /// 新版本的版本号
+    pub label: String,
+
+    /// 打包到哪个频道
+    pub channel: String,
+
+    /// 所有文件变动，格式和元数据里的一样。因为没有真正写入更新包，所有的`offset`都是0
+    pub changes: serde_json::Value,
+
+    /// 写入更新包的文件数据的总大小（差异补丁和压缩后的大小）
+    pub data_size: u64,
+
+    /// 预计的更新包文件大小，包括tar头和元数据
+    pub archive_size: u64,
+
+    /// 各个旧版本的客户端更新到新版本时需要下载的数据量
+    pub downloads: Vec<DownloadSize>,
+}
+
+/// 代表某个旧版本的客户端更新到新版本时需要下载的数据量
+#[derive(Serialize)]
+pub struct DownloadSize {
+    /// 客户端当前的版本号，为None时代表全新安装
+    pub from: Option<String>,
+
+    /// 需要下载的元数据和文件数据的总大小
+    pub size: u64,
+}
+
+impl PackPlan {
+    /// 根据新版本的元数据生成打包计划，`index_file`是要打包到的频道的索引文件
+    pub fn new(meta: VersionMeta, channel: String, index_file: &IndexFile, public_dir: &Path) -> Result<Self, ManagerError> {
+        let metadata = VersionMetaGroup::with_one(meta.clone()).serialize();
+
+        let mut data_size = 0;
+        let mut archive_size = tar_entry_size("metadata.txt", metadata.len() as u64) + 2 * TAR_BLOCK;
+
+        for change in &meta.changes {
+            if let Some((path, _offset, len)) = stored_data(change) {
+                data_size += len;
+                archive_size += tar_entry_size(path, len);
+            }
+        }
+
+        // 客户端需要下载新版本之后的所有版本的元数据和文件数据，合并包里的多个版本共用一个元数据组，只需要下载一次
+        let mut downloads = Vec::<DownloadSize>::new();
+        let mut remaining = metadata.len() as u64 + data_size;
+        let mut downloaded_groups = HashSet::<(String, u64, u64)>::new();
+
+        // 合并包里只存储了最终文件状态的数据，中间版本的数据是不存在的，客户端也不会去下载
+        let metas = index_file.read_all_metas(public_dir)?;
+        let stored = final_locations(metas.iter().filter(|e| e.0.filename == COMBINED_FILENAME).map(|e| &e.1));
+
+        for (index, old_meta) in metas.into_iter().rev() {
+            downloads.push(DownloadSize { from: Some(index.label.to_owned()), size: remaining });
+
+            if downloaded_groups.insert((index.filename.to_owned(), index.offset, index.len)) {
+                remaining += index.len;
+            }
+
+            let in_combined = index.filename == COMBINED_FILENAME;
+
+            remaining += old_meta.changes.iter()
+                .filter_map(stored_data)
+                .filter(|(_, offset, len)| !in_combined || stored.contains(&(old_meta.label.to_owned(), *offset, *len)))
+                .map(|e| e.2)
+                .sum::<u64>();
+        }
+
+        downloads.push(DownloadSize { from: None, size: remaining });
+        downloads.reverse();
+
+        Ok(Self {
+            changes: serde_json::from_str(&meta.serialize()["changes"].dump()).unwrap(),
+            label: meta.label,
+            channel,
+            data_size,
+            archive_size,
+            downloads,
+        })
+    }
+}
+
+/// 文件操作在更新包里存储的数据的路径、偏移值和长度，没有数据的操作返回None
+fn stored_data(change: &FileChange) -> Option<(&str, u64, u64)> {
+    match change {
+        FileChange::UpdateFile { path, offset, compressed_len, .. } => Some((path, *offset, *compressed_len)),
+        FileChange::CreateIfMissing { path, offset, compressed_len, .. } => Some((path, *offset, *compressed_len)),
+        FileChange::PatchFile { path, offset, patch_len, .. } => Some((path, *offset, *patch_len)),
+        _ => None,
+    }
+}
+
+/// 一个条目在tar文件里占用的大小，路径超过100字节时还需要额外的GNU长路径条目
+fn tar_entry_size(path: &str, len: u64) -> u64 {
+    let padded = |len: u64| len.div_ceil(TAR_BLOCK) * TAR_BLOCK;
+
+    let long_name = match path.len() > 100 {
+        true => TAR_BLOCK + padded(path.len() as u64 + 1),
+        false => 0,
+    };
+
+    long_name + TAR_BLOCK + padded(len)
+}
@@ -0,0 +1,74 @@
+use crate::app_path::AppPath;
+use crate::app_path::is_valid_channel_name;
+use crate::config::Config;
+use crate::core::archive_tester::ArchiveTester;
+use crate::core::data::index_file::IndexFile;
+use crate::core::signing::Signer;
+use crate::error::ManagerError;
+use crate::task::full_install::generate_full_installs;
+use crate::web::log::Console;
+
+/// 将一个频道里的某个版本（以及它之前所有目标频道还没有的版本）推送到另一个频道
+/// 
+/// 比如在beta频道测试完1.3版本后，将其推送到stable频道，让所有人都能收到
+pub fn task_promote(version_label: String, from: String, to: String, apppath: &AppPath, config: &Config, console: &Console) -> Result<(), ManagerError> {
+    for channel in [&from, &to] {
+        if !is_valid_channel_name(channel) {
+            return Err(ManagerError::task(format!("频道名不合法: {}", channel)));
+        }
+    }
+
+    if from == to {
+        return Err(ManagerError::task("来源频道和目标频道不能相同"));
+    }
+
+    let source_filepath = apppath.index_file_of(&from);
+    let target_filepath = apppath.index_file_of(&to);
+
+    if !source_filepath.exists() {
+        return Err(ManagerError::task(format!("频道不存在: {}", from)));
+    }
+
+    let source = IndexFile::load_from_file(&source_filepath)?;
+
+    let mut target = IndexFile::load_or_create_channel(apppath, &to)?;
+
+    let position = (&source).into_iter()
+        .position(|e| e.label == version_label)
+        .ok_or_else(|| ManagerError::task(format!("{}频道里没有这个版本: {}", from, version_label)))?;
+
+    if target.contains(&version_label) {
+        return Err(ManagerError::task(format!("{}频道里已经有这个版本了: {}", to, version_label)));
+    }
+
+    // 每个版本都是基于上一个版本的文件状态打包的，所以目标频道的版本列表必须和来源频道的开头部分完全一致才能推送
+    if !target.is_prefix_of(&source) {
+        return Err(ManagerError::task(format!("{}频道和{}频道的版本已经不一致了，无法推送", to, from)));
+    }
+
+    for i in target.len()..=position {
+        console.log_info(format!("推送版本 {}: {} -> {}", source[i].label, from, to));
+
+        target.add(source[i].clone());
+    }
+
+    // 进行解压测试
+    console.log_debug("正在测试");
+
+    let mut tester = ArchiveTester::new();
+    for (index, meta) in target.read_all_metas(&apppath.public_dir)? {
+        tester.feed_version(apppath.public_dir.join(&index.filename), &meta);
+    }
+    tester.finish(|e| console.log_debug(format!("{}/{} 正在测试 {} 的 {} ({}+{})", e.index, e.total, e.label, e.path, e.offset, e.len)))?;
+
+    target.save(&target_filepath, Signer::load(apppath)?.as_ref())?;
+
+    console.log_info("测试通过，推送完成！");
+
+    // 频道的最新版本可能变了，需要更新完整安装包
+    if config.core.full_install {
+        generate_full_installs(apppath, console)?;
+    }
+
+    Ok(())
+}
@@ -1,36 +1,43 @@
+use std::fs::File;
 use std::fs::FileTimes;
 use std::ops::Deref;
+use std::path::Path;
+use std::path::PathBuf;
 use std::rc::Weak;
 
 use crate::app_path::AppPath;
 use crate::config::Config;
 use crate::core::data::index_file::IndexFile;
-use crate::core::tar_reader::TarReader;
+use crate::core::packed_file::open_packed_file;
+use crate::core::rule_filter::RuleFilter;
 use crate::diff::abstract_file::AbstractFile;
 use crate::diff::diff::Diff;
 use crate::diff::disk_file::DiskFile;
 use crate::diff::history_file::HistoryFile;
+use crate::error::IoContext;
+use crate::error::ManagerError;
 use crate::web::log::Console;
 
 
-pub fn task_revert(apppath: &AppPath, config: &Config, console: &Console) -> u8 {
-    let index_file = IndexFile::load_from_file(&apppath.index_file);
+pub fn task_revert(channel: String, apppath: &AppPath, config: &Config, console: &Console) -> Result<(), ManagerError> {
+    let index_file = IndexFile::load_channel(apppath, &channel)?;
 
     // 读取现有更新包，并复现在history上
     console.log_debug("正在读取数据");
 
     let mut history = HistoryFile::new_empty();
 
-    for (_index, meta) in index_file.read_all_metas(&apppath.public_dir) {
+    for (_index, meta) in index_file.read_all_metas(&apppath.public_dir)? {
         history.replay_operations(&meta);
     }
 
     // 对比文件
     console.log_debug("正在扫描文件更改");
 
-    let exclude_rules = &config.core.exclude_rules;
-    let disk_file = DiskFile::new(apppath.workspace_dir.clone(), Weak::new());
-    let diff = Diff::diff(&history, &disk_file, Some(exclude_rules));
+    let filter = RuleFilter::load(&config.core, &apppath.workspace_dir)?;
+    let disk_file = DiskFile::new(apppath.workspace_dir.clone(), Weak::new(), config.core.hash_algorithm, config.core.symlink_mode)?;
+    disk_file.check_files(&filter)?;
+    let diff = Diff::diff(&history, &disk_file, Some(&filter));
     drop(disk_file);
 
     // 输出文件差异
@@ -45,34 +52,26 @@ pub fn task_revert(apppath: &AppPath, config: &Config, console: &Console) -> u8
     for mk in diff.added_folders {
         let dir = apppath.workspace_dir.join(mk.path().deref());
 
-        if let Err(e) = std::fs::create_dir_all(dir) {
-            panic!("{}: {:?}", mk.path().deref(), e);
-        }
+        std::fs::create_dir_all(&dir).with_path(&dir)?;
     }
 
     for mv in diff.renamed_files {
         let src = mv.0.disk_file();
         let dst = apppath.workspace_dir.join(mv.1.path().deref());
 
-        if let Err(e) = std::fs::rename(src, dst) {
-            panic!("{} => {}: {:?}", mv.0.path().deref(), mv.1.path().deref(), e);
-        }
+        std::fs::rename(src, dst).context(format_args!("{} => {}", mv.0.path().deref(), mv.1.path().deref()))?;
     }
 
     for rm in diff.missing_files {
         let file = rm.disk_file();
 
-        if let Err(e) = std::fs::remove_file(file) {
-            panic!("{}({}): {:?}", rm.path().deref(), file.to_str().unwrap(), e);
-        }
+        std::fs::remove_file(file).with_path(file)?;
     }
 
     for rm in diff.missing_folders {
         let dir = rm.disk_file();
         
-        if let Err(e) = std::fs::remove_dir(dir) {
-            panic!("{}: {:?}", rm.path().deref(), e);
-        }
+        std::fs::remove_dir(dir).with_path(dir)?;
     }
 
     let mut vec = Vec::<&HistoryFile>::new();
@@ -85,33 +84,81 @@ pub fn task_revert(apppath: &AppPath, config: &Config, console: &Console) -> u8
         vec.push(&f);
     }
 
+    let archive_of = |label: &str| index_file.archive_of(&apppath.public_dir, label);
+
+    // 工作空间代表的是全新安装时的文件状态，所以按需创建的文件被修改过时，也会被还原成发布时的内容
     for up in vec {
-        let file = apppath.workspace_dir.join(up.path().deref());
+        restore_file(up, &apppath.workspace_dir, &archive_of)?;
+    }
 
-        let loc = up.file_location();
-        let meta = index_file.find(&loc.version).unwrap();
-        
-        let mut reader = TarReader::new(apppath.public_dir.join(&meta.filename));
+    for link in diff.added_symlinks {
+        restore_symlink(&link, &apppath.workspace_dir)?;
+    }
 
-        let open = std::fs::File::options()
-            .write(true)
-            .truncate(true)
-            .create(true)
-            .open(file);
+    console.log_info("工作空间目录已经退回到未修改之前");
 
-        let mut open = match open {
-            Ok(open) => open,
-            Err(e) => panic!("{}: {}", up.path().deref(), e.to_string()),
-        };
+    Ok(())
+}
 
-        let mut src = reader.open_file(loc.offset, loc.length as u64);
+/// 从更新包里读取一个文件的数据，写到`base_dir`下对应的位置，并恢复文件的修改时间和权限
+pub fn restore_file(file: &HistoryFile, base_dir: &Path, archive_of: &impl Fn(&str) -> Result<PathBuf, ManagerError>) -> Result<(), ManagerError> {
+    let path = base_dir.join(file.path().deref());
 
-        std::io::copy(&mut src, &mut open).unwrap();
+    let mut open = std::fs::File::options()
+        .write(true)
+        .truncate(true)
+        .create(true)
+        .open(&path)
+        .with_path(&path)?;
 
-        open.set_times(FileTimes::new().set_modified(up.modified())).unwrap();
+    let mut src = open_packed_file(file.file_location(), archive_of)?;
+
+    std::io::copy(&mut src, &mut open).with_path(&path)?;
+
+    open.set_times(FileTimes::new().set_modified(file.modified())).with_path(&path)?;
+
+    if let Some(mode) = file.mode() {
+        set_file_mode(&open, mode).with_path(&path)?;
     }
 
-    console.log_info("工作空间目录已经退回到未修改之前");
+    Ok(())
+}
+
+/// 在`base_dir`下对应的位置创建一个符号链接
+pub fn restore_symlink(link: &HistoryFile, base_dir: &Path) -> Result<(), ManagerError> {
+    let path = base_dir.join(link.path().deref());
+    let target = link.symlink_target().unwrap();
+
+    create_symlink(&target, &path).context(format_args!("{} -> {}", link.path().deref(), target))
+}
+
+/// 创建一个符号链接
+#[cfg(unix)]
+fn create_symlink(target: &str, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+/// 创建一个符号链接，windows上需要区分链接指向的是文件还是目录
+#[cfg(windows)]
+fn create_symlink(target: &str, link: &Path) -> std::io::Result<()> {
+    let is_dir = link.parent().unwrap().join(target).is_dir();
+
+    match is_dir {
+        true => std::os::windows::fs::symlink_dir(target, link),
+        false => std::os::windows::fs::symlink_file(target, link),
+    }
+}
+
+/// 恢复文件的unix权限，非unix平台上什么也不做
+#[cfg(unix)]
+fn set_file_mode(file: &File, mode: u32) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    file.set_permissions(std::fs::Permissions::from_mode(mode))
+}
 
-    0
-}
\ No newline at end of file
+/// 恢复文件的unix权限，非unix平台上什么也不做
+#[cfg(not(unix))]
+fn set_file_mode(_file: &File, _mode: u32) -> std::io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+use std::collections::LinkedList;
+use std::ops::Deref;
+
+use crate::app_path::AppPath;
+use crate::app_path::is_valid_channel_name;
+use crate::config::Config;
+use crate::core::archive_tester::ArchiveTester;
+use crate::core::compression::Compression;
+use crate::core::data::index_file::IndexFile;
+use crate::core::data::index_file::VersionIndex;
+use crate::core::data::version_meta::FileChange;
+use crate::core::data::version_meta::VersionMeta;
+use crate::core::data::version_meta_group::VersionMetaGroup;
+use crate::core::file_hash::calculate_archive_hash;
+use crate::core::packed_file::read_packed_file;
+use crate::core::rule_filter::RuleFilter;
+use crate::core::signing::Signer;
+use crate::core::tar_reader::TarReader;
+use crate::core::tar_writer::TarWriter;
+use crate::diff::abstract_file::AbstractFile;
+use crate::diff::diff::Diff;
+use crate::diff::history_file::HistoryFile;
+use crate::error::ManagerError;
+use crate::task::combine::COMBINED_FILENAME;
+use crate::task::combine::final_locations;
+use crate::task::combine::find_discarded;
+use crate::task::full_install::generate_full_installs;
+use crate::task::pack::check_label_available;
+use crate::task::pack::find_followers;
+use crate::web::log::Console;
+
+/// 打包一个回退版本，客户端更新到这个版本后，文件状态会回到`target_label`这个旧版本的样子
+///
+/// 和普通的打包不同，这里不会读取工作空间目录，所有文件数据都是从现有的更新包里复制出来的
+pub fn task_pack_rollback(target_label: String, version_label: String, change_logs: String, channel: String, apppath: &AppPath, config: &Config, console: &Console) -> Result<(), ManagerError> {
+    let change_logs = match change_logs.is_empty() {
+        false => change_logs,
+        true => format!("回退到版本 {}", target_label),
+    };
+
+    if !is_valid_channel_name(&channel) {
+        return Err(ManagerError::task(format!("频道名不合法: {}", channel)));
+    }
+
+    let index_filepath = apppath.index_file_of(&channel);
+    let mut index_file = IndexFile::load_channel(apppath, &channel)?;
+
+    if !index_file.contains(&target_label) {
+        return Err(ManagerError::task(format!("{}频道里没有这个版本: {}", channel, target_label)));
+    }
+
+    check_label_available(&version_label, apppath)?;
+
+    let followers = find_followers(&channel, &index_file, apppath, console)?;
+
+    // 1. 分别推演出目标版本和最新版本的文件状态
+    console.log_debug("正在读取数据");
+
+    let mut target = HistoryFile::new_empty();
+    let mut head = HistoryFile::new_empty();
+    let mut reached = false;
+
+    let metas = index_file.read_all_metas(&apppath.public_dir)?;
+
+    for (_index, meta) in &metas {
+        if !reached {
+            target.replay_operations(meta);
+        }
+
+        head.replay_operations(meta);
+
+        reached |= meta.label == target_label;
+    }
+
+    let protected = RuleFilter::from_globs(&config.core.protected_paths, "配置文件protected-paths")?;
+    let mut diff = Diff::diff(&target, &head, None);
+    diff.protect(&protected);
+
+    for path in diff.protected_deletions() {
+        console.log_warning(format!("受保护的路径不会在客户端上被删除: {}", path));
+    }
+
+    if !diff.has_diff() {
+        return Err(ManagerError::task(format!("版本 {} 和最新版本的文件完全一样，不需要回退", target_label)));
+    }
+
+    console.log_info(format!("{:#?}", diff));
+
+    // 合并包里只存储了最终文件状态的数据，回退到合并包里的中间版本时，需要的数据可能已经被丢掉了
+    let stored = final_locations(metas.iter().filter(|e| e.0.filename == COMBINED_FILENAME).map(|e| &e.1));
+
+    for f in diff.added_files.iter().chain(diff.modified_files.iter()) {
+        if let Some(version) = find_discarded(f.file_location(), &index_file, &stored) {
+            return Err(ManagerError::task(format!("文件 {} 在版本 {} 的数据在合并时已经被丢弃，无法回退到版本 {}", f.path().deref(), version, target_label)));
+        }
+    }
+
+    // 2. 打包回退版本，出错时删掉写了一半的更新包
+    let version_file = apppath.public_dir.join(format!("{}.tar", version_label));
+    let signer = Signer::load(apppath)?;
+    let meta = VersionMeta::new(version_label, change_logs, LinkedList::new());
+
+    let version_index = match write_rollback(&diff, meta, &mut index_file, signer.as_ref(), apppath, config, console) {
+        Ok(ok) => ok,
+        Err(err) => {
+            let _ = std::fs::remove_file(&version_file);
+            return Err(err);
+        },
+    };
+
+    console.log_info(format!("测试通过，回退版本打包完成！（{}频道）", channel));
+
+    index_file.save(&index_filepath, signer.as_ref())?;
+
+    for (ch, mut other) in followers {
+        other.add(version_index.clone());
+        other.save(&apppath.index_file_of(&ch), signer.as_ref())?;
+    }
+
+    // 频道的最新版本可能变了，需要更新完整安装包
+    if config.core.full_install {
+        generate_full_installs(apppath, console)?;
+    }
+
+    Ok(())
+}
+
+/// 从现有的更新包里把目标版本的文件数据复制到新的更新包里，写入元数据后添加到`index_file`里并进行测试
+/// 
+/// `meta`是回退版本的元数据，里面的文件操作会根据`diff`生成
+fn write_rollback(diff: &Diff<HistoryFile, HistoryFile>, mut meta: VersionMeta, index_file: &mut IndexFile, signer: Option<&Signer>, apppath: &AppPath, config: &Config, console: &Console) -> Result<VersionIndex, ManagerError> {
+    let version_label = meta.label.to_owned();
+    let version_filename = format!("{}.tar", version_label);
+    let version_file = apppath.public_dir.join(&version_filename);
+    let mut writer = TarWriter::new(&version_file)?;
+
+    let archive_of = |label: &str| index_file.archive_of(&apppath.public_dir, label);
+
+    // 记录每个文件的数据是怎么存储的，key: 文件路径，value: (压缩算法, 存储的长度)
+    let mut stored_as = HashMap::<String, (Compression, u64)>::new();
+
+    let vec = diff.added_files.iter().chain(diff.modified_files.iter()).collect::<Vec<_>>();
+
+    for (i, f) in vec.iter().enumerate() {
+        console.log_debug(format!("复制({}/{}) {}", i + 1, vec.len(), f.path().deref()));
+
+        let path = f.path().to_owned();
+        let loc = f.file_location();
+
+        match &loc.base {
+            // 完整的文件数据可以原样复制（压缩过的数据也保持压缩状态）
+            None => {
+                let mut reader = TarReader::new(archive_of(&loc.version)?)?;
+                let read = reader.open_file(loc.offset, loc.length)?;
+                writer.add_file(read, loc.length, &path, &version_label)?;
+                stored_as.insert(path, (loc.compression, loc.length));
+            },
+
+            // 差异补丁需要先还原成完整数据，再按配置重新压缩
+            Some(_) => {
+                let data = read_packed_file(loc, &archive_of)?;
+                let (compression, stored) = config.core.compression.compress_if_smaller(data, config.core.compression_threshold);
+
+                writer.add_file(std::io::Cursor::new(&stored), stored.len() as u64, &path, &version_label)?;
+                stored_as.insert(path, (compression, stored.len() as u64));
+            },
+        }
+    }
+
+    // 3. 写入元数据
+    console.log_debug("写入元数据");
+
+    // 目标版本里按需创建的文件回退后也只在不存在时创建
+    let mut changes = diff.to_file_changes_with(|f| f.is_create_if_missing());
+
+    for change in changes.iter_mut() {
+        if let FileChange::UpdateFile { path, compression, compressed_len, .. } | FileChange::CreateIfMissing { path, compression, compressed_len, .. } = change {
+            (*compression, *compressed_len) = stored_as[path.deref()];
+        }
+    }
+
+    meta.changes = changes;
+    let meta_info = writer.finish(VersionMetaGroup::with_one(meta), signer)?;
+
+    // 4. 更新索引文件
+    let version_index = VersionIndex {
+        label: version_label.to_owned(),
+        filename: version_filename,
+        offset: meta_info.offset,
+        len: meta_info.length,
+        hash: calculate_archive_hash(&version_file)?,
+        signature: meta_info.signature,
+        full_install: None,
+    };
+
+    index_file.add(version_index.clone());
+
+    // 进行解压测试
+    console.log_debug("正在测试");
+
+    let mut tester = ArchiveTester::new();
+    for (index, meta) in index_file.read_all_metas(&apppath.public_dir)? {
+        tester.feed_version(apppath.public_dir.join(&index.filename), &meta);
+    }
+    tester.finish(|e| console.log_debug(format!("{}/{} 正在测试 {} 的 {} ({}+{})", e.index, e.total, e.label, e.path, e.offset, e.len)))?;
+
+    Ok(version_index)
+}
@@ -2,14 +2,16 @@ use std::time::UNIX_EPOCH;
 
 use crate::app_path::AppPath;
 use crate::config::Config;
+use crate::error::IoContext;
+use crate::error::ManagerError;
 use crate::upload::file_list_cache::FileListCache;
 use crate::upload::s3::S3Target;
 use crate::upload::webdav::WebdavTarget;
 use crate::upload::UploadTarget;
 use crate::web::log::Console;
 
-pub fn task_upload(apppath: &AppPath, config: &Config, console: &Console) -> u8 {
-    let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build().unwrap();
+pub fn task_upload(apppath: &AppPath, config: &Config, console: &Console) -> Result<(), ManagerError> {
+    let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build().context("创建异步运行时")?;
 
     runtime.block_on(async move {
         let webdav_config = config.webdav.clone();
@@ -19,34 +21,26 @@ pub fn task_upload(apppath: &AppPath, config: &Config, console: &Console) -> u8
         if webdav_config.enabled {
             let target = FileListCache::new(WebdavTarget::new(webdav_config).await);
             
-            if let Err(err) = upload("webdav", target, &apppath, console).await {
-                console.log_error(err);
-                return 1;
-            }
+            upload("webdav", target, &apppath, console).await?;
         }
     
         // 再上传s3
         if s3_config.enabled {
             let target = FileListCache::new(S3Target::new(s3_config).await);
 
-            if let Err(err) = upload("s3", target, &apppath, console).await {
-                console.log_error(err);
-                return 1;
-            }
+            upload("s3", target, &apppath, console).await?;
         }
     
-        0
-    });
-    
-    0
+        Ok(())
+    })
 }
 
-async fn upload(name: &str, mut target: impl UploadTarget, apppath: &AppPath, console: &Console) -> Result<(), String> {
+async fn upload(name: &str, mut target: impl UploadTarget, apppath: &AppPath, console: &Console) -> Result<(), ManagerError> {
     console.log_debug("收集本地文件列表...");
-    let local = get_local(&apppath).await;
+    let local = get_local(&apppath).await.with_path(&apppath.public_dir)?;
 
     console.log_debug(format!("收集 {} 上的文件列表...", name));
-    let remote = target.list().await?;
+    let remote = target.list().await.map_err(ManagerError::Upload)?;
 
     console.log_debug("计算文件列表差异...");
 
@@ -74,14 +68,14 @@ async fn upload(name: &str, mut target: impl UploadTarget, apppath: &AppPath, co
     for f in &need_upload {
         console.log_debug(format!("上传文件: {}", f));
 
-        target.upload(&f, apppath.public_dir.join(&f)).await?;
+        target.upload(&f, apppath.public_dir.join(&f)).await.map_err(ManagerError::Upload)?;
     }
 
     // 删除文件
     for f in &need_delete {
         console.log_debug(format!("删除文件: {}", f));
         
-        target.delete(&f).await?;
+        target.delete(&f).await.map_err(ManagerError::Upload)?;
     }
 
     console.log_info("文件同步完成");
@@ -89,17 +83,17 @@ async fn upload(name: &str, mut target: impl UploadTarget, apppath: &AppPath, co
     Ok(())
 }
 
-async fn get_local(apppath: &AppPath) -> Vec<(String, u64)> {
-    let mut dir = tokio::fs::read_dir(&apppath.public_dir).await.unwrap();
+async fn get_local(apppath: &AppPath) -> std::io::Result<Vec<(String, u64)>> {
+    let mut dir = tokio::fs::read_dir(&apppath.public_dir).await?;
 
     let mut files = Vec::new();
 
-    while let Some(entry) = dir.next_entry().await.unwrap() {
+    while let Some(entry) = dir.next_entry().await? {
         let file = entry.file_name().to_str().unwrap().to_owned();
-        let mtime = entry.metadata().await.unwrap().modified().unwrap();
+        let mtime = entry.metadata().await?.modified()?;
 
         files.push((file, mtime.duration_since(UNIX_EPOCH).unwrap().as_secs()));
     }
 
-    files
+    Ok(files)
 }
\ No newline at end of file
@@ -1,26 +1,138 @@
+use std::collections::HashSet;
+
 use crate::app_path::AppPath;
 use crate::config::Config;
 use crate::core::archive_tester::ArchiveTester;
 use crate::core::data::index_file::IndexFile;
+use crate::core::data::index_file::NO_HASH;
+use crate::core::file_hash::calculate_archive_hash;
+use crate::core::signing::signature_file_of;
+use crate::core::signing::verify_signature;
+use crate::core::signing::Signer;
+use crate::core::tar_reader::TarReader;
+use crate::error::IoContext;
+use crate::error::ManagerError;
 use crate::web::log::Console;
 
 
-pub fn task_test(apppath: &AppPath, _config: &Config, console: &Console) -> u8 {
+pub fn task_test(apppath: &AppPath, _config: &Config, console: &Console) -> Result<(), ManagerError> {
     console.log_debug("正在执行更新包的解压测试");
 
-    let index_file = IndexFile::load_from_file(&apppath.index_file);
+    // 检查每个更新包文件的校验值，同一个文件只需要检查一次
+    let mut checked = HashSet::<String>::new();
 
-    let mut tester = ArchiveTester::new();
+    // 生成过签名密钥的话，所有的索引文件和元数据都必须有正确的签名
+    let public_key = check_public_key(apppath)?;
 
-    // 读取现有更新包
-    for (index, meta) in index_file.read_all_metas(&apppath.public_dir) {
-        tester.feed_version(apppath.public_dir.join(&index.filename), &meta);
-    }
+    // 记录每个频道测试了多少个文件
+    let mut tested = Vec::<serde_json::Value>::new();
+
+    // 每个频道都要单独测试一遍
+    for channel in apppath.channels()? {
+        let index_filepath = apppath.index_file_of(&channel);
+        let index_file = IndexFile::load_from_file(&index_filepath)?;
+
+        console.log_debug(format!("正在测试{}频道", channel));
+
+        // 索引文件不存在说明这个频道还没有发布过任何版本，也就没有需要验证的签名
+        if let Some(public_key) = public_key.as_ref().filter(|_| index_filepath.exists()) {
+            console.log_debug(format!("正在验证 {:?} 的签名", index_filepath.file_name().unwrap()));
+
+            let content = std::fs::read(&index_filepath).with_path(&index_filepath)?;
+
+            let result = match std::fs::read_to_string(signature_file_of(&index_filepath)) {
+                Ok(signature) => verify_signature(public_key, &content, signature.trim()),
+                Err(_) => Err("signature file is missing".to_owned()),
+            };
+
+            if let Err(err) = result {
+                return Err(ManagerError::task(format!("索引文件签名验证失败: {:?}，{}", index_filepath.file_name().unwrap(), err)));
+            }
+
+            for index in &index_file {
+                let mut reader = TarReader::new(apppath.public_dir.join(&index.filename))?;
+                let metadata = reader.read_metadata_raw(index.offset, index.len)?;
+
+                let result = match &index.signature {
+                    Some(signature) => verify_signature(public_key, &metadata, signature),
+                    None => Err("signature is missing".to_owned()),
+                };
+
+                if let Err(err) = result {
+                    return Err(ManagerError::task(format!("元数据签名验证失败: {}，{}", index.label, err)));
+                }
+            }
+        }
+
+        for index in &index_file {
+            if index.hash == NO_HASH || !checked.insert(index.filename.to_owned()) {
+                continue;
+            }
+
+            console.log_debug(format!("正在校验 {}", index.filename));
+
+            let actual = calculate_archive_hash(apppath.public_dir.join(&index.filename))?;
+
+            if actual != index.hash {
+                return Err(ManagerError::task(format!("更新包校验值不匹配: {}，实际: {}，预期: {}", index.filename, actual, index.hash)));
+            }
+        }
 
-    // 执行测试
-    tester.finish(|e| console.log_debug(format!("{}/{} 正在测试 {} 的 {} ({}+{})", e.index, e.total, e.label, e.path, e.offset, e.len))).unwrap();
+        for full_install in (&index_file).into_iter().filter_map(|e| e.full_install.as_ref()) {
+            if !checked.insert(full_install.filename.to_owned()) {
+                continue;
+            }
+
+            console.log_debug(format!("正在校验完整安装包 {}", full_install.filename));
+
+            let actual = calculate_archive_hash(apppath.public_dir.join(&full_install.filename))?;
+
+            if actual != full_install.hash {
+                return Err(ManagerError::task(format!("完整安装包校验值不匹配: {}，实际: {}，预期: {}", full_install.filename, actual, full_install.hash)));
+            }
+        }
+
+        let mut tester = ArchiveTester::new();
+
+        // 读取现有更新包
+        for (index, meta) in index_file.read_all_metas(&apppath.public_dir)? {
+            tester.feed_version(apppath.public_dir.join(&index.filename), &meta);
+        }
+
+        // 执行测试
+        let mut files = 0;
+
+        tester.finish(|e| {
+            files = e.total;
+            console.log_debug(format!("{}/{} 正在测试 {} 的 {} ({}+{})", e.index, e.total, e.label, e.path, e.offset, e.len));
+        })?;
+
+        tested.push(serde_json::json!({ "channel": channel, "versions": index_file.len(), "files": files }));
+    }
 
     console.log_info("测试通过！");
 
-    0
-}
\ No newline at end of file
+    console.set_output(serde_json::json!({ "channels": tested }));
+
+    Ok(())
+}
+
+/// 从签名私钥推导出公钥，作为验证签名的依据，还没有生成过密钥时返回None
+/// 
+/// 不能直接信任public目录里的公钥，能写入public目录的人可以删掉或者替换掉它，所以只检查它和私钥是否一致
+fn check_public_key(apppath: &AppPath) -> Result<Option<String>, ManagerError> {
+    let published = match std::fs::read_to_string(&apppath.public_key_file) {
+        Ok(ok) => Some(ok.trim().to_owned()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+        Err(err) => return Err(err).with_path(&apppath.public_key_file),
+    };
+
+    let public_key = Signer::load(apppath)?.map(|e| e.public_key());
+
+    match (&public_key, &published) {
+        (Some(expected), Some(actual)) if expected != actual => Err(ManagerError::task(format!("公钥文件和签名私钥不匹配: {:?}", apppath.public_key_file))),
+        (Some(_), None) => Err(ManagerError::task(format!("公钥文件不存在: {:?}", apppath.public_key_file))),
+        (None, Some(_)) => Err(ManagerError::task(format!("公钥文件存在，但是找不到签名私钥: {:?}", apppath.signing_key_file))),
+        _ => Ok(public_key),
+    }
+}
@@ -0,0 +1,240 @@
+use std::collections::HashSet;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::app_path::AppPath;
+use crate::config::Config;
+use crate::core::archive_tester::ArchiveTester;
+use crate::core::data::index_file::IndexFile;
+use crate::core::data::index_file::VersionIndex;
+use crate::core::data::version_meta::FileChange;
+use crate::core::data::version_meta_group::VersionMetaGroup;
+use crate::core::file_hash::calculate_archive_hash;
+use crate::core::signing::Signer;
+use crate::core::tar_reader::TarReader;
+use crate::core::tar_writer::MetadataLocation;
+use crate::core::tar_writer::TarWriter;
+use crate::error::IoContext;
+use crate::error::ManagerError;
+use crate::task::combine::COMBINED_FILENAME;
+use crate::task::combine::final_locations;
+use crate::task::full_install::generate_full_installs;
+use crate::task::full_install::remove_unused_full_installs;
+use crate::web::log::Console;
+
+/// 删除一个已经发布的版本
+///
+/// 默认只能删除频道里最新的版本。指定`force`后也可以删除更早的版本，但因为后面的版本都是基于这个版本打包的，
+/// 所以这个版本之后的所有版本也会被一并删除
+///
+/// 没有指定`channel`时，会从所有包含这个版本的频道里删除。更新包文件不再被任何频道使用时会被删掉，
+/// 如果版本存储在合并包里，则会重新生成合并包，把这个版本从合并包里去掉
+pub fn task_yank(version_label: String, channel: Option<String>, force: bool, apppath: &AppPath, config: &Config, console: &Console) -> Result<(), ManagerError> {
+    let all_channels = apppath.channels()?;
+
+    let channels = match channel {
+        Some(channel) => {
+            if !all_channels.contains(&channel) {
+                return Err(ManagerError::task(format!("频道不存在: {}", channel)));
+            }
+
+            vec![channel]
+        },
+        None => all_channels.clone(),
+    };
+
+    let mut indexes = all_channels.iter()
+        .map(|e| Ok((e.to_owned(), IndexFile::load_from_file(&apppath.index_file_of(e))?)))
+        .collect::<Result<Vec<_>, ManagerError>>()?;
+
+    // 1. 从各个频道里删除版本
+    let mut removed = Vec::<VersionIndex>::new();
+    let mut modified_channels = HashSet::<String>::new();
+
+    for (channel, index_file) in indexes.iter_mut().filter(|e| channels.contains(&e.0)) {
+        let position = match (&*index_file).into_iter().position(|e| e.label == version_label) {
+            Some(ok) => ok,
+            None => continue,
+        };
+
+        if position + 1 != index_file.len() && !force {
+            let tip = &index_file[index_file.len() - 1].label;
+            return Err(ManagerError::task(format!("{}频道里最新的版本是 {}，只能删除最新的版本。如果确实要删除，请使用--force参数，之后的所有版本也会被一并删除", channel, tip)));
+        }
+
+        for v in index_file.split_off(position) {
+            console.log_info(format!("从{}频道删除版本 {}", channel, v.label));
+
+            if !removed.iter().any(|e| e.label == v.label) {
+                removed.push(v);
+            }
+        }
+
+        modified_channels.insert(channel.to_owned());
+    }
+
+    if removed.is_empty() {
+        return Err(ManagerError::task(format!("版本号不存在: {}", version_label)));
+    }
+
+    // 2. 找出已经不再被任何频道使用的版本和更新包文件
+    let mut referenced_labels = HashSet::<String>::new();
+    let mut referenced_files = HashSet::<String>::new();
+
+    for (_, index_file) in &indexes {
+        for v in index_file {
+            referenced_labels.insert(v.label.to_owned());
+            referenced_files.insert(v.filename.to_owned());
+        }
+    }
+
+    let dropped_labels = removed.iter()
+        .filter(|e| !referenced_labels.contains(&e.label))
+        .map(|e| e.label.to_owned())
+        .collect::<HashSet<_>>();
+
+    let dropped_files = removed.iter()
+        .filter(|e| !referenced_files.contains(&e.filename))
+        .map(|e| e.filename.to_owned())
+        .collect::<HashSet<_>>();
+
+    // 3. 合并包还要继续使用的话，需要把删掉的版本从合并包里去掉
+    let temp_public = apppath.public_dir.join(".temp");
+    let new_combined_file = temp_public.join(COMBINED_FILENAME);
+    let signer = Signer::load(apppath)?;
+
+    let combined_entry = removed.iter()
+        .find(|e| e.filename == COMBINED_FILENAME && dropped_labels.contains(&e.label) && referenced_files.contains(&e.filename))
+        .cloned();
+
+    let rebuilt = combined_entry.is_some();
+
+    if let Some(entry) = combined_entry {
+        console.log_debug("正在重新生成合并包");
+
+        if !std::fs::exists(&temp_public).with_path(&temp_public)? {
+            std::fs::create_dir(&temp_public).with_path(&temp_public)?;
+        }
+
+        let meta_loc = match rebuild_combined(&entry, &dropped_labels, &new_combined_file, apppath, signer.as_ref()) {
+            Ok(ok) => ok,
+            Err(err) => {
+                let _ = std::fs::remove_dir_all(&temp_public);
+                return Err(err);
+            },
+        };
+
+        let hash = calculate_archive_hash(&new_combined_file)?;
+
+        for (_, index_file) in indexes.iter_mut() {
+            for v in index_file.into_iter().filter(|e| e.filename == COMBINED_FILENAME) {
+                v.offset = meta_loc.offset;
+                v.len = meta_loc.length;
+                v.hash = hash.to_owned();
+                v.signature = meta_loc.signature.clone();
+            }
+        }
+    }
+
+    // 4. 测试删除后的所有频道
+    console.log_debug("正在测试");
+
+    let archive_of = |filename: &str| match rebuilt && filename == COMBINED_FILENAME {
+        true => new_combined_file.clone(),
+        false => apppath.public_dir.join(filename),
+    };
+
+    for (channel, index_file) in &indexes {
+        if !rebuilt && !modified_channels.contains(channel) {
+            continue;
+        }
+
+        let result = test_channel(index_file, archive_of, console);
+
+        if let Err(err) = result {
+            let _ = std::fs::remove_dir_all(&temp_public);
+            return Err(ManagerError::task(format!("删除版本后{}频道测试失败，已放弃删除: {}", channel, err)));
+        }
+    }
+
+    // 5. 保存修改
+    if rebuilt {
+        let combined_file = apppath.public_dir.join(COMBINED_FILENAME);
+
+        std::fs::rename(&new_combined_file, &combined_file).with_path(&combined_file)?;
+        let _ = std::fs::remove_dir(&temp_public);
+    }
+
+    for (channel, index_file) in &indexes {
+        if rebuilt || modified_channels.contains(channel) {
+            index_file.save(&apppath.index_file_of(channel), signer.as_ref())?;
+        }
+    }
+
+    for filename in &dropped_files {
+        console.log_debug(format!("删除更新包 {}", filename));
+
+        let file = apppath.public_dir.join(filename);
+
+        std::fs::remove_file(&file).with_path(&file)?;
+    }
+
+    console.log_info(format!("删除完成！一共删除了 {} 个版本", removed.len()));
+
+    // 频道的最新版本可能变了，需要更新完整安装包，没有开启时也要清理掉不再使用的安装包
+    match config.core.full_install {
+        true => generate_full_installs(apppath, console)?,
+        false => remove_unused_full_installs(apppath, console)?,
+    }
+
+    Ok(())
+}
+
+/// 对一个频道进行解压测试，`archive_of`负责根据文件名决定去哪里读取更新包
+fn test_channel(index_file: &IndexFile, archive_of: impl Fn(&str) -> PathBuf, console: &Console) -> Result<(), ManagerError> {
+    let mut tester = ArchiveTester::new();
+    for (index, meta) in index_file.read_all_metas_with(&archive_of)? {
+        tester.feed_version(archive_of(&index.filename), &meta);
+    }
+    tester.finish(|e| console.log_debug(format!("{}/{} 正在测试 {} 的 {} ({}+{})", e.index, e.total, e.label, e.path, e.offset, e.len)))?;
+
+    Ok(())
+}
+
+/// 重新生成一个去掉了`dropped_labels`这些版本的合并包，写到`output`文件里
+fn rebuild_combined(entry: &VersionIndex, dropped_labels: &HashSet<String>, output: &Path, apppath: &AppPath, signer: Option<&Signer>) -> Result<MetadataLocation, ManagerError> {
+    let mut reader = TarReader::new(apppath.public_dir.join(COMBINED_FILENAME))?;
+    let old_group = reader.read_metadata_group(entry.offset, entry.len)?;
+
+    let mut new_group = VersionMetaGroup::new();
+
+    for meta in &old_group {
+        if !dropped_labels.contains(&meta.label) {
+            new_group.add_meta(meta.clone());
+        }
+    }
+
+    // 合并包里只存储了最终文件状态的数据，中间版本的数据在合并时就已经丢掉了。
+    // 如果删除的版本修改过某个文件，那么这个文件上一个版本的数据就找不回来了
+    let stored = final_locations(&old_group);
+    let needed = final_locations(&new_group);
+
+    if let Some((label, _, _)) = needed.difference(&stored).next() {
+        return Err(ManagerError::task(format!("版本 {} 的部分文件数据在合并时已经被丢弃，无法从合并包里删除版本", label)));
+    }
+
+    let mut writer = TarWriter::new(output)?;
+
+    for meta in &new_group {
+        for change in &meta.changes {
+            if let FileChange::UpdateFile { path, offset, compressed_len, .. } | FileChange::CreateIfMissing { path, offset, compressed_len, .. } = change {
+                if needed.contains(&(meta.label.to_owned(), *offset, *compressed_len)) {
+                    let read = reader.open_file(*offset, *compressed_len)?;
+                    writer.add_file(read, *compressed_len, path, &meta.label)?;
+                }
+            }
+        }
+    }
+
+    writer.finish(new_group, signer)
+}
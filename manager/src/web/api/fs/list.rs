@@ -52,7 +52,10 @@ pub async fn api_list(State(state): State<WebState>, Json(payload): Json<Request
         let metadata = entry.metadata().await.unwrap();
 
         let status = match entry.path().strip_prefix(&state.apppath.workspace_dir) {
-            Ok(ok) => status.get_file_status(&ok.to_str().unwrap().replace("\\", "/")).await,
+            Ok(ok) => match status.get_file_status(&ok.to_str().unwrap().replace("\\", "/")).await {
+                Ok(ok) => ok,
+                Err(err) => return PublicResponseBody::<ResponseData>::err(&err.to_string()),
+            },
             Err(_) => SingleFileStatus::Keep,
         };
 
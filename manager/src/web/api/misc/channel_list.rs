@@ -0,0 +1,43 @@
+use axum::extract::State;
+use axum::response::Response;
+use serde::Serialize;
+
+use crate::core::data::index_file::IndexFile;
+use crate::web::api::PublicResponseBody;
+use crate::web::webstate::WebState;
+
+#[derive(Serialize)]
+pub struct ResponseBody {
+    /// 所有的发布频道
+    channels: Vec<Channel>,
+}
+
+#[derive(Serialize)]
+pub struct Channel {
+    /// 频道名
+    pub name: String,
+
+    /// 这个频道里所有的版本号
+    pub versions: Vec<String>,
+}
+
+pub async fn api_channel_list(State(state): State<WebState>) -> Response {
+    let mut channels = Vec::<Channel>::new();
+
+    let names = match state.apppath.channels() {
+        Ok(ok) => ok,
+        Err(err) => return PublicResponseBody::<ResponseBody>::err(&err.to_string()),
+    };
+
+    for name in names {
+        let index_file = match IndexFile::load_channel(&state.apppath, &name) {
+            Ok(ok) => ok,
+            Err(err) => return PublicResponseBody::<ResponseBody>::err(&err.to_string()),
+        };
+        let versions = (&index_file).into_iter().map(|e| e.label.to_owned()).collect();
+
+        channels.push(Channel { name, versions });
+    }
+
+    PublicResponseBody::<ResponseBody>::ok(ResponseBody { channels })
+}
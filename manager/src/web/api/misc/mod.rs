@@ -1 +1,3 @@
+pub mod channel_list;
+pub mod public_key;
 pub mod version_list;
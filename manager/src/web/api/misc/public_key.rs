@@ -0,0 +1,22 @@
+use axum::extract::State;
+use axum::response::Response;
+use serde::Serialize;
+
+use crate::core::signing::Signer;
+use crate::web::api::PublicResponseBody;
+use crate::web::webstate::WebState;
+
+#[derive(Serialize)]
+pub struct ResponseBody {
+    /// base64编码的签名公钥，还没有生成过密钥时为null
+    public_key: Option<String>,
+}
+
+pub async fn api_public_key(State(state): State<WebState>) -> Response {
+    let public_key = match Signer::load(&state.apppath) {
+        Ok(ok) => ok.map(|e| e.public_key()),
+        Err(err) => return PublicResponseBody::<ResponseBody>::err(&err.to_string()),
+    };
+
+    PublicResponseBody::<ResponseBody>::ok(ResponseBody { public_key })
+}
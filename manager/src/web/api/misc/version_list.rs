@@ -1,13 +1,24 @@
 use axum::extract::State;
 use axum::response::Response;
+use axum::Json;
+use serde::Deserialize;
 use serde::Serialize;
 
+use crate::app_path::STABLE_CHANNEL;
 use crate::core::data::index_file::IndexFile;
+use crate::core::data::index_file::VersionIndex;
 use crate::core::data::version_meta::FileChange;
 use crate::core::data::version_meta::VersionMeta;
 use crate::web::api::PublicResponseBody;
 use crate::web::webstate::WebState;
 
+#[derive(Deserialize)]
+pub struct RequestBody {
+    /// 要列出哪个频道的版本，省略时为稳定频道
+    #[serde(default)]
+    channel: Option<String>,
+}
+
 #[derive(Serialize)]
 pub struct ResponseBody {
     /// 要删除的文件路径
@@ -19,25 +30,35 @@ pub struct Version {
     pub label: String,
     pub size: u64,
     pub change_logs: String,
+    pub hash: String,
+    pub signature: Option<String>,
+    pub full_install: Option<String>,
 }
 
-pub async fn api_version_list(State(state): State<WebState>) -> Response {
-    let index_file = IndexFile::load_from_file(&state.apppath.index_file);
+pub async fn api_version_list(State(state): State<WebState>, payload: Option<Json<RequestBody>>) -> Response {
+    let channel = payload.and_then(|e| e.0.channel).unwrap_or_else(|| STABLE_CHANNEL.to_owned());
+    let index_file = match IndexFile::load_channel(&state.apppath, &channel) {
+        Ok(ok) => ok,
+        Err(err) => return PublicResponseBody::<ResponseBody>::err(&err.to_string()),
+    };
 
-    let mut metas = Vec::<VersionMeta>::new();
+    let mut metas = Vec::<(VersionIndex, VersionMeta)>::new();
 
-    for (_index, meta) in index_file.read_all_metas(&state.apppath.public_dir) {
-        metas.push(meta);
+    for (index, meta) in index_file.read_all_metas(&state.apppath.public_dir).unwrap() {
+        metas.push((index, meta));
     }
     
     let mut versions = Vec::<Version>::new();
 
-    for meta in metas {
+    for (index, meta) in metas {
         let mut total_size = 0u64;
 
         for change in &meta.changes {
-            if let FileChange::UpdateFile { len, .. } = change {
-                total_size += len;
+            match change {
+                FileChange::UpdateFile { compressed_len, .. } => total_size += compressed_len,
+                FileChange::CreateIfMissing { compressed_len, .. } => total_size += compressed_len,
+                FileChange::PatchFile { patch_len, .. } => total_size += patch_len,
+                _ => (),
             }
         }
         
@@ -45,6 +66,9 @@ pub async fn api_version_list(State(state): State<WebState>) -> Response {
             label: meta.label, 
             size: total_size, 
             change_logs: meta.logs,
+            hash: index.hash,
+            signature: index.signature,
+            full_install: index.full_install.map(|e| e.filename),
         });
     }
 
@@ -2,6 +2,8 @@ use axum::extract::State;
 use axum::http::HeaderMap;
 use axum::response::Response;
 
+use crate::app_path::STABLE_CHANNEL;
+use crate::error::ManagerError;
 use crate::task::check::task_check;
 use crate::web::webstate::WebState;
 
@@ -13,6 +15,6 @@ pub async fn api_status(State(state): State<WebState>, headers: HeaderMap) -> Re
         .try_schedule(wait, state.clone(), move || do_status(state)).await
 }
 
-fn do_status(state: WebState) -> u8 {
-    task_check(&state.apppath, &state.config, &state.console)
+fn do_status(state: WebState) -> Result<(), ManagerError> {
+    task_check(STABLE_CHANNEL.to_owned(), &state.apppath, &state.config, &state.console)
 }
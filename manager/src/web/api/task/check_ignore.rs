@@ -0,0 +1,27 @@
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::response::Response;
+use axum::Json;
+use serde::Deserialize;
+
+use crate::error::ManagerError;
+use crate::task::check_ignore::task_check_ignore;
+use crate::web::webstate::WebState;
+
+#[derive(Deserialize)]
+pub struct RequestBody {
+    /// 要检查的路径，相对于工作空间目录，以/结尾的路径视为目录
+    paths: Vec<String>,
+}
+
+/// 检查工作空间里的路径会不会被排除，以及是被哪条规则排除的
+pub async fn api_check_ignore(State(state): State<WebState>, headers: HeaderMap, Json(payload): Json<RequestBody>) -> Response {
+    let wait = headers.get("wait").is_some();
+
+    state.clone().te.lock().await
+        .try_schedule(wait, state.clone(), move || do_check_ignore(payload, state)).await
+}
+
+fn do_check_ignore(payload: RequestBody, state: WebState) -> Result<(), ManagerError> {
+    task_check_ignore(payload.paths, &state.apppath, &state.config, &state.console)
+}
@@ -1,18 +1,50 @@
 use axum::extract::State;
 use axum::http::HeaderMap;
 use axum::response::Response;
+use axum::Json;
+use serde::Deserialize;
 
+use crate::error::ManagerError;
+use crate::task::combine::CombineRange;
 use crate::task::combine::task_combine;
 use crate::web::webstate::WebState;
 
+#[derive(Deserialize)]
+pub struct RequestBody {
+    /// 保留最新的N个版本不合并
+    #[serde(default)]
+    keep_latest: Option<usize>,
+
+    /// 只合并到指定的版本为止（包括这个版本）
+    #[serde(default)]
+    until: Option<String>,
+
+    /// 将合并的所有历史操作压缩成一个只包含最终文件状态的基准版本
+    #[serde(default)]
+    squash: bool,
+
+    /// 压缩历史时，在元数据组里保留其它版本的版本号和更新记录，仅供查阅
+    #[serde(default)]
+    keep_labels: bool,
+}
+
 // 执行更新包合并操作
-pub async fn api_combine(State(state): State<WebState>, headers: HeaderMap) -> Response {
+pub async fn api_combine(State(state): State<WebState>, headers: HeaderMap, payload: Option<Json<RequestBody>>) -> Response {
     let wait = headers.get("wait").is_some();
 
     state.clone().te.lock().await
-        .try_schedule(wait, state.clone(), move || do_combine(state)).await
+        .try_schedule(wait, state.clone(), move || do_combine(payload.map(|e| e.0), state)).await
 }
 
-fn do_combine(state: WebState) -> u8 {
-    task_combine(&state.apppath, &state.config, &state.console)
+fn do_combine(payload: Option<RequestBody>, state: WebState) -> Result<(), ManagerError> {
+    let squash = payload.as_ref().is_some_and(|e| e.squash);
+    let keep_labels = payload.as_ref().is_some_and(|e| e.keep_labels);
+
+    let range = match payload {
+        Some(RequestBody { keep_latest: Some(n), .. }) => CombineRange::KeepLatest(n),
+        Some(RequestBody { until: Some(label), .. }) => CombineRange::Until(label),
+        _ => CombineRange::All,
+    };
+
+    task_combine(range, squash, keep_labels, &state.apppath, &state.config, &state.console)
 }
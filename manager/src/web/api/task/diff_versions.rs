@@ -0,0 +1,37 @@
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::response::Response;
+use axum::Json;
+use serde::Deserialize;
+
+use crate::app_path::STABLE_CHANNEL;
+use crate::error::ManagerError;
+use crate::task::diff_versions::task_diff_versions;
+use crate::web::webstate::WebState;
+
+#[derive(Deserialize)]
+pub struct RequestBody {
+    /// 旧版本号
+    from: String,
+
+    /// 新版本号
+    to: String,
+
+    /// 版本所在的频道，省略时为稳定频道
+    #[serde(default)]
+    channel: Option<String>,
+}
+
+/// 对比两个已发布版本之间的文件差异
+pub async fn api_diff_versions(State(state): State<WebState>, headers: HeaderMap, Json(payload): Json<RequestBody>) -> Response {
+    let wait = headers.get("wait").is_some();
+
+    state.clone().te.lock().await
+        .try_schedule(wait, state.clone(), move || do_diff_versions(payload, state)).await
+}
+
+fn do_diff_versions(payload: RequestBody, state: WebState) -> Result<(), ManagerError> {
+    let channel = payload.channel.unwrap_or_else(|| STABLE_CHANNEL.to_owned());
+
+    task_diff_versions(payload.from, payload.to, channel, &state.apppath, &state.config, &state.console)
+}
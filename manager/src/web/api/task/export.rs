@@ -0,0 +1,46 @@
+use std::path::Component;
+use std::path::Path;
+
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::response::Response;
+use axum::Json;
+use serde::Deserialize;
+
+use crate::app_path::STABLE_CHANNEL;
+use crate::error::ManagerError;
+use crate::task::export::task_export;
+use crate::web::webstate::WebState;
+
+#[derive(Deserialize)]
+pub struct RequestBody {
+    /// 要导出的版本号
+    label: String,
+
+    /// 导出到哪个目录，相对于管理端的工作目录
+    dest: String,
+
+    /// 版本所在的频道，省略时为稳定频道
+    #[serde(default)]
+    channel: Option<String>,
+}
+
+/// 把某个版本的完整文件导出到一个目录里
+pub async fn api_export(State(state): State<WebState>, headers: HeaderMap, Json(payload): Json<RequestBody>) -> Response {
+    let wait = headers.get("wait").is_some();
+
+    state.clone().te.lock().await
+        .try_schedule(wait, state.clone(), move || do_export(payload, state)).await
+}
+
+fn do_export(payload: RequestBody, state: WebState) -> Result<(), ManagerError> {
+    // 只允许导出到工作目录里面，绝对路径和..都会跑到工作目录外面去
+    if Path::new(&payload.dest).components().any(|e| !matches!(e, Component::Normal(_) | Component::CurDir)) {
+        return Err(ManagerError::task(format!("导出目录必须是工作目录下的相对路径，并且不能包含..: {}", payload.dest)));
+    }
+
+    let dest = state.apppath.working_dir.join(payload.dest);
+    let channel = payload.channel.unwrap_or_else(|| STABLE_CHANNEL.to_owned());
+
+    task_export(payload.label, dest, channel, &state.apppath, &state.config, &state.console)
+}
@@ -0,0 +1,19 @@
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::response::Response;
+
+use crate::error::ManagerError;
+use crate::task::full_install::task_full_install;
+use crate::web::webstate::WebState;
+
+/// 为每个频道的最新版本生成完整安装包
+pub async fn api_full_install(State(state): State<WebState>, headers: HeaderMap) -> Response {
+    let wait = headers.get("wait").is_some();
+
+    state.clone().te.lock().await
+        .try_schedule(wait, state.clone(), move || do_full_install(state)).await
+}
+
+fn do_full_install(state: WebState) -> Result<(), ManagerError> {
+    task_full_install(&state.apppath, &state.config, &state.console)
+}
@@ -0,0 +1,34 @@
+use std::path::Path;
+
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::response::Response;
+use axum::Json;
+use serde::Deserialize;
+
+use crate::error::ManagerError;
+use crate::task::inspect::task_inspect;
+use crate::web::webstate::WebState;
+
+#[derive(Deserialize)]
+pub struct RequestBody {
+    /// 要查看的更新包文件名
+    file: String,
+}
+
+/// 打印一个更新包里的所有版本、文件操作和偏移值
+pub async fn api_inspect(State(state): State<WebState>, headers: HeaderMap, Json(payload): Json<RequestBody>) -> Response {
+    let wait = headers.get("wait").is_some();
+
+    state.clone().te.lock().await
+        .try_schedule(wait, state.clone(), move || do_inspect(payload, state)).await
+}
+
+fn do_inspect(payload: RequestBody, state: WebState) -> Result<(), ManagerError> {
+    // 只允许查看public目录下的文件
+    if Path::new(&payload.file).file_name().is_none_or(|e| e != payload.file.as_str()) {
+        return Err(ManagerError::task(format!("不是public目录下的文件: {}", payload.file)));
+    }
+
+    task_inspect(payload.file, &state.apppath, &state.config, &state.console)
+}
@@ -0,0 +1,28 @@
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::response::Response;
+use axum::Json;
+use serde::Deserialize;
+
+use crate::error::ManagerError;
+use crate::task::keygen::task_keygen;
+use crate::web::webstate::WebState;
+
+#[derive(Deserialize)]
+pub struct RequestBody {
+    /// 是否覆盖已经存在的密钥
+    #[serde(default)]
+    force: bool,
+}
+
+/// 生成签名密钥，并重新签名所有的索引文件和元数据
+pub async fn api_keygen(State(state): State<WebState>, headers: HeaderMap, Json(payload): Json<RequestBody>) -> Response {
+    let wait = headers.get("wait").is_some();
+
+    state.clone().te.lock().await
+        .try_schedule(wait, state.clone(), move || do_keygen(payload, state)).await
+}
+
+fn do_keygen(payload: RequestBody, state: WebState) -> Result<(), ManagerError> {
+    task_keygen(payload.force, &state.apppath, &state.config, &state.console)
+}
@@ -1,6 +1,16 @@
 pub mod pack;
+pub mod pack_dry_run;
 pub mod test;
 pub mod combine;
 pub mod check;
+pub mod check_ignore;
 pub mod revert;
 pub mod sync;
+pub mod promote;
+pub mod keygen;
+pub mod yank;
+pub mod rollback;
+pub mod inspect;
+pub mod diff_versions;
+pub mod export;
+pub mod full_install;
@@ -4,6 +4,8 @@ use axum::response::Response;
 use axum::Json;
 use serde::Deserialize;
 
+use crate::app_path::STABLE_CHANNEL;
+use crate::error::ManagerError;
 use crate::task::pack::task_pack;
 use crate::web::webstate::WebState;
 
@@ -14,6 +16,10 @@ pub struct RequestBody {
 
     /// 新包的更新记录
     change_logs: String,
+
+    /// 要打包到哪个频道，省略时为稳定频道
+    #[serde(default)]
+    channel: Option<String>,
 }
 
 /// 打包新版本
@@ -24,9 +30,10 @@ pub async fn api_pack(State(state): State<WebState>, headers: HeaderMap, Json(pa
         .try_schedule(wait, state.clone(), move || do_check(payload, state)).await
 }
 
-fn do_check(payload: RequestBody, state: WebState) -> u8 {
+fn do_check(payload: RequestBody, state: WebState) -> Result<(), ManagerError> {
     let version_label = payload.label;
     let change_logs = payload.change_logs;
+    let channel = payload.channel.unwrap_or_else(|| STABLE_CHANNEL.to_owned());
 
-    task_pack(version_label, change_logs, &state.apppath, &state.config, &state.console)
+    task_pack(version_label, change_logs, channel, &state.apppath, &state.config, &state.console)
 }
\ No newline at end of file
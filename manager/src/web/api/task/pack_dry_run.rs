@@ -0,0 +1,36 @@
+use axum::extract::State;
+use axum::response::Response;
+use axum::Json;
+use serde::Deserialize;
+
+use crate::app_path::STABLE_CHANNEL;
+use crate::task::pack::plan_pack;
+use crate::task::pack_plan::PackPlan;
+use crate::web::api::PublicResponseBody;
+use crate::web::webstate::WebState;
+
+#[derive(Deserialize)]
+pub struct RequestBody {
+    /// 新包的版本号
+    label: String,
+
+    /// 要打包到哪个频道，省略时为稳定频道
+    #[serde(default)]
+    channel: Option<String>,
+}
+
+/// 试运行打包，直接返回打包计划
+/// 
+/// 试运行不会写入任何文件，所以不需要占用任务执行器
+pub async fn api_pack_dry_run(State(state): State<WebState>, Json(payload): Json<RequestBody>) -> Response {
+    let channel = payload.channel.unwrap_or_else(|| STABLE_CHANNEL.to_owned());
+
+    // 对比文件和生成差异补丁都比较耗时，放到单独的线程里进行
+    let result = tokio::task::spawn_blocking(move || plan_pack(payload.label, channel, &state.apppath, &state.config, &state.console)).await;
+
+    match result {
+        Ok(Ok(plan)) => PublicResponseBody::<PackPlan>::ok(plan),
+        Ok(Err(err)) => PublicResponseBody::<()>::err(&err.to_string()),
+        Err(_) => PublicResponseBody::<()>::err("任务意外中止，请检查控制台输出"),
+    }
+}
@@ -0,0 +1,33 @@
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::response::Response;
+use axum::Json;
+use serde::Deserialize;
+
+use crate::error::ManagerError;
+use crate::task::promote::task_promote;
+use crate::web::webstate::WebState;
+
+#[derive(Deserialize)]
+pub struct RequestBody {
+    /// 要推送的版本号
+    label: String,
+
+    /// 来源频道
+    from: String,
+
+    /// 目标频道
+    to: String,
+}
+
+/// 将一个频道里的版本推送到另一个频道
+pub async fn api_promote(State(state): State<WebState>, headers: HeaderMap, Json(payload): Json<RequestBody>) -> Response {
+    let wait = headers.get("wait").is_some();
+
+    state.clone().te.lock().await
+        .try_schedule(wait, state.clone(), move || do_promote(payload, state)).await
+}
+
+fn do_promote(payload: RequestBody, state: WebState) -> Result<(), ManagerError> {
+    task_promote(payload.label, payload.from, payload.to, &state.apppath, &state.config, &state.console)
+}
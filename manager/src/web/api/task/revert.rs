@@ -2,6 +2,8 @@ use axum::extract::State;
 use axum::http::HeaderMap;
 use axum::response::Response;
 
+use crate::app_path::STABLE_CHANNEL;
+use crate::error::ManagerError;
 use crate::task::revert::task_revert;
 use crate::web::webstate::WebState;
 
@@ -15,6 +17,6 @@ pub async fn api_revert(State(state): State<WebState>, headers: HeaderMap) -> Re
         .try_schedule(wait, state.clone(), move || do_revert(state)).await
 }
 
-pub fn do_revert(state: WebState) -> u8 {
-    task_revert(&state.apppath, &state.config, &state.console)
+pub fn do_revert(state: WebState) -> Result<(), ManagerError> {
+    task_revert(STABLE_CHANNEL.to_owned(), &state.apppath, &state.config, &state.console)
 }
\ No newline at end of file
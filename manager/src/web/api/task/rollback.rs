@@ -0,0 +1,41 @@
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::response::Response;
+use axum::Json;
+use serde::Deserialize;
+
+use crate::app_path::STABLE_CHANNEL;
+use crate::error::ManagerError;
+use crate::task::rollback::task_pack_rollback;
+use crate::web::webstate::WebState;
+
+#[derive(Deserialize)]
+pub struct RequestBody {
+    /// 要回退到的旧版本号
+    target: String,
+
+    /// 新包的版本号
+    label: String,
+
+    /// 新包的更新记录，省略时自动生成
+    #[serde(default)]
+    change_logs: String,
+
+    /// 要打包到哪个频道，省略时为稳定频道
+    #[serde(default)]
+    channel: Option<String>,
+}
+
+/// 打包一个回退版本
+pub async fn api_pack_rollback(State(state): State<WebState>, headers: HeaderMap, Json(payload): Json<RequestBody>) -> Response {
+    let wait = headers.get("wait").is_some();
+
+    state.clone().te.lock().await
+        .try_schedule(wait, state.clone(), move || do_pack_rollback(payload, state)).await
+}
+
+fn do_pack_rollback(payload: RequestBody, state: WebState) -> Result<(), ManagerError> {
+    let channel = payload.channel.unwrap_or_else(|| STABLE_CHANNEL.to_owned());
+
+    task_pack_rollback(payload.target, payload.label, payload.change_logs, channel, &state.apppath, &state.config, &state.console)
+}
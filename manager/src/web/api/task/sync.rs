@@ -2,6 +2,7 @@ use axum::extract::State;
 use axum::http::HeaderMap;
 use axum::response::Response;
 
+use crate::error::ManagerError;
 use crate::task::sync::task_upload;
 use crate::web::webstate::WebState;
 
@@ -13,6 +14,6 @@ pub async fn api_upload_api(State(state): State<WebState>, headers: HeaderMap) -
         .try_schedule(wait, state.clone(), move || do_upload(state)).await
 }
 
-fn do_upload(state: WebState) -> u8 {
+fn do_upload(state: WebState) -> Result<(), ManagerError> {
     task_upload(&state.apppath, &state.config, &state.console)
 }
@@ -2,6 +2,7 @@ use axum::extract::State;
 use axum::http::HeaderMap;
 use axum::response::Response;
 
+use crate::error::ManagerError;
 use crate::task::test::task_test;
 use crate::web::webstate::WebState;
 
@@ -13,6 +14,6 @@ pub async fn api_test(State(state): State<WebState>, headers: HeaderMap) -> Resp
         .try_schedule(wait, state.clone(), move || do_test(state)).await
 }
 
-fn do_test(state: WebState) -> u8 {
+fn do_test(state: WebState) -> Result<(), ManagerError> {
     task_test(&state.apppath, &state.config, &state.console)
 }
\ No newline at end of file
@@ -0,0 +1,35 @@
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::response::Response;
+use axum::Json;
+use serde::Deserialize;
+
+use crate::error::ManagerError;
+use crate::task::yank::task_yank;
+use crate::web::webstate::WebState;
+
+#[derive(Deserialize)]
+pub struct RequestBody {
+    /// 要删除的版本号
+    label: String,
+
+    /// 只从这个频道里删除，省略时会从所有包含这个版本的频道里删除
+    #[serde(default)]
+    channel: Option<String>,
+
+    /// 允许删除不是最新的版本
+    #[serde(default)]
+    force: bool,
+}
+
+/// 删除一个已经发布的版本
+pub async fn api_yank(State(state): State<WebState>, headers: HeaderMap, Json(payload): Json<RequestBody>) -> Response {
+    let wait = headers.get("wait").is_some();
+
+    state.clone().te.lock().await
+        .try_schedule(wait, state.clone(), move || do_yank(payload, state)).await
+}
+
+fn do_yank(payload: RequestBody, state: WebState) -> Result<(), ManagerError> {
+    task_yank(payload.label, payload.channel, payload.force, &state.apppath, &state.config, &state.console)
+}
@@ -1,12 +1,14 @@
 use std::rc::Weak;
 
 use crate::app_path::AppPath;
-use crate::core::data::index_file::IndexFile;
 use crate::config::Config;
+use crate::core::data::index_file::IndexFile;
+use crate::core::rule_filter::RuleFilter;
 use crate::diff::abstract_file::AbstractFile;
 use crate::diff::diff::Diff;
 use crate::diff::disk_file::DiskFile;
 use crate::diff::history_file::HistoryFile;
+use crate::error::ManagerError;
 
 pub struct FileStatus {
     pub app_path: AppPath,
@@ -25,8 +27,8 @@ impl FileStatus {
     }
 
     /// 获取一个文件的修改状态
-    pub async fn get_file_status(&mut self, path: &str) -> SingleFileStatus {
-        let status = self.refresh().await;
+    pub async fn get_file_status(&mut self, path: &str) -> Result<SingleFileStatus, ManagerError> {
+        let status = self.refresh().await?;
 
         let path = &path.to_string();
 
@@ -34,93 +36,94 @@ impl FileStatus {
 
         if status.added_folders.contains(path) {
             // println!("1 {}", join_string(status.added_folders.iter().map(|e| e.to_owned()), "\n"));
-            return SingleFileStatus::Added;
+            return Ok(SingleFileStatus::Added);
         }
 
         if status.added_files.contains(path) {
             // println!("2");
-            return SingleFileStatus::Added;
+            return Ok(SingleFileStatus::Added);
         }
 
         if status.modified_files.contains(path) {
             // println!("3");
-            return SingleFileStatus::Modified;
+            return Ok(SingleFileStatus::Modified);
         }
 
         if status.missing_folders.contains(path) {
             // println!("4");
-            return SingleFileStatus::Missing;
+            return Ok(SingleFileStatus::Missing);
         }
 
         if status.missing_files.contains(path) {
             // println!("5");
-            return SingleFileStatus::Missing;
+            return Ok(SingleFileStatus::Missing);
         }
 
         if status.gone_files.contains(path) {
             // println!("6");
-            return SingleFileStatus::Gone;
+            return Ok(SingleFileStatus::Gone);
         }
 
         if status.come_files.contains(path) {
             // println!("7");
-            return SingleFileStatus::Come;
+            return Ok(SingleFileStatus::Come);
         }
 
         // 如果目录下有文件有变动，也要视为修改状态
         if status.added_folders.iter().any(|e| e.starts_with(path)) {
             // println!("a");
-            return SingleFileStatus::Modified;
+            return Ok(SingleFileStatus::Modified);
         }
         if status.added_files.iter().any(|e| e.starts_with(path)) {
             // println!("b");
-            return SingleFileStatus::Modified;
+            return Ok(SingleFileStatus::Modified);
         }
         if status.modified_files.iter().any(|e| e.starts_with(path)) {
             // println!("c");
-            return SingleFileStatus::Modified;
+            return Ok(SingleFileStatus::Modified);
         }
         if status.missing_folders.iter().any(|e| e.starts_with(path)) {
             // println!("d");
-            return SingleFileStatus::Modified;
+            return Ok(SingleFileStatus::Modified);
         }
         if status.missing_files.iter().any(|e| e.starts_with(path)) {
             // println!("e");
-            return SingleFileStatus::Modified;
+            return Ok(SingleFileStatus::Modified);
         }
         if status.gone_files.iter().any(|e| e.starts_with(path)) {
             // println!("f");
-            return SingleFileStatus::Modified;
+            return Ok(SingleFileStatus::Modified);
         }
         if status.come_files.iter().any(|e| e.starts_with(path)) {
             // println!("g");
-            return SingleFileStatus::Modified;
+            return Ok(SingleFileStatus::Modified);
         }
 
         // println!("8");
-        return SingleFileStatus::Keep;
+        return Ok(SingleFileStatus::Keep);
     }
 
     /// 尝试重新生成文件状态缓存
-    async fn refresh(&mut self) -> &Status {
+    async fn refresh(&mut self) -> Result<&Status, ManagerError> {
         if self.status.is_none() {
             println!("rebuild cache");
 
             let app_path = &self.app_path;
 
             // 读取现有更新包，并复现在history上
-            let index_file = IndexFile::load_from_file(&app_path.index_file);
+            let index_file = IndexFile::load_from_file(&app_path.index_file)?;
 
             let mut history = HistoryFile::new_empty();
 
-            for (_index, meta) in index_file.read_all_metas(&app_path.public_dir) {
+            for (_index, meta) in index_file.read_all_metas(&app_path.public_dir)? {
                 history.replay_operations(&meta);
             }
 
             // 对比文件
-            let exclude_rules = &self.config.core.exclude_rules;
-            let disk_file = DiskFile::new(app_path.workspace_dir.clone(), Weak::new());
-            let diff = Diff::diff(&disk_file, &history, Some(&exclude_rules));
+            let filter = RuleFilter::load(&self.config.core, &app_path.workspace_dir)?;
+            let disk_file = DiskFile::new(app_path.workspace_dir.clone(), Weak::new(), self.config.core.hash_algorithm, self.config.core.symlink_mode)?;
+            disk_file.check_files(&filter)?;
+            let diff = Diff::diff(&disk_file, &history, Some(&filter));
 
             let mut status = Status::default();
             
@@ -132,6 +135,11 @@ impl FileStatus {
                 status.added_files.push(f.path().to_owned());
             }
 
+            // 符号链接在文件状态上也算作新增的文件
+            for f in diff.added_symlinks {
+                status.added_files.push(f.path().to_owned());
+            }
+
             for f in diff.modified_files {
                 status.modified_files.push(f.path().to_owned());
             }
@@ -152,7 +160,7 @@ impl FileStatus {
             self.status = Some(status);
         }
 
-        return &self.status.as_ref().unwrap();
+        return Ok(self.status.as_ref().unwrap());
     }
 }
 
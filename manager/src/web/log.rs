@@ -22,13 +22,13 @@ pub struct Console {
 impl Console {
     pub fn new_cli() -> Self {
         Self {
-            inner: Arc::new(Mutex::new(Inner { buf: LinkedList::new(), mode: Mode::Cli }))
+            inner: Arc::new(Mutex::new(Inner { buf: LinkedList::new(), mode: Mode::Cli, stderr: false, output: None }))
         }
     }
 
     pub fn new_webui() -> Self {
         Self {
-            inner: Arc::new(Mutex::new(Inner { buf: LinkedList::new(), mode: Mode::Webui }))
+            inner: Arc::new(Mutex::new(Inner { buf: LinkedList::new(), mode: Mode::Webui, stderr: false, output: None }))
         }
     }
 
@@ -64,6 +64,23 @@ impl Console {
         entries
     }
 
+    /// 设置是否把日志输出到标准错误，而不是标准输出
+    /// 
+    /// 命令行的`--json`模式下，标准输出只用来输出任务的结果，日志需要输出到标准错误上
+    pub fn set_stderr(&self, stderr: bool) {
+        self.inner.lock().unwrap().stderr = stderr;
+    }
+
+    /// 记录任务的结构化结果，供命令行的`--json`模式输出。同一个任务多次调用时只保留最后一次
+    pub fn set_output(&self, output: serde_json::Value) {
+        self.inner.lock().unwrap().output = Some(output);
+    }
+
+    /// 取出任务的结构化结果，任务没有结果时返回None
+    pub fn take_output(&self) -> Option<serde_json::Value> {
+        self.inner.lock().unwrap().output.take()
+    }
+
     /// 记录一条“调试”日志
     pub fn log_debug(&self, content: impl AsRef<str>) {
         self.log(content, LogLevel::Debug);
@@ -89,7 +106,10 @@ impl Console {
         let mut lock = self.inner.lock().unwrap();
         
         for line in content.as_ref().split("\n") {
-            println!("{}", line);
+            match lock.stderr {
+                true => eprintln!("{}", line),
+                false => println!("{}", line),
+            }
 
             if lock.mode == Mode::Webui {
                 lock.buf.push_back(Line::new(line.to_owned(), level));
@@ -105,6 +125,12 @@ impl Console {
 pub struct Inner {
     pub buf: LinkedList<Line>,
     mode: Mode,
+
+    /// 日志是否输出到标准错误
+    stderr: bool,
+
+    /// 任务的结构化结果
+    output: Option<serde_json::Value>,
 }
 
 /// 代表单条日志，序列化专用
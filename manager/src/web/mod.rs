@@ -27,14 +27,26 @@ use crate::config::Config;
 use crate::web::api::fs::extract_file::api_extract_file;
 use crate::web::api::fs::r#move::api_move;
 use crate::web::api::fs::sign_file::api_sign_file;
+use crate::web::api::misc::channel_list::api_channel_list;
+use crate::web::api::misc::public_key::api_public_key;
 use crate::web::api::misc::version_list::api_version_list;
 use crate::web::api::public::api_public;
 use crate::web::api::task::check::api_status;
+use crate::web::api::task::check_ignore::api_check_ignore;
 use crate::web::api::task::combine::api_combine;
+use crate::web::api::task::diff_versions::api_diff_versions;
+use crate::web::api::task::export::api_export;
+use crate::web::api::task::full_install::api_full_install;
+use crate::web::api::task::inspect::api_inspect;
+use crate::web::api::task::keygen::api_keygen;
 use crate::web::api::task::pack::api_pack;
+use crate::web::api::task::pack_dry_run::api_pack_dry_run;
+use crate::web::api::task::promote::api_promote;
 use crate::web::api::task::revert::api_revert;
+use crate::web::api::task::rollback::api_pack_rollback;
 use crate::web::api::task::sync::api_upload_api;
 use crate::web::api::task::test::api_test;
+use crate::web::api::task::yank::api_yank;
 use crate::web::api::fs::delete::api_delete;
 use crate::web::api::fs::disk_info::api_disk_info;
 use crate::web::api::fs::download::api_download;
@@ -133,11 +145,21 @@ pub async fn serve_web(apppath: AppPath, config: Config) {
         .route("/api/terminal/more", post(api_more))
 
         .route("/api/task/status", post(api_status))
+        .route("/api/task/check-ignore", post(api_check_ignore))
         .route("/api/task/test", post(api_test))
         .route("/api/task/combine", post(api_combine))
         .route("/api/task/pack", post(api_pack))
+        .route("/api/task/pack-dry-run", post(api_pack_dry_run))
+        .route("/api/task/pack-rollback", post(api_pack_rollback))
+        .route("/api/task/promote", post(api_promote))
+        .route("/api/task/keygen", post(api_keygen))
+        .route("/api/task/yank", post(api_yank))
         .route("/api/task/revert", post(api_revert))
         .route("/api/task/upload", post(api_upload_api))
+        .route("/api/task/inspect", post(api_inspect))
+        .route("/api/task/diff-versions", post(api_diff_versions))
+        .route("/api/task/export", post(api_export))
+        .route("/api/task/full-install", post(api_full_install))
 
         .route("/api/fs/disk-info", post(api_disk_info))
         .route("/api/fs/list", post(api_list))
@@ -149,6 +171,8 @@ pub async fn serve_web(apppath: AppPath, config: Config) {
         .route("/api/fs/sign-file", post(api_sign_file))
         
         .route("/api/misc/version-list", post(api_version_list))
+        .route("/api/misc/channel-list", post(api_channel_list))
+        .route("/api/misc/public-key", post(api_public_key))
         .route_layer(AuthLayer::new(webstate.clone()))
 
         // 这部分不参与请求验证
@@ -1,4 +1,5 @@
 use std::ops::Deref;
+use std::panic::AssertUnwindSafe;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -6,6 +7,8 @@ use axum::body::Body;
 use axum::response::Response;
 use tokio::sync::Mutex;
 
+use crate::error::report_result;
+use crate::error::ManagerError;
 use crate::web::api::PublicResponseBody;
 use crate::web::webstate::WebState;
 
@@ -22,8 +25,10 @@ impl LongTimeExecutor {
     }
 
     /// 尝试执行一个任务。并直接生成Response对象
+    /// 
+    /// 任务返回的错误会输出到日志里，并以500状态码返回
     pub async fn try_schedule<F>(&self, wait: bool, state: WebState, f: F) -> Response where 
-        F: FnOnce() -> u8, 
+        F: FnOnce() -> Result<(), ManagerError>, 
         F: Send + 'static 
     {
         // 同时只能有一个任务在运行
@@ -35,7 +40,7 @@ impl LongTimeExecutor {
         state.console.get_logs(true);
 
         // 执行任务
-        let code = self.schedule(wait, f).await;
+        let code = self.schedule(wait, state.clone(), f).await;
 
         // 如果不等待的话，就直接返回
         if !wait {
@@ -69,8 +74,10 @@ impl LongTimeExecutor {
     /// 
     /// + 当`wait`为true时，会等待任务结束后返回，同时携带返回代码
     /// + 当`wait`为false时，会立即返回，没有返回代码
-    async fn schedule<F>(&self, wait: bool, f: F) -> Option<u8> where
-        F: FnOnce() -> u8,
+    /// 
+    /// 任务失败或者意外panic时，错误会输出到日志里，返回代码为1，执行器可以继续接受新的任务
+    async fn schedule<F>(&self, wait: bool, state: WebState, f: F) -> Option<u8> where
+        F: FnOnce() -> Result<(), ManagerError>,
         F: Send + 'static
     {
         assert!(!self.is_busy().await);
@@ -86,8 +93,14 @@ impl LongTimeExecutor {
         let handle = std::thread::Builder::new()
             .name("mcpatch-task".into())
             .spawn(move || {
-                // 执行任务
-                let value = f();
+                // 执行任务，即使任务panic了也要继续往下走，否则busy标记永远不会被清除
+                let value = match std::panic::catch_unwind(AssertUnwindSafe(f)) {
+                    Ok(result) => report_result(result, &state.console),
+                    Err(_) => {
+                        state.console.log_error("任务意外中止，请检查控制台输出");
+                        1
+                    },
+                };
 
                 // 保存返回代码
                 *returns2.blocking_lock() = value;
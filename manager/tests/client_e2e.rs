@@ -0,0 +1,258 @@
+//! 端到端测试：用管理端打包，再用参考客户端分别通过私有协议和http更新，检查更新后的文件和工作空间完全一致
+
+use std::collections::BTreeMap;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::path::Path;
+use std::process::Child;
+use std::process::Command;
+use std::process::Stdio;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::UNIX_EPOCH;
+
+use client::error::ClientError;
+use client::source::Source;
+use client::transaction::STAGING_DIR;
+use client::UpdateClient;
+use client::VERSION_FILE;
+
+/// 测试结束时（包括测试失败时）结束掉所有启动的服务端进程
+struct Servers(Vec<Child>);
+
+impl Drop for Servers {
+    fn drop(&mut self) {
+        for child in &mut self.0 {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+fn manager(dir: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_manager"));
+
+    // 有CARGO环境变量时管理端会把test子目录当成工作目录
+    command.current_dir(dir).env_remove("CARGO").stdout(Stdio::null()).stderr(Stdio::null());
+    command
+}
+
+fn run(dir: &Path, args: &[&str]) {
+    let status = manager(dir).args(args).status().unwrap();
+
+    assert!(status.success(), "manager {:?} failed", args);
+}
+
+fn wait_for_port(port: u16) {
+    let start = Instant::now();
+
+    while TcpStream::connect(("127.0.0.1", port)).is_err() {
+        assert!(start.elapsed() < Duration::from_secs(30), "server on port {} did not start", port);
+
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
+
+/// 写入文件并指定修改时间，管理端靠修改时间和长度判断文件有没有变化，测试里的修改都在同一秒内发生
+fn write(path: &Path, data: impl AsRef<[u8]>, modified: u64) {
+    std::fs::write(path, data).unwrap();
+
+    let open = std::fs::File::options().write(true).open(path).unwrap();
+    open.set_modified(UNIX_EPOCH + Duration::from_secs(modified)).unwrap();
+}
+
+fn pseudo_random(len: usize, seed: u64) -> Vec<u8> {
+    let mut state = seed;
+
+    (0..len).map(|_| {
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (state >> 56) as u8
+    }).collect()
+}
+
+/// 收集目录下所有的文件和目录，目录的内容为None
+fn snapshot(dir: &Path) -> BTreeMap<String, Option<Vec<u8>>> {
+    fn walk(dir: &Path, prefix: &str, out: &mut BTreeMap<String, Option<Vec<u8>>>) {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let entry = entry.unwrap();
+            let name = format!("{}{}", prefix, entry.file_name().to_str().unwrap());
+
+            if name == VERSION_FILE {
+                continue;
+            }
+
+            if entry.file_type().unwrap().is_dir() {
+                walk(&entry.path(), &format!("{}/", name), out);
+                out.insert(name, None);
+            } else {
+                out.insert(name, Some(std::fs::read(entry.path()).unwrap()));
+            }
+        }
+    }
+
+    let mut out = BTreeMap::new();
+    walk(dir, "", &mut out);
+    out
+}
+
+fn assert_same(target: &Path, workspace: &Path) {
+    assert_eq!(snapshot(target), snapshot(workspace), "{} differs from the workspace", target.display());
+
+    // 元数据里的修改时间只精确到秒
+    let mtime = |dir: &Path| std::fs::metadata(dir.join("big.bin")).unwrap().modified().unwrap().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+    assert_eq!(mtime(target), mtime(workspace));
+}
+
+fn local_version(target: &Path) -> String {
+    std::fs::read_to_string(target.join(VERSION_FILE)).unwrap()
+}
+
+#[tokio::test]
+async fn test_update_through_builtin_server_and_http() {
+    let temp = std::env::temp_dir().join(format!("mcpatch-client-e2e-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&temp);
+
+    let dir = temp.join("manager");
+    let workspace = dir.join("workspace");
+    let builtin_target = temp.join("builtin");
+    let http_target = temp.join("http");
+
+    std::fs::create_dir_all(&workspace).unwrap();
+
+    let web_port = free_port();
+    let builtin_port = free_port();
+
+    let config = format!(
+        "[core]\ncompression = \"zstd\"\ncreate-if-missing = [\"options.txt\"]\n\n[web]\nlisten-addr = \"127.0.0.1\"\nlisten-port = {}\n\n[builtin-server]\nenabled = true\nlisten-addr = \"127.0.0.1\"\nlisten-port = {}\n",
+        web_port, builtin_port
+    );
+
+    std::fs::write(dir.join("config.toml"), config).unwrap();
+
+    // 启用签名，客户端发现服务端上有公钥时会验证所有的签名
+    run(&dir, &["keygen"]);
+
+    // 1.0：初始文件
+    std::fs::create_dir_all(workspace.join("sub")).unwrap();
+    std::fs::create_dir_all(workspace.join("empty")).unwrap();
+    write(&workspace.join("big.bin"), pseudo_random(300 * 1024, 1), 1600000000);
+    write(&workspace.join("sub/a.txt"), "a".repeat(5000), 1600000000);
+    write(&workspace.join("keep.txt"), "keep", 1600000000);
+    write(&workspace.join("gone.txt"), "gone", 1600000000);
+    write(&workspace.join("options.txt"), "fov:70", 1600000000);
+
+    run(&dir, &["pack", "1.0"]);
+
+    let _servers = Servers(vec![
+        manager(&dir).arg("serve").spawn().unwrap(),
+        manager(&dir).arg("webui").spawn().unwrap(),
+    ]);
+
+    wait_for_port(builtin_port);
+    wait_for_port(web_port);
+
+    let builtin_source = Source::builtin(format!("127.0.0.1:{}", builtin_port), "stable");
+    let http_source = Source::from_index_url(&format!("http://127.0.0.1:{}/public/index.json", web_port));
+
+    let mut builtin_client = UpdateClient::new(builtin_source, &builtin_target);
+    let mut http_client = UpdateClient::new(http_source, &http_target);
+
+    assert_eq!(builtin_client.update().await.unwrap(), ["1.0"]);
+    assert_same(&builtin_target, &workspace);
+    assert_eq!(builtin_client.update().await.unwrap(), Vec::<String>::new());
+
+    // 玩家修改了自己的选项文件
+    write(&builtin_target.join("options.txt"), "fov:90", 1605000000);
+
+    // 1.1：修改大文件的一小部分（生成补丁），删除文件和目录，移动文件，新建多级目录
+    let mut big = pseudo_random(300 * 1024, 1);
+    big[5000..5100].copy_from_slice(&[b'x'; 100]);
+    write(&workspace.join("big.bin"), &big, 1610000000);
+    std::fs::remove_file(workspace.join("gone.txt")).unwrap();
+    std::fs::remove_dir(workspace.join("empty")).unwrap();
+    std::fs::rename(workspace.join("sub/a.txt"), workspace.join("sub/b.txt")).unwrap();
+    std::fs::create_dir_all(workspace.join("new/dir")).unwrap();
+    write(&workspace.join("new/dir/c.txt"), "c", 1610000000);
+    write(&workspace.join("options.txt"), "fov:80", 1610000000);
+
+    run(&dir, &["pack", "1.1"]);
+
+    // 1.2：再修改一次，让http客户端一次跨越多个版本
+    big[200_000..200_100].copy_from_slice(&[b'y'; 100]);
+    write(&workspace.join("big.bin"), &big, 1620000000);
+    write(&workspace.join("keep.txt"), "kept", 1620000000);
+
+    run(&dir, &["pack", "1.2"]);
+
+    assert_eq!(builtin_client.update().await.unwrap(), ["1.1", "1.2"]);
+
+    // 已经存在的选项文件不会被覆盖，全新安装的客户端拿到的是最新的选项文件
+    assert_eq!(std::fs::read_to_string(builtin_target.join("options.txt")).unwrap(), "fov:90");
+    std::fs::write(builtin_target.join("options.txt"), "fov:80").unwrap();
+    assert_same(&builtin_target, &workspace);
+
+    assert_eq!(http_client.update().await.unwrap(), ["1.0", "1.1", "1.2"]);
+    assert_same(&http_target, &workspace);
+    assert_eq!(local_version(&http_target), "1.2");
+
+    // 合并以后，合并包里只存储了最终文件状态的数据，全新安装的客户端不能去读取中间版本的数据
+    run(&dir, &["combine"]);
+
+    let fresh_target = temp.join("fresh");
+    let mut fresh_client = UpdateClient::new(Source::builtin(format!("127.0.0.1:{}", builtin_port), "stable"), &fresh_target);
+
+    assert_eq!(fresh_client.update().await.unwrap(), ["1.0", "1.1", "1.2"]);
+    assert_same(&fresh_target, &workspace);
+
+    // 1.3：更新包里的数据损坏时，更新失败，目标目录保持原样
+    let fresh = pseudo_random(10 * 1024, 2);
+    write(&workspace.join("fresh.bin"), &fresh, 1630000000);
+    std::fs::remove_file(workspace.join("keep.txt")).unwrap();
+
+    run(&dir, &["pack", "1.3"]);
+
+    let before = snapshot(&http_target);
+    let archive = dir.join("public/1.3.tar");
+    let mut data = std::fs::read(&archive).unwrap();
+    let pos = data.windows(fresh.len()).position(|e| e == fresh).expect("random data should be stored uncompressed");
+    data[pos + 100] ^= 0xff;
+    std::fs::write(&archive, data).unwrap();
+
+    for client in [&mut builtin_client, &mut http_client] {
+        match client.update().await {
+            Err(ClientError::Corrupted(_)) => (),
+            other => panic!("expected a corrupted error, got {:?}", other),
+        }
+    }
+
+    for target in [&builtin_target, &http_target] {
+        assert_eq!(snapshot(target), before);
+        assert_eq!(local_version(target), "1.2");
+        assert!(!target.join(STAGING_DIR).exists());
+    }
+
+    // 本地版本号不在服务端的版本列表里时拒绝更新
+    std::fs::write(http_target.join(VERSION_FILE), "0.9").unwrap();
+
+    assert!(matches!(http_client.update().await, Err(ClientError::UnknownLocalVersion(_))));
+
+    // 内置的公钥和服务端的签名对不上时拒绝更新
+    let other_key = "9jysgOnNQO1LEW0KGxg4eBl8rQvfa7EG+xS3hNzgQco=";
+    let mut pinned_client = UpdateClient::new(Source::builtin(format!("127.0.0.1:{}", builtin_port), "stable"), temp.join("pinned")).with_public_key(other_key);
+
+    assert!(matches!(pinned_client.update().await, Err(ClientError::BadSignature(_))));
+
+    // 索引文件被篡改过时拒绝更新
+    let index = dir.join("public/index.json");
+    let tampered = std::fs::read_to_string(&index).unwrap().replace("\"1.0\"", "\"0.9\"");
+    std::fs::write(&index, tampered).unwrap();
+
+    assert!(matches!(http_client.update().await, Err(ClientError::BadSignature(_))));
+
+    let _ = std::fs::remove_dir_all(&temp);
+}